@@ -0,0 +1,9 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // protoc がない環境でもビルドできるよう、.proto の解析には protox を使う
+    let file_descriptors = protox::compile(["proto/pokedex/v1/pokedex.proto"], ["proto"])?;
+    tonic_build::configure()
+        .build_client(false)
+        .compile_fds(file_descriptors)?;
+    println!("cargo:rerun-if-changed=proto");
+    Ok(())
+}
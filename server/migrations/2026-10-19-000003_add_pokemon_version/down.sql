@@ -0,0 +1 @@
+ALTER TABLE public.pokemon DROP COLUMN IF EXISTS version;
@@ -0,0 +1 @@
+ALTER TABLE pokemon ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,2 @@
+DROP TRIGGER IF EXISTS set_updated_at ON public.pokemon;
+ALTER TABLE public.pokemon DROP COLUMN IF EXISTS updated_at;
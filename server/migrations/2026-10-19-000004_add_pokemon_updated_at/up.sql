@@ -0,0 +1,2 @@
+ALTER TABLE pokemon ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+SELECT diesel_manage_updated_at('pokemon');
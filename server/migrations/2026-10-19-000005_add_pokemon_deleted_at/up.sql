@@ -0,0 +1 @@
+ALTER TABLE pokemon ADD COLUMN deleted_at TIMESTAMPTZ;
@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS public.webhook_queue;
+DROP INDEX IF EXISTS public.outbox_webhooks_pending;
+ALTER TABLE public.outbox DROP COLUMN IF EXISTS webhooks_queued_at;
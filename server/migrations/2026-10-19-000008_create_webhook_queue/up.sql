@@ -0,0 +1,19 @@
+ALTER TABLE outbox ADD COLUMN webhooks_queued_at TIMESTAMPTZ;
+-- 既存のイベントは通知済みとして扱う
+UPDATE outbox SET webhooks_queued_at = CURRENT_TIMESTAMP;
+
+CREATE INDEX outbox_webhooks_pending ON outbox (id) WHERE webhooks_queued_at IS NULL;
+
+CREATE TABLE webhook_queue (
+    id BIGSERIAL PRIMARY KEY,
+    webhook_id INT4 NOT NULL REFERENCES webhooks (id) ON DELETE CASCADE,
+    delivery_id TEXT NOT NULL,
+    event TEXT NOT NULL,
+    body TEXT NOT NULL,
+    attempt INT4 NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    locked_until TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX webhook_queue_due ON webhook_queue (next_attempt_at);
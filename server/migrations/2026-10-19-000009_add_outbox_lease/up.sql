@@ -0,0 +1,2 @@
+-- 複数のインスタンスが同じメッセージを配信しないよう、取り出し中の期限を記録する
+ALTER TABLE outbox ADD COLUMN locked_until TIMESTAMPTZ;
@@ -0,0 +1,123 @@
+//! API キー認証処理のためのアプリケーションサービス。
+//! 認証処理のユースケースの振る舞いを定義する。
+
+use crate::domain::models::api_key::{
+    api_key::{hash_api_key, ApiKey},
+    api_key_repository::ApiKeyRepository,
+};
+use anyhow::Result;
+use chrono::Utc;
+
+/// アプリケーションサービスの構造体。
+/// generics でリポジトリへの依存を表し、trait 境界を定義することで、DI を行う。
+pub struct ApiKeyAuthenticateService<T>
+where
+    T: ApiKeyRepository,
+{
+    api_key_repository: T,
+}
+
+/// アプリケーションサービスの振る舞いを定義。
+impl<T: ApiKeyRepository> ApiKeyAuthenticateService<T> {
+    /// コンストラクタ
+    pub fn new(api_key_repository: T) -> Self {
+        Self { api_key_repository }
+    }
+
+    /// 認証処理の実行。
+    /// 平文のキーに一致する有効な API キーがあれば最終利用日時を記録して返し、なければ None を返す。
+    pub fn handle(&self, key: &str) -> Result<Option<ApiKey>> {
+        let now = Utc::now();
+        match self.api_key_repository.find_by_hash(&hash_api_key(key))? {
+            Some(mut api_key) if api_key.is_active(now) => {
+                self.api_key_repository.touch(api_key.id, now)?;
+                api_key.last_used_at = Some(now);
+                Ok(Some(api_key))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::api_key::api_key::NewApiKey;
+    use crate::domain::models::role::permission::Permission;
+    use chrono::{DateTime, Duration};
+
+    /// テストのためのモックリポジトリ
+    pub struct MockApiKeyRepositoryImpl {}
+
+    impl MockApiKeyRepositoryImpl {
+        fn new() -> Self {
+            MockApiKeyRepositoryImpl {}
+        }
+    }
+
+    /// モックリポジトリの振る舞い
+    impl ApiKeyRepository for MockApiKeyRepositoryImpl {
+        fn find_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>> {
+            let api_key = ApiKey {
+                id: 1,
+                name: "batch".to_string(),
+                key_hash: key_hash.to_string(),
+                scopes: vec![Permission::PokemonWrite],
+                expires_at: None,
+                last_used_at: None,
+                created_at: Utc::now(),
+                revoked_at: None,
+            };
+            if key_hash == hash_api_key("pk_active") {
+                Ok(Some(api_key))
+            } else if key_hash == hash_api_key("pk_expired") {
+                Ok(Some(ApiKey {
+                    expires_at: Some(Utc::now() - Duration::days(1)),
+                    ..api_key
+                }))
+            } else {
+                Ok(None)
+            }
+        }
+
+        fn find_by_id(&self, _id: i32) -> Result<Option<ApiKey>> {
+            unimplemented!();
+        }
+
+        fn insert(&self, _api_key: &NewApiKey) -> Result<ApiKey> {
+            unimplemented!();
+        }
+
+        fn revoke(&self, _id: i32, _revoked_at: DateTime<Utc>) -> Result<()> {
+            unimplemented!();
+        }
+
+        fn touch(&self, _id: i32, _used_at: DateTime<Utc>) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn handle_ok_active() {
+        let repository = MockApiKeyRepositoryImpl::new();
+        let service = ApiKeyAuthenticateService::new(repository);
+        let result = service.handle("pk_active").unwrap();
+        assert!(result.unwrap().last_used_at.is_some());
+    }
+
+    #[test]
+    fn handle_ng_expired() {
+        let repository = MockApiKeyRepositoryImpl::new();
+        let service = ApiKeyAuthenticateService::new(repository);
+        let result = service.handle("pk_expired").unwrap();
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn handle_ng_unknown() {
+        let repository = MockApiKeyRepositoryImpl::new();
+        let service = ApiKeyAuthenticateService::new(repository);
+        let result = service.handle("pk_unknown").unwrap();
+        assert!(result.is_none());
+    }
+}
@@ -0,0 +1,40 @@
+//! API キーのドメインオブジェクトのための DTO
+
+use crate::domain::models::api_key::api_key::ApiKey;
+use chrono::{DateTime, Utc};
+use getset::Getters;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// API キーの情報。ハッシュ値は含めない。
+#[derive(Serialize, Clone, Getters, PartialEq, Eq, Debug, ToSchema)]
+pub struct ApiKeyData {
+    #[getset(get = "pub with_prefix")]
+    id: i32,
+    #[getset(get = "pub with_prefix")]
+    name: String,
+    #[getset(get = "pub with_prefix")]
+    scopes: Vec<String>,
+    #[getset(get = "pub with_prefix")]
+    expires_at: Option<DateTime<Utc>>,
+    #[getset(get = "pub with_prefix")]
+    last_used_at: Option<DateTime<Utc>>,
+    #[getset(get = "pub with_prefix")]
+    created_at: DateTime<Utc>,
+    #[getset(get = "pub with_prefix")]
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKeyData {
+    pub fn new(source: ApiKey) -> Self {
+        Self {
+            id: source.id,
+            name: source.name,
+            scopes: source.scopes.into_iter().map(String::from).collect(),
+            expires_at: source.expires_at,
+            last_used_at: source.last_used_at,
+            created_at: source.created_at,
+            revoked_at: source.revoked_at,
+        }
+    }
+}
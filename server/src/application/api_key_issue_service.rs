@@ -0,0 +1,181 @@
+//! API キー発行処理のためのアプリケーションサービス。
+//! 発行処理のユースケースの振る舞いを定義する。
+
+use super::api_key_data::ApiKeyData;
+use crate::domain::models::api_key::{
+    api_key::{generate_api_key, hash_api_key, NewApiKey},
+    api_key_repository::ApiKeyRepository,
+};
+use crate::domain::models::role::permission::Permission;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use getset::{Getters, Setters};
+use serde::Serialize;
+use std::convert::TryFrom;
+use utoipa::ToSchema;
+
+/// アプリケーションサービスの構造体。
+/// generics でリポジトリへの依存を表し、trait 境界を定義することで、DI を行う。
+pub struct ApiKeyIssueService<T>
+where
+    T: ApiKeyRepository,
+{
+    api_key_repository: T,
+}
+
+/// アプリケーションサービスの振る舞いを定義。
+impl<T: ApiKeyRepository> ApiKeyIssueService<T> {
+    /// コンストラクタ
+    pub fn new(api_key_repository: T) -> Self {
+        Self { api_key_repository }
+    }
+
+    /// 発行処理の実行。平文のキーを返すのはこの時だけで、以降はハッシュ値しか保持しない。
+    pub fn handle(&self, command: ApiKeyIssueCommand) -> Result<IssuedApiKey> {
+        if command.get_name().is_empty() {
+            return Err(anyhow::anyhow!("API キーの名前が空です。"));
+        }
+        let mut scopes = vec![];
+        for scope in command.get_scopes().iter() {
+            match Permission::try_from(scope.clone()) {
+                Ok(permission) => scopes.push(permission),
+                Err(_) => return Err(anyhow::anyhow!("不正なスコープです: {}", scope)),
+            }
+        }
+        if let Some(expires_at) = command.get_expires_at() {
+            if *expires_at <= Utc::now() {
+                return Err(anyhow::anyhow!("有効期限が過去の日時です。"));
+            }
+        }
+
+        let key = generate_api_key();
+        let new_api_key = NewApiKey {
+            name: command.get_name().clone(),
+            key_hash: hash_api_key(&key),
+            scopes,
+            expires_at: *command.get_expires_at(),
+        };
+        let api_key = self.api_key_repository.insert(&new_api_key)?;
+        Ok(IssuedApiKey {
+            key,
+            api_key: ApiKeyData::new(api_key),
+        })
+    }
+}
+
+/// API キー発行のコマンドオブジェクト
+#[derive(Getters, Setters)]
+pub struct ApiKeyIssueCommand {
+    #[getset(get = "pub with_prefix")]
+    name: String,
+    #[getset(get = "pub with_prefix")]
+    scopes: Vec<String>,
+    #[getset(get = "pub with_prefix", set = "pub with_prefix")]
+    expires_at: Option<DateTime<Utc>>,
+}
+
+/// API キー発行のコマンドオブジェクトの振る舞いを定義
+impl ApiKeyIssueCommand {
+    /// コンストラクタ
+    pub fn new(name: String, scopes: Vec<String>) -> Self {
+        Self {
+            name,
+            scopes,
+            expires_at: None,
+        }
+    }
+}
+
+/// 発行した API キー。平文のキーを含む。
+#[derive(Serialize, Debug, ToSchema)]
+pub struct IssuedApiKey {
+    pub key: String,
+    #[serde(flatten)]
+    pub api_key: ApiKeyData,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::api_key::api_key::ApiKey;
+    use chrono::Duration;
+
+    /// テストのためのモックリポジトリ
+    pub struct MockApiKeyRepositoryImpl {}
+
+    impl MockApiKeyRepositoryImpl {
+        fn new() -> Self {
+            MockApiKeyRepositoryImpl {}
+        }
+    }
+
+    /// モックリポジトリの振る舞い
+    impl ApiKeyRepository for MockApiKeyRepositoryImpl {
+        fn find_by_hash(&self, _key_hash: &str) -> Result<Option<ApiKey>> {
+            unimplemented!();
+        }
+
+        fn find_by_id(&self, _id: i32) -> Result<Option<ApiKey>> {
+            unimplemented!();
+        }
+
+        fn insert(&self, api_key: &NewApiKey) -> Result<ApiKey> {
+            Ok(ApiKey {
+                id: 1,
+                name: api_key.name.clone(),
+                key_hash: api_key.key_hash.clone(),
+                scopes: api_key.scopes.clone(),
+                expires_at: api_key.expires_at,
+                last_used_at: None,
+                created_at: Utc::now(),
+                revoked_at: None,
+            })
+        }
+
+        fn revoke(&self, _id: i32, _revoked_at: DateTime<Utc>) -> Result<()> {
+            unimplemented!();
+        }
+
+        fn touch(&self, _id: i32, _used_at: DateTime<Utc>) -> Result<()> {
+            unimplemented!();
+        }
+    }
+
+    #[test]
+    fn handle_ok() {
+        let repository = MockApiKeyRepositoryImpl::new();
+        let service = ApiKeyIssueService::new(repository);
+        let mut command =
+            ApiKeyIssueCommand::new("batch".to_string(), vec!["pokemon:write".to_string()]);
+        command.set_expires_at(Some(Utc::now() + Duration::days(30)));
+        let result = service.handle(command);
+        assert!(result.is_ok());
+
+        let issued = result.unwrap();
+        assert!(issued.key.starts_with("pk_"));
+        assert_eq!(
+            issued.api_key.get_scopes(),
+            &vec!["pokemon:write".to_string()]
+        );
+    }
+
+    #[test]
+    fn handle_ng_invalid_scope() {
+        let repository = MockApiKeyRepositoryImpl::new();
+        let service = ApiKeyIssueService::new(repository);
+        let command = ApiKeyIssueCommand::new("batch".to_string(), vec!["hoge".to_string()]);
+        let result = service.handle(command);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn handle_ng_expired() {
+        let repository = MockApiKeyRepositoryImpl::new();
+        let service = ApiKeyIssueService::new(repository);
+        let mut command =
+            ApiKeyIssueCommand::new("batch".to_string(), vec!["pokemon:write".to_string()]);
+        command.set_expires_at(Some(Utc::now() - Duration::days(1)));
+        let result = service.handle(command);
+        assert!(result.is_err());
+    }
+}
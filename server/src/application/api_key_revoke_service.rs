@@ -0,0 +1,107 @@
+//! API キー失効処理のためのアプリケーションサービス。
+//! 失効処理のユースケースの振る舞いを定義する。
+
+use super::api_key_data::ApiKeyData;
+use crate::domain::models::api_key::api_key_repository::ApiKeyRepository;
+use anyhow::Result;
+use chrono::Utc;
+
+/// アプリケーションサービスの構造体。
+/// generics でリポジトリへの依存を表し、trait 境界を定義することで、DI を行う。
+pub struct ApiKeyRevokeService<T>
+where
+    T: ApiKeyRepository,
+{
+    api_key_repository: T,
+}
+
+/// アプリケーションサービスの振る舞いを定義。
+impl<T: ApiKeyRepository> ApiKeyRevokeService<T> {
+    /// コンストラクタ
+    pub fn new(api_key_repository: T) -> Self {
+        Self { api_key_repository }
+    }
+
+    /// 失効処理の実行。対象の API キーが存在しない場合は None を返す。
+    /// 既に失効している場合は失効日時を更新せずにそのまま返す。
+    pub fn handle(&self, id: i32) -> Result<Option<ApiKeyData>> {
+        match self.api_key_repository.find_by_id(id)? {
+            Some(mut api_key) => {
+                if api_key.revoked_at.is_none() {
+                    let now = Utc::now();
+                    self.api_key_repository.revoke(id, now)?;
+                    api_key.revoked_at = Some(now);
+                }
+                Ok(Some(ApiKeyData::new(api_key)))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::api_key::api_key::{ApiKey, NewApiKey};
+    use chrono::DateTime;
+
+    /// テストのためのモックリポジトリ
+    pub struct MockApiKeyRepositoryImpl {}
+
+    impl MockApiKeyRepositoryImpl {
+        fn new() -> Self {
+            MockApiKeyRepositoryImpl {}
+        }
+    }
+
+    /// モックリポジトリの振る舞い
+    impl ApiKeyRepository for MockApiKeyRepositoryImpl {
+        fn find_by_hash(&self, _key_hash: &str) -> Result<Option<ApiKey>> {
+            unimplemented!();
+        }
+
+        fn find_by_id(&self, id: i32) -> Result<Option<ApiKey>> {
+            match id {
+                1 => Ok(Some(ApiKey {
+                    id,
+                    name: "batch".to_string(),
+                    key_hash: "hash".to_string(),
+                    scopes: vec![],
+                    expires_at: None,
+                    last_used_at: None,
+                    created_at: Utc::now(),
+                    revoked_at: None,
+                })),
+                _ => Ok(None),
+            }
+        }
+
+        fn insert(&self, _api_key: &NewApiKey) -> Result<ApiKey> {
+            unimplemented!();
+        }
+
+        fn revoke(&self, _id: i32, _revoked_at: DateTime<Utc>) -> Result<()> {
+            Ok(())
+        }
+
+        fn touch(&self, _id: i32, _used_at: DateTime<Utc>) -> Result<()> {
+            unimplemented!();
+        }
+    }
+
+    #[test]
+    fn handle_ok_exist_id() {
+        let repository = MockApiKeyRepositoryImpl::new();
+        let service = ApiKeyRevokeService::new(repository);
+        let result = service.handle(1);
+        assert!(result.unwrap().unwrap().get_revoked_at().is_some());
+    }
+
+    #[test]
+    fn handle_ok_not_exist_id() {
+        let repository = MockApiKeyRepositoryImpl::new();
+        let service = ApiKeyRevokeService::new(repository);
+        let result = service.handle(2);
+        assert!(result.unwrap().is_none());
+    }
+}
@@ -1,6 +1,21 @@
+pub mod api_key_authenticate_service;
+pub mod api_key_data;
+pub mod api_key_issue_service;
+pub mod api_key_revoke_service;
+pub mod outbox_relay_service;
+pub mod pokemon_batch_service;
 pub mod pokemon_data;
 pub mod pokemon_delete_service;
 pub mod pokemon_get_service;
+pub mod pokemon_import_service;
 pub mod pokemon_list_service;
 pub mod pokemon_register_service;
+pub mod pokemon_restore_service;
 pub mod pokemon_update_service;
+pub mod pokemon_upsert_service;
+pub mod pokemon_watch_service;
+pub mod webhook_data;
+pub mod webhook_delete_service;
+pub mod webhook_delivery_list_service;
+pub mod webhook_dispatch_service;
+pub mod webhook_register_service;
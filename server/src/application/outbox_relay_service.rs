@@ -0,0 +1,104 @@
+//! アウトボックスの配信のためのアプリケーションサービス。
+//! 未配信のドメインイベントを取り出し、配信の結果を記録するユースケースの振る舞いを定義する。
+
+use crate::domain::models::outbox::{outbox::OutboxMessage, outbox_repository::OutboxRepository};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+/// アプリケーションサービスの構造体。
+/// generics でリポジトリへの依存を表し、trait 境界を定義することで、DI を行う。
+pub struct OutboxRelayService<T>
+where
+    T: OutboxRepository,
+{
+    outbox_repository: T,
+}
+
+/// アプリケーションサービスの振る舞いを定義。
+impl<T: OutboxRepository> OutboxRelayService<T> {
+    /// コンストラクタ
+    pub fn new(outbox_repository: T) -> Self {
+        Self { outbox_repository }
+    }
+
+    /// 未配信のメッセージを記録順に最大 limit 件取り出す。取り出したメッセージは locked_until まで他から取り出されない。
+    /// 配信済みにするまでは期限が切れると再び取り出すため、配信先は同じメッセージを重複して受け取りうる。
+    pub fn handle(&self, limit: i64, locked_until: DateTime<Utc>) -> Result<Vec<OutboxMessage>> {
+        self.outbox_repository.claim(limit, locked_until)
+    }
+
+    /// 配信に成功したことを記録する
+    pub fn complete(&self, id: i64) -> Result<()> {
+        self.outbox_repository.mark_sent(id)
+    }
+
+    /// 配信に失敗したことを記録する。メッセージは未配信のまま残り、次の配信で再送する。
+    pub fn fail(&self, id: i64, error: &str) -> Result<()> {
+        self.outbox_repository.mark_failed(id, error)
+    }
+
+    /// 取り出したまま配信しなかったメッセージを、次の配信で取り出せるように戻す
+    pub fn release(&self, ids: &[i64]) -> Result<()> {
+        self.outbox_repository.release(ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// テストのためのモックリポジトリ
+    pub struct MockOutboxRepositoryImpl {}
+
+    impl MockOutboxRepositoryImpl {
+        fn new() -> Self {
+            MockOutboxRepositoryImpl {}
+        }
+    }
+
+    /// モックリポジトリの振る舞い
+    impl OutboxRepository for MockOutboxRepositoryImpl {
+        fn claim(&self, limit: i64, _locked_until: DateTime<Utc>) -> Result<Vec<OutboxMessage>> {
+            Ok((1..=3)
+                .take(limit as usize)
+                .map(|id| OutboxMessage {
+                    id,
+                    event_type: "pokemon.deleted".to_string(),
+                    aggregate_id: 1,
+                    payload: r#"{"number":1,"soft":false}"#.to_string(),
+                    created_at: Utc::now(),
+                    attempts: 0,
+                })
+                .collect())
+        }
+
+        fn mark_sent(&self, _id: i64) -> Result<()> {
+            Ok(())
+        }
+
+        fn mark_failed(&self, id: i64, _error: &str) -> Result<()> {
+            Err(anyhow::anyhow!("Not Found Outbox Message id:{}", id))
+        }
+
+        fn release(&self, _ids: &[i64]) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn handle_ok() {
+        let service = OutboxRelayService::new(MockOutboxRepositoryImpl::new());
+        let messages = service.handle(2, Utc::now()).unwrap();
+        assert_eq!(
+            messages.iter().map(|m| m.id).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    fn fail_ng() {
+        let service = OutboxRelayService::new(MockOutboxRepositoryImpl::new());
+        assert!(service.complete(1).is_ok());
+        assert!(service.fail(1, "Dummy Error").is_err());
+    }
+}
@@ -0,0 +1,418 @@
+//! ポケモンの一括処理のためのアプリケーションサービス。
+//! 登録・更新・削除をまとめて実行するユースケースの振る舞いを定義する。
+
+use crate::application::pokemon_data::PokemonData;
+use crate::application::pokemon_update_service::PokemonUpdateCommand;
+use crate::domain::models::pokemon::{
+    pokemon::Pokemon,
+    pokemon_error::PokemonError,
+    pokemon_event::{PokemonDeleted, PokemonEvent},
+    pokemon_event_bus::PokemonEventBus,
+    pokemon_number::PokemonNumber,
+    pokemon_repository::{PokemonOperation, PokemonRepository},
+};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/// アプリケーションサービスの構造体。
+/// generics でリポジトリへの依存を表し、trait 境界を定義することで、DI を行う。
+pub struct PokemonBatchService<T>
+where
+    T: PokemonRepository,
+{
+    pokemon_repository: T,
+    soft_delete: bool,
+    event_bus: PokemonEventBus,
+}
+
+/// 永続化の操作と、その結果として起きるドメインイベント
+type Plan = (PokemonOperation, Vec<PokemonEvent>);
+
+/// 一括処理の 1 件分のコマンド
+pub enum PokemonBatchCommand {
+    Create {
+        number: i32,
+        name: String,
+        types: Vec<String>,
+    },
+    Update(PokemonUpdateCommand),
+    Delete {
+        number: i32,
+        expected_version: Option<i32>,
+    },
+}
+
+/// 一括処理の 1 件分の結果
+#[derive(Debug)]
+pub enum PokemonBatchOutcome {
+    Created(PokemonData),
+    Updated(PokemonData),
+    Deleted,
+    Failed(anyhow::Error),
+    /// 他の操作の失敗により実行されなかった（取り消された）
+    Skipped,
+}
+
+/// アプリケーションサービスの振る舞いを定義。
+impl<T: PokemonRepository> PokemonBatchService<T> {
+    /// コンストラクタ
+    pub fn new(pokemon_repository: T) -> Self {
+        Self {
+            pokemon_repository,
+            soft_delete: false,
+            event_bus: PokemonEventBus::default(),
+        }
+    }
+
+    /// 成功した操作のドメインイベントを配信するイベントバスを設定する
+    pub fn with_event_bus(mut self, event_bus: PokemonEventBus) -> Self {
+        self.event_bus = event_bus;
+        self
+    }
+
+    /// 削除の操作で物理削除の代わりに論理削除を行うかどうかを設定する
+    pub fn with_soft_delete(mut self, soft_delete: bool) -> Self {
+        self.soft_delete = soft_delete;
+        self
+    }
+
+    /// 一括処理の実行。コマンドと同じ順序で結果を返す。
+    /// 検証は登録・更新・削除の各サービスと同じ規則で行い、先行するコマンドの結果を踏まえて判定する。
+    /// `atomic` が true の場合は 1 件でも失敗すれば全て取り消し、失敗以外の結果は `Skipped` とする。
+    /// 各操作のドメインイベントはその操作と同じトランザクションで記録し、成功した操作の分だけ配信する。
+    pub fn handle(
+        &self,
+        commands: Vec<PokemonBatchCommand>,
+        atomic: bool,
+    ) -> Result<Vec<PokemonBatchOutcome>> {
+        let mut planner = Planner {
+            pokemon_repository: &self.pokemon_repository,
+            soft_delete: self.soft_delete,
+            overlay: HashMap::new(),
+        };
+        let plans = commands
+            .iter()
+            .map(|command| planner.plan(command))
+            .collect::<Vec<_>>();
+
+        if atomic && plans.iter().any(|plan| plan.is_err()) {
+            return Ok(plans
+                .into_iter()
+                .map(|plan| match plan {
+                    Ok(_) => PokemonBatchOutcome::Skipped,
+                    Err(e) => PokemonBatchOutcome::Failed(e.into()),
+                })
+                .collect());
+        }
+
+        let operations = plans
+            .iter()
+            .filter_map(|plan| plan.as_ref().ok().map(|(operation, _)| operation.clone()))
+            .collect::<Vec<_>>();
+        let results = self.pokemon_repository.batch(&operations, atomic)?;
+        // 取り消された場合は何も記録されていないため配信しない
+        if !(atomic && results.iter().any(|result| result.is_err())) {
+            for ((_, events), result) in operations.iter().zip(results.iter()) {
+                if result.is_ok() {
+                    self.event_bus.publish(events);
+                }
+            }
+        }
+
+        if atomic && results.iter().any(|result| result.is_err()) {
+            // 失敗した操作があれば全て取り消されているため、それ以外は実行されなかったものとする
+            let mut results = results.into_iter();
+            return Ok(plans
+                .into_iter()
+                .map(|_| match results.next() {
+                    Some(Err(e)) => PokemonBatchOutcome::Failed(e),
+                    _ => PokemonBatchOutcome::Skipped,
+                })
+                .collect());
+        }
+
+        let mut results = results.into_iter();
+        Ok(plans
+            .into_iter()
+            .map(|plan| match plan {
+                Ok((_, outcome)) => match results.next() {
+                    Some(Ok(())) | None => outcome,
+                    Some(Err(e)) => PokemonBatchOutcome::Failed(e),
+                },
+                Err(e) => PokemonBatchOutcome::Failed(e.into()),
+            })
+            .collect())
+    }
+}
+
+/// 先行するコマンドの結果を反映した状態で、コマンドを永続化の操作に変換する
+struct Planner<'a, T: PokemonRepository> {
+    pokemon_repository: &'a T,
+    soft_delete: bool,
+    /// 一括処理の中で変更された図鑑 No ごとの状態。削除された場合は None。
+    overlay: HashMap<i32, Option<Pokemon>>,
+}
+
+impl<'a, T: PokemonRepository> Planner<'a, T> {
+    fn current(&self, number: &PokemonNumber) -> Option<Pokemon> {
+        let key: i32 = number.clone().into();
+        match self.overlay.get(&key) {
+            Some(pokemon) => pokemon.clone(),
+            None => self.pokemon_repository.find_by_number(number).ok(),
+        }
+    }
+
+    fn plan(
+        &mut self,
+        command: &PokemonBatchCommand,
+    ) -> Result<(Plan, PokemonBatchOutcome), PokemonError> {
+        match command {
+            PokemonBatchCommand::Create {
+                number,
+                name,
+                types,
+            } => {
+                let pokemon = Pokemon::try_new(*number, name.clone(), types.clone())?;
+                if self.current(&pokemon.number).is_some() {
+                    return Err(PokemonError::AlreadyExists(*number));
+                }
+                self.overlay.insert(*number, Some(pokemon.clone()));
+                let events = vec![PokemonEvent::registered(&pokemon)];
+                Ok((
+                    (PokemonOperation::Insert(pokemon.clone()), events),
+                    PokemonBatchOutcome::Created(PokemonData::new(pokemon)),
+                ))
+            }
+            PokemonBatchCommand::Update(command) => {
+                let number = *command.get_number();
+                let target_no =
+                    PokemonNumber::try_from(number).map_err(|_| PokemonError::NotFound(number))?;
+                let current = self
+                    .current(&target_no)
+                    .ok_or(PokemonError::NotFound(number))?;
+                let pokemon = command.apply(current.clone())?;
+                let events = PokemonEvent::updated(&current, &pokemon);
+                let mut updated = pokemon.clone();
+                updated.version += 1;
+                self.overlay.insert(number, Some(updated.clone()));
+                Ok((
+                    (PokemonOperation::Update(pokemon), events),
+                    PokemonBatchOutcome::Updated(PokemonData::new(updated)),
+                ))
+            }
+            PokemonBatchCommand::Delete {
+                number,
+                expected_version,
+            } => {
+                let target_no = PokemonNumber::try_from(*number)
+                    .map_err(|_| PokemonError::NotFound(*number))?;
+                let current = self
+                    .current(&target_no)
+                    .ok_or(PokemonError::NotFound(*number))?;
+                if let Some(expected) = expected_version {
+                    if *expected != current.version {
+                        return Err(PokemonError::VersionMismatch {
+                            number: *number,
+                            expected: *expected,
+                        });
+                    }
+                }
+                self.overlay.insert(*number, None);
+                let events = vec![PokemonEvent::Deleted(PokemonDeleted {
+                    number: target_no.clone(),
+                    soft: self.soft_delete,
+                })];
+                let operation = if self.soft_delete {
+                    PokemonOperation::SoftDelete(target_no, *expected_version)
+                } else {
+                    PokemonOperation::Delete(target_no, *expected_version)
+                };
+                Ok(((operation, events), PokemonBatchOutcome::Deleted))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::pokemon::pokemon_event::PokemonRenamed;
+    use crate::domain::models::pokemon::pokemon_event_bus::tests::RecordingSubscriber;
+    use crate::domain::models::pokemon::pokemon_repository::tests::MockPokemonRepository;
+    use crate::domain::models::pokemon::{pokemon_name::PokemonName, pokemon_types::PokemonTypes};
+    use std::cell::{Cell, RefCell};
+
+    /// テストのためのモックリポジトリ。操作とともに渡されたドメインイベントを記録する。
+    pub struct MockPokemonRepositoryImpl {
+        batch_called: Cell<bool>,
+        events: RefCell<Vec<PokemonEvent>>,
+    }
+
+    impl MockPokemonRepositoryImpl {
+        fn new() -> Self {
+            MockPokemonRepositoryImpl {
+                batch_called: Cell::new(false),
+                events: RefCell::new(vec![]),
+            }
+        }
+    }
+
+    /// モックリポジトリの振る舞い
+    impl MockPokemonRepository for MockPokemonRepositoryImpl {
+        fn find_by_number(
+            &self,
+            number: &PokemonNumber,
+        ) -> Result<crate::domain::models::pokemon::pokemon::Pokemon> {
+            let target_no: i32 = number.clone().into();
+            match target_no {
+                1 => Ok(Pokemon::new(
+                    number.clone(),
+                    PokemonName::try_from("TestPokemon".to_string()).unwrap(),
+                    PokemonTypes::try_from(vec!["Fire".to_string()]).unwrap(),
+                )),
+                _ => Err(anyhow::anyhow!("Dummy Error")),
+            }
+        }
+
+        fn batch(&self, operations: &[Plan], _atomic: bool) -> Result<Vec<Result<()>>> {
+            self.batch_called.set(true);
+            for (_, events) in operations {
+                self.events.borrow_mut().extend(events.iter().cloned());
+            }
+            Ok(operations.iter().map(|_| Ok(())).collect())
+        }
+    }
+
+    fn create(number: i32, types: &str) -> PokemonBatchCommand {
+        PokemonBatchCommand::Create {
+            number,
+            name: "TestPokemon".to_string(),
+            types: vec![types.to_string()],
+        }
+    }
+
+    fn rename(number: i32, name: &str) -> PokemonBatchCommand {
+        let mut command = PokemonUpdateCommand::new(number);
+        command.set_name(Some(name.to_string()));
+        PokemonBatchCommand::Update(command)
+    }
+
+    #[test]
+    fn handle_ok_atomic() {
+        let repository = MockPokemonRepositoryImpl::new();
+        let service = PokemonBatchService::new(repository);
+        let commands = vec![
+            create(2, "Water"),
+            rename(2, "TestName"),
+            PokemonBatchCommand::Delete {
+                number: 1,
+                expected_version: Some(1),
+            },
+        ];
+        let result = service.handle(commands, true).unwrap();
+        assert!(matches!(&result[0], PokemonBatchOutcome::Created(_)));
+        match &result[1] {
+            PokemonBatchOutcome::Updated(data) => {
+                assert_eq!(data.get_name(), "TestName");
+                assert_eq!(*data.get_version(), 2);
+            }
+            other => panic!("unexpected outcome: {:?}", other),
+        }
+        assert!(matches!(result[2], PokemonBatchOutcome::Deleted));
+    }
+
+    #[test]
+    fn handle_ok_events() {
+        let recorder = RecordingSubscriber::default();
+        let mut event_bus = PokemonEventBus::default();
+        event_bus.subscribe(recorder.clone());
+        let service = PokemonBatchService::new(MockPokemonRepositoryImpl::new())
+            .with_soft_delete(true)
+            .with_event_bus(event_bus);
+        let commands = vec![
+            create(2, "Water"),
+            rename(2, "TestName"),
+            PokemonBatchCommand::Delete {
+                number: 1,
+                expected_version: None,
+            },
+        ];
+        service.handle(commands, true).unwrap();
+
+        let number = |n| PokemonNumber::try_from(n).unwrap();
+        let registered =
+            Pokemon::try_new(2, "TestPokemon".to_string(), vec!["Water".to_string()]).unwrap();
+        let mut renamed =
+            Pokemon::try_new(2, "TestName".to_string(), vec!["Water".to_string()]).unwrap();
+        renamed.version = 2;
+        let expected = vec![
+            PokemonEvent::registered(&registered),
+            PokemonEvent::Renamed(PokemonRenamed {
+                from: PokemonName::try_from("TestPokemon".to_string()).unwrap(),
+                to: PokemonName::try_from("TestName".to_string()).unwrap(),
+                pokemon: renamed,
+            }),
+            PokemonEvent::Deleted(PokemonDeleted {
+                number: number(1),
+                soft: true,
+            }),
+        ];
+        // 操作とともに永続化され、成功した後に配信される
+        assert_eq!(*service.pokemon_repository.events.borrow(), expected);
+        assert_eq!(*recorder.0.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn handle_ng_atomic() {
+        let repository = MockPokemonRepositoryImpl::new();
+        let service = PokemonBatchService::new(repository);
+        let commands = vec![create(2, "Water"), create(1, "Fire")];
+        let result = service.handle(commands, true).unwrap();
+        assert!(matches!(result[0], PokemonBatchOutcome::Skipped));
+        match &result[1] {
+            PokemonBatchOutcome::Failed(e) => assert_eq!(
+                e.downcast_ref::<PokemonError>(),
+                Some(&PokemonError::AlreadyExists(1))
+            ),
+            other => panic!("unexpected outcome: {:?}", other),
+        }
+        assert!(!service.pokemon_repository.batch_called.get());
+    }
+
+    #[test]
+    fn handle_ok_best_effort() {
+        let repository = MockPokemonRepositoryImpl::new();
+        let service = PokemonBatchService::new(repository);
+        let commands = vec![
+            create(2, "Hoge"),
+            rename(1, "TestName"),
+            PokemonBatchCommand::Delete {
+                number: 2,
+                expected_version: None,
+            },
+        ];
+        let result = service.handle(commands, false).unwrap();
+        assert!(matches!(result[0], PokemonBatchOutcome::Failed(_)));
+        assert!(matches!(result[1], PokemonBatchOutcome::Updated(_)));
+        match &result[2] {
+            PokemonBatchOutcome::Failed(e) => assert_eq!(
+                e.downcast_ref::<PokemonError>(),
+                Some(&PokemonError::NotFound(2))
+            ),
+            other => panic!("unexpected outcome: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn handle_ng_version_mismatch() {
+        let repository = MockPokemonRepositoryImpl::new();
+        let service = PokemonBatchService::new(repository);
+        let commands = vec![PokemonBatchCommand::Delete {
+            number: 1,
+            expected_version: Some(2),
+        }];
+        let result = service.handle(commands, false).unwrap();
+        assert!(matches!(result[0], PokemonBatchOutcome::Failed(_)));
+    }
+}
@@ -1,11 +1,12 @@
 //! ポケモンのドメインオブジェクトのための DTO
 
 use crate::domain::models::pokemon::pokemon::Pokemon;
+use chrono::{DateTime, Utc};
 use getset::Getters;
 use serde::{Deserialize, Serialize};
-use std::convert::TryInto;
+use utoipa::ToSchema;
 
-#[derive(Serialize, Deserialize, Clone, Getters, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, Clone, Getters, PartialEq, Eq, Debug, ToSchema)]
 pub struct PokemonData {
     #[getset(get = "pub with_prefix")]
     number: i32,
@@ -13,14 +14,24 @@ pub struct PokemonData {
     name: String,
     #[getset(get = "pub with_prefix")]
     types: Vec<String>,
+    /// ETag として返す版。本文には含めない。
+    #[serde(skip)]
+    #[getset(get = "pub with_prefix")]
+    version: i32,
+    /// Last-Modified として返す最終更新日時。本文には含めない。
+    #[serde(skip)]
+    #[getset(get = "pub with_prefix")]
+    updated_at: Option<DateTime<Utc>>,
 }
 
 impl PokemonData {
     pub fn new(source: Pokemon) -> Self {
         Self {
-            number: source.number.try_into().unwrap(),
-            name: source.name.try_into().unwrap(),
-            types: source.types.try_into().unwrap(),
+            number: source.number.into(),
+            name: source.name.into(),
+            types: source.types.into(),
+            version: source.version,
+            updated_at: source.updated_at,
         }
     }
 }
@@ -2,7 +2,11 @@
 //! 削除処理のユースケースの振る舞いを定義する。
 
 use crate::domain::models::pokemon::{
-    pokemon_number::PokemonNumber, pokemon_repository::PokemonRepository,
+    pokemon_error::PokemonError,
+    pokemon_event::{PokemonDeleted, PokemonEvent},
+    pokemon_event_bus::PokemonEventBus,
+    pokemon_number::PokemonNumber,
+    pokemon_repository::{PokemonOperation, PokemonRepository},
 };
 use anyhow::Result;
 use std::convert::TryFrom;
@@ -14,25 +18,62 @@ where
     T: PokemonRepository,
 {
     pokemon_repository: T,
+    soft_delete: bool,
+    event_bus: PokemonEventBus,
 }
 
 /// アプリケーションサービスの振る舞いを定義。
 impl<T: PokemonRepository> PokemonDeleteService<T> {
     /// コンストラクタ
     pub fn new(pokemon_repository: T) -> Self {
-        Self { pokemon_repository }
+        Self {
+            pokemon_repository,
+            soft_delete: false,
+            event_bus: PokemonEventBus::default(),
+        }
+    }
+
+    /// 削除に成功した際にドメインイベントを配信するイベントバスを設定する
+    pub fn with_event_bus(mut self, event_bus: PokemonEventBus) -> Self {
+        self.event_bus = event_bus;
+        self
+    }
+
+    /// 物理削除の代わりに論理削除を行うかどうかを設定する
+    pub fn with_soft_delete(mut self, soft_delete: bool) -> Self {
+        self.soft_delete = soft_delete;
+        self
     }
 
     // 削除処理の実行。
-    pub fn handle(&self, number: i32) -> Result<()> {
-        let target_no = PokemonNumber::try_from(number).unwrap();
+    // 対象が存在しない場合は `PokemonError::NotFound` を返す。
+    // 版が指定されている場合、現在の版と一致しなければ `PokemonError::VersionMismatch` を返す。
+    pub fn handle(&self, number: i32, expected_version: Option<i32>) -> Result<()> {
+        let target_no =
+            PokemonNumber::try_from(number).map_err(|_| PokemonError::NotFound(number))?;
         match self.pokemon_repository.find_by_number(&target_no) {
-            Ok(_) => {
-                self.pokemon_repository.delete(&target_no).unwrap();
+            Ok(current) => {
+                if let Some(expected) = expected_version {
+                    if expected != current.version {
+                        return Err(PokemonError::VersionMismatch { number, expected }.into());
+                    }
+                }
+                let events = [PokemonEvent::Deleted(PokemonDeleted {
+                    number: target_no.clone(),
+                    soft: self.soft_delete,
+                })];
+                // 確認してから削除するまでの間に更新された場合も検知できるよう、版を指定して削除する
+                let operation = if self.soft_delete {
+                    PokemonOperation::SoftDelete(target_no, expected_version)
+                } else {
+                    PokemonOperation::Delete(target_no, expected_version)
+                };
+                // 削除とドメインイベントの記録は同じトランザクションで行う
+                self.pokemon_repository.save(&operation, &events)?;
+                self.event_bus.publish(&events);
                 Ok(())
             }
-            // エラーの場合でも対象のデータが存在しないので、消去は成功しているものとして OK にする。
-            Err(_) => Ok(()),
+            Err(_) => Err(PokemonError::NotFound(number).into()),
         }
     }
 }
@@ -40,27 +81,33 @@ impl<T: PokemonRepository> PokemonDeleteService<T> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::models::pokemon::pokemon_event_bus::tests::RecordingSubscriber;
+    use crate::domain::models::pokemon::pokemon_repository::tests::MockPokemonRepository;
     use crate::domain::models::pokemon::{
         pokemon::Pokemon, pokemon_name::PokemonName, pokemon_types::PokemonTypes,
     };
-    use std::convert::TryInto;
+    use std::cell::RefCell;
 
-    /// テストのためのモックリポジトリ
-    pub struct MockPokemonRepositoryImpl {}
+    /// テストのためのモックリポジトリ。永続化した操作を記録する。
+    pub struct MockPokemonRepositoryImpl {
+        saved: RefCell<Vec<PokemonOperation>>,
+    }
 
     impl MockPokemonRepositoryImpl {
-        fn new() -> impl PokemonRepository {
-            MockPokemonRepositoryImpl {}
+        fn new() -> Self {
+            MockPokemonRepositoryImpl {
+                saved: RefCell::new(vec![]),
+            }
         }
     }
 
     /// モックリポジトリの振る舞い
-    impl PokemonRepository for MockPokemonRepositoryImpl {
+    impl MockPokemonRepository for MockPokemonRepositoryImpl {
         fn find_by_number(
             &self,
             number: &PokemonNumber,
         ) -> Result<crate::domain::models::pokemon::pokemon::Pokemon> {
-            let target_no: i32 = number.clone().try_into().unwrap();
+            let target_no: i32 = number.clone().into();
             match target_no {
                 1 => Ok(Pokemon::new(
                     number.clone(),
@@ -71,25 +118,12 @@ mod tests {
             }
         }
 
-        fn list(&self) -> Result<Vec<crate::domain::models::pokemon::pokemon::Pokemon>> {
-            unimplemented!();
-        }
-
-        fn insert(
+        fn save(
             &self,
-            _pokemon: &crate::domain::models::pokemon::pokemon::Pokemon,
+            operation: &crate::domain::models::pokemon::pokemon_repository::PokemonOperation,
+            _events: &[crate::domain::models::pokemon::pokemon_event::PokemonEvent],
         ) -> Result<()> {
-            unimplemented!();
-        }
-
-        fn update(
-            &self,
-            _pokemon: &crate::domain::models::pokemon::pokemon::Pokemon,
-        ) -> Result<()> {
-            unimplemented!();
-        }
-
-        fn delete(&self, _number: &PokemonNumber) -> Result<()> {
+            self.saved.borrow_mut().push(operation.clone());
             Ok(())
         }
     }
@@ -98,15 +132,80 @@ mod tests {
     fn handle_ok_exist_no() {
         let repository = MockPokemonRepositoryImpl::new();
         let service = PokemonDeleteService::new(repository);
-        let result = service.handle(1);
+        let result = service.handle(1, None);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn handle_ok_soft_delete() {
+        let repository = MockPokemonRepositoryImpl::new();
+        let service = PokemonDeleteService::new(repository).with_soft_delete(true);
+        let result = service.handle(1, None);
         assert!(result.is_ok());
     }
 
     #[test]
-    fn handle_ok_not_exist_no() {
+    fn handle_ok_publish_deleted() {
+        let recorder = RecordingSubscriber::default();
+        let mut event_bus = PokemonEventBus::default();
+        event_bus.subscribe(recorder.clone());
+        let service = PokemonDeleteService::new(MockPokemonRepositoryImpl::new())
+            .with_soft_delete(true)
+            .with_event_bus(event_bus);
+        service.handle(1, None).unwrap();
+        assert!(service.handle(2, None).is_err());
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            vec![PokemonEvent::Deleted(PokemonDeleted {
+                number: PokemonNumber::try_from(1).unwrap(),
+                soft: true,
+            })]
+        );
+    }
+
+    #[test]
+    fn handle_ng_not_exist_no() {
+        let repository = MockPokemonRepositoryImpl::new();
+        let service = PokemonDeleteService::new(repository);
+        let result = service.handle(2, None);
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<PokemonError>(),
+            Some(&PokemonError::NotFound(2))
+        );
+    }
+
+    #[test]
+    fn handle_ng_invalid_no() {
         let repository = MockPokemonRepositoryImpl::new();
         let service = PokemonDeleteService::new(repository);
-        let result = service.handle(2);
+        let result = service.handle(0, None);
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<PokemonError>(),
+            Some(&PokemonError::NotFound(0))
+        );
+    }
+
+    #[test]
+    fn handle_ok_expected_version() {
+        let repository = MockPokemonRepositoryImpl::new();
+        let service = PokemonDeleteService::new(repository);
+        let result = service.handle(1, Some(1));
         assert!(result.is_ok());
+        // 確認後に更新された場合に備え、削除の操作にも版を指定する
+        assert_eq!(
+            *service.pokemon_repository.saved.borrow(),
+            vec![PokemonOperation::Delete(
+                PokemonNumber::try_from(1).unwrap(),
+                Some(1)
+            )]
+        );
+    }
+
+    #[test]
+    fn handle_ng_version_mismatch() {
+        let repository = MockPokemonRepositoryImpl::new();
+        let service = PokemonDeleteService::new(repository);
+        let result = service.handle(1, Some(2));
+        assert!(result.unwrap_err().downcast_ref::<PokemonError>().is_some());
     }
 }
@@ -2,6 +2,7 @@
 //! 更新処理のユースケースの振る舞いを定義する。
 
 use super::pokemon_data::PokemonData;
+use crate::domain::models::pokemon::pokemon_error::PokemonError;
 use crate::domain::models::pokemon::pokemon_number::PokemonNumber;
 use crate::domain::models::pokemon::pokemon_repository::PokemonRepository;
 use anyhow::Result;
@@ -24,74 +25,49 @@ impl<T: PokemonRepository> PokemonGetService<T> {
     }
 
     /// 取得処理の実行。
+    /// 対象が存在しない場合（論理削除されたものを含む）は `PokemonError::NotFound` を返す。
     pub fn handle(&self, no: i32) -> Result<PokemonData> {
-        let number = PokemonNumber::try_from(no).unwrap();
-        match self.pokemon_repository.find_by_number(&number) {
-            Ok(value) => Ok(PokemonData::new(value)),
-            Err(_) => Err(anyhow::anyhow!(
-                "取得しようとしたポケモンが存在しません: no {:?}",
-                number
-            )),
-        }
+        let number = PokemonNumber::try_from(no).map_err(|_| PokemonError::NotFound(no))?;
+        let pokemon = self.pokemon_repository.find_by_number(&number)?;
+        Ok(PokemonData::new(pokemon))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::models::pokemon::pokemon_repository::tests::MockPokemonRepository;
     use crate::domain::models::pokemon::{
         pokemon::Pokemon, pokemon_name::PokemonName, pokemon_types::PokemonTypes,
     };
     use anyhow::Result;
-    use std::convert::TryInto;
 
     /// テストのためのモックリポジトリ
     pub struct MockPokemonRepositoryImpl {}
 
     impl MockPokemonRepositoryImpl {
-        fn new() -> impl PokemonRepository {
+        fn new() -> Self {
             MockPokemonRepositoryImpl {}
         }
     }
 
     /// モックリポジトリの振る舞い
-    impl PokemonRepository for MockPokemonRepositoryImpl {
+    impl MockPokemonRepository for MockPokemonRepositoryImpl {
         fn find_by_number(
             &self,
             number: &PokemonNumber,
         ) -> Result<crate::domain::models::pokemon::pokemon::Pokemon> {
-            let target_no: i32 = number.clone().try_into().unwrap();
+            let target_no: i32 = number.clone().into();
             match target_no {
                 1 => Ok(Pokemon::new(
                     number.clone(),
                     PokemonName::try_from("TestPokemon".to_string()).unwrap(),
                     PokemonTypes::try_from(vec!["Fire".to_string()]).unwrap(),
                 )),
-                _ => Err(anyhow::anyhow!("Dummy Error")),
+                2 => Err(anyhow::anyhow!("Dummy Error")),
+                _ => Err(PokemonError::NotFound(target_no).into()),
             }
         }
-
-        fn list(&self) -> Result<Vec<crate::domain::models::pokemon::pokemon::Pokemon>> {
-            unimplemented!();
-        }
-
-        fn insert(
-            &self,
-            _pokemon: &crate::domain::models::pokemon::pokemon::Pokemon,
-        ) -> Result<()> {
-            unimplemented!();
-        }
-
-        fn update(
-            &self,
-            _pokemon: &crate::domain::models::pokemon::pokemon::Pokemon,
-        ) -> Result<()> {
-            unimplemented!();
-        }
-
-        fn delete(&self, _number: &PokemonNumber) -> Result<()> {
-            unimplemented!();
-        }
     }
 
     #[test]
@@ -117,4 +93,16 @@ mod tests {
         let result = service.handle(2);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn handle_ng_not_found() {
+        let service = PokemonGetService::new(MockPokemonRepositoryImpl::new());
+        for no in [3, 0] {
+            let result = service.handle(no);
+            assert_eq!(
+                result.unwrap_err().downcast_ref::<PokemonError>(),
+                Some(&PokemonError::NotFound(no))
+            );
+        }
+    }
 }
@@ -0,0 +1,339 @@
+//! ポケモンの取り込み処理のためのアプリケーションサービス。
+//! 表計算ソフトなどで管理されたデータを検証し、まとめて登録・置き換えるユースケースの振る舞いを定義する。
+
+use crate::domain::models::pokemon::{
+    pokemon::Pokemon,
+    pokemon_event::PokemonEvent,
+    pokemon_event_bus::PokemonEventBus,
+    pokemon_name::PokemonName,
+    pokemon_number::PokemonNumber,
+    pokemon_repository::{PokemonOperation, PokemonRepository},
+    pokemon_types::PokemonTypes,
+};
+use anyhow::Result;
+use getset::Getters;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use utoipa::ToSchema;
+
+/// アプリケーションサービスの構造体。
+/// generics でリポジトリへの依存を表し、trait 境界を定義することで、DI を行う。
+pub struct PokemonImportService<T>
+where
+    T: PokemonRepository,
+{
+    pokemon_repository: T,
+    event_bus: PokemonEventBus,
+}
+
+/// 取り込む 1 行分のデータ。値は検証前の文字列のまま保持する。
+#[derive(Clone, Debug)]
+pub struct PokemonImportRow {
+    /// 元データでの行番号（見出し行を 1 行目とする）
+    pub line: usize,
+    pub number: String,
+    pub name: String,
+    pub types: Vec<String>,
+}
+
+/// 1 行分の取り込み内容
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PokemonImportAction {
+    Create,
+    Replace,
+}
+
+/// 1 行分の検証結果
+#[derive(Serialize, Getters, Clone, Debug, ToSchema)]
+pub struct PokemonImportRowResult {
+    #[getset(get = "pub with_prefix")]
+    line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[getset(get = "pub with_prefix")]
+    number: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[getset(get = "pub with_prefix")]
+    action: Option<PokemonImportAction>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[getset(get = "pub with_prefix")]
+    errors: Vec<String>,
+}
+
+/// 取り込み処理の結果
+#[derive(Serialize, Getters, Clone, Debug, ToSchema)]
+pub struct PokemonImportReport {
+    #[getset(get = "pub with_prefix")]
+    dry_run: bool,
+    /// データベースに反映したかどうか
+    #[getset(get = "pub with_prefix")]
+    imported: bool,
+    #[getset(get = "pub with_prefix")]
+    created: usize,
+    #[getset(get = "pub with_prefix")]
+    replaced: usize,
+    #[getset(get = "pub with_prefix")]
+    invalid: usize,
+    #[getset(get = "pub with_prefix")]
+    rows: Vec<PokemonImportRowResult>,
+}
+
+impl<T: PokemonRepository> PokemonImportService<T> {
+    /// コンストラクタ
+    pub fn new(pokemon_repository: T) -> Self {
+        Self {
+            pokemon_repository,
+            event_bus: PokemonEventBus::default(),
+        }
+    }
+
+    /// 取り込みに成功した際にドメインイベントを配信するイベントバスを設定する
+    pub fn with_event_bus(mut self, event_bus: PokemonEventBus) -> Self {
+        self.event_bus = event_bus;
+        self
+    }
+
+    /// 取り込み処理の実行。
+    /// 全ての行を検証し、1 行でも不正な行があれば何も反映しない。
+    /// `dry_run` が true の場合は検証結果だけを返す。
+    /// 反映する場合は 1 つのトランザクションで、存在しない図鑑 No は登録、存在する図鑑 No は置き換える。
+    /// 登録・置き換えのドメインイベントも同じトランザクションで記録する。
+    pub fn handle(
+        &self,
+        rows: Vec<PokemonImportRow>,
+        dry_run: bool,
+    ) -> Result<PokemonImportReport> {
+        let mut seen = HashSet::new();
+        let mut operations = vec![];
+        let mut results = vec![];
+        for row in rows.iter() {
+            let result = match self.validate(row, &mut seen) {
+                Ok((pokemon, action, current)) => {
+                    let number = pokemon.number.clone().into();
+                    let events = PokemonEvent::upserted(current.as_ref(), &pokemon);
+                    operations.push((PokemonOperation::Upsert(pokemon), events));
+                    PokemonImportRowResult {
+                        line: row.line,
+                        number: Some(number),
+                        action: Some(action),
+                        errors: vec![],
+                    }
+                }
+                Err(errors) => PokemonImportRowResult {
+                    line: row.line,
+                    number: row.number.trim().parse().ok(),
+                    action: None,
+                    errors,
+                },
+            };
+            results.push(result);
+        }
+
+        let count = |action| results.iter().filter(|r| r.action == Some(action)).count();
+        let created = count(PokemonImportAction::Create);
+        let replaced = count(PokemonImportAction::Replace);
+        let invalid = results.iter().filter(|r| !r.errors.is_empty()).count();
+
+        let imported = !dry_run && invalid == 0 && !operations.is_empty();
+        if imported {
+            for result in self.pokemon_repository.batch(&operations, true)? {
+                result?;
+            }
+            for (_, events) in operations.iter() {
+                self.event_bus.publish(events);
+            }
+        }
+        Ok(PokemonImportReport {
+            dry_run,
+            imported,
+            created,
+            replaced,
+            invalid,
+            rows: results,
+        })
+    }
+
+    /// 1 行分の値を検証し、エンティティと取り込み内容、置き換える場合は現在の状態を返す。
+    /// 不正な場合は全ての問題点を返す。
+    fn validate(
+        &self,
+        row: &PokemonImportRow,
+        seen: &mut HashSet<i32>,
+    ) -> Result<(Pokemon, PokemonImportAction, Option<Pokemon>), Vec<String>> {
+        let mut errors = vec![];
+        let number = match row.number.trim().parse::<i32>() {
+            Ok(value) => match PokemonNumber::try_from(value) {
+                Ok(number) if seen.insert(value) => Some(number),
+                Ok(_) => {
+                    errors.push(format!("図鑑 No が他の行と重複しています: no {}", value));
+                    None
+                }
+                Err(_) => {
+                    errors.push(format!("不正な図鑑 No です: no {}", value));
+                    None
+                }
+            },
+            Err(_) => {
+                errors.push(format!("図鑑 No が数値ではありません: {:?}", row.number));
+                None
+            }
+        };
+        let name = PokemonName::try_from(row.name.clone())
+            .map_err(|_| errors.push(format!("不正な名前です: {:?}", row.name)))
+            .ok();
+        let types = PokemonTypes::try_from(row.types.clone())
+            .map_err(|_| errors.push(format!("不正なタイプです: {:?}", row.types)))
+            .ok();
+
+        match (number, name, types) {
+            (Some(number), Some(name), Some(types)) => {
+                let current = self.pokemon_repository.find_by_number(&number).ok();
+                let action = if current.is_some() {
+                    PokemonImportAction::Replace
+                } else {
+                    PokemonImportAction::Create
+                };
+                Ok((Pokemon::new(number, name, types), action, current))
+            }
+            _ => Err(errors),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::pokemon::pokemon_event::PokemonRenamed;
+    use crate::domain::models::pokemon::pokemon_event_bus::tests::RecordingSubscriber;
+    use crate::domain::models::pokemon::pokemon_repository::tests::MockPokemonRepository;
+    use std::cell::{Cell, RefCell};
+
+    /// テストのためのモックリポジトリ。操作とともに渡されたドメインイベントを記録する。
+    pub struct MockPokemonRepositoryImpl {
+        batch_called: Cell<bool>,
+        events: RefCell<Vec<PokemonEvent>>,
+    }
+
+    impl MockPokemonRepositoryImpl {
+        fn new() -> Self {
+            MockPokemonRepositoryImpl {
+                batch_called: Cell::new(false),
+                events: RefCell::new(vec![]),
+            }
+        }
+    }
+
+    /// モックリポジトリの振る舞い
+    impl MockPokemonRepository for MockPokemonRepositoryImpl {
+        fn find_by_number(
+            &self,
+            number: &PokemonNumber,
+        ) -> Result<crate::domain::models::pokemon::pokemon::Pokemon> {
+            let target_no: i32 = number.clone().into();
+            match target_no {
+                1 => Ok(Pokemon::new(
+                    number.clone(),
+                    PokemonName::try_from("TestPokemon".to_string()).unwrap(),
+                    PokemonTypes::try_from(vec!["Fire".to_string()]).unwrap(),
+                )),
+                _ => Err(anyhow::anyhow!("Dummy Error")),
+            }
+        }
+
+        fn batch(
+            &self,
+            operations: &[(PokemonOperation, Vec<PokemonEvent>)],
+            _atomic: bool,
+        ) -> Result<Vec<Result<()>>> {
+            self.batch_called.set(true);
+            for (_, events) in operations {
+                self.events.borrow_mut().extend(events.iter().cloned());
+            }
+            Ok(operations.iter().map(|_| Ok(())).collect())
+        }
+    }
+
+    fn row(line: usize, number: &str, name: &str, types: &[&str]) -> PokemonImportRow {
+        PokemonImportRow {
+            line,
+            number: number.to_string(),
+            name: name.to_string(),
+            types: types.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn handle_ok() {
+        let repository = MockPokemonRepositoryImpl::new();
+        let service = PokemonImportService::new(repository);
+        let rows = vec![
+            row(2, "1", "TestPokemon", &["Fire"]),
+            row(3, "2", "TestName", &["Water", "Flying"]),
+        ];
+        let report = service.handle(rows, false).unwrap();
+        assert!(report.get_imported());
+        assert_eq!(*report.get_created(), 1);
+        assert_eq!(*report.get_replaced(), 1);
+        assert!(service.pokemon_repository.batch_called.get());
+    }
+
+    #[test]
+    fn handle_ok_events() {
+        let recorder = RecordingSubscriber::default();
+        let mut event_bus = PokemonEventBus::default();
+        event_bus.subscribe(recorder.clone());
+        let service =
+            PokemonImportService::new(MockPokemonRepositoryImpl::new()).with_event_bus(event_bus);
+        let rows = vec![
+            row(2, "1", "TestName", &["Fire"]),
+            row(3, "2", "TestName", &["Water"]),
+        ];
+        service.handle(rows, false).unwrap();
+
+        let mut renamed =
+            Pokemon::try_new(1, "TestName".to_string(), vec!["Fire".to_string()]).unwrap();
+        renamed.version = 2;
+        let expected = vec![
+            PokemonEvent::Renamed(PokemonRenamed {
+                from: PokemonName::try_from("TestPokemon".to_string()).unwrap(),
+                to: PokemonName::try_from("TestName".to_string()).unwrap(),
+                pokemon: renamed,
+            }),
+            PokemonEvent::registered(
+                &Pokemon::try_new(2, "TestName".to_string(), vec!["Water".to_string()]).unwrap(),
+            ),
+        ];
+        // 置き換えと同じトランザクションで記録され、取り込みの後に配信される
+        assert_eq!(*service.pokemon_repository.events.borrow(), expected);
+        assert_eq!(*recorder.0.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn handle_ok_dry_run() {
+        let repository = MockPokemonRepositoryImpl::new();
+        let service = PokemonImportService::new(repository);
+        let rows = vec![row(2, "2", "TestName", &["Water"])];
+        let report = service.handle(rows, true).unwrap();
+        assert!(!report.get_imported());
+        assert_eq!(*report.get_created(), 1);
+        assert!(!service.pokemon_repository.batch_called.get());
+    }
+
+    #[test]
+    fn handle_ng_invalid_rows() {
+        let repository = MockPokemonRepositoryImpl::new();
+        let service = PokemonImportService::new(repository);
+        let rows = vec![
+            row(2, "2", "TestName", &["Water"]),
+            row(3, "abc", "", &["Hoge"]),
+            row(4, "2", "TestName", &["Water"]),
+        ];
+        let report = service.handle(rows, false).unwrap();
+        assert!(!report.get_imported());
+        assert_eq!(*report.get_invalid(), 2);
+        assert_eq!(report.get_rows()[1].get_errors().len(), 3);
+        assert_eq!(report.get_rows()[2].get_number(), &Some(2));
+        assert!(!service.pokemon_repository.batch_called.get());
+    }
+}
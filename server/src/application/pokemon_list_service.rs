@@ -2,7 +2,9 @@
 //! 更新処理のユースケースの振る舞いを定義する
 
 use super::pokemon_data::PokemonData;
-use crate::domain::models::pokemon::pokemon_repository::PokemonRepository;
+use crate::domain::models::pokemon::{
+    pokemon_query::PokemonQuery, pokemon_repository::PokemonRepository,
+};
 use anyhow::Result;
 
 /// アプリケーションサービスの構造体。
@@ -20,24 +22,58 @@ impl<T: PokemonRepository> PokemonListService<T> {
         Self { pokemon_repository }
     }
 
-    /// 登録されているポケモンの一覧を表示
+    /// 登録されているポケモンの一覧を表示。1 件も登録されていない場合は空の一覧を返す。
     pub fn handle(&self) -> Result<Vec<PokemonData>> {
         match self.pokemon_repository.list() {
             Ok(value) => Ok(value
                 .iter()
                 .map(|c| PokemonData::new(c.clone()))
                 .collect::<Vec<PokemonData>>()),
-            Err(_) => Err(anyhow::anyhow!("登録されたポケモンが1つもありません。")),
+            Err(_) => Err(anyhow::anyhow!("ポケモンの一覧を取得できませんでした。")),
         }
     }
+
+    /// 条件に一致するポケモンの一覧を図鑑 No 順に返す。絞り込みはリポジトリで行う。
+    pub fn search(&self, query: &PokemonQuery) -> Result<Vec<PokemonData>> {
+        Ok(self
+            .pokemon_repository
+            .search(query)?
+            .into_iter()
+            .map(PokemonData::new)
+            .collect())
+    }
+
+    /// 条件に一致するポケモンを図鑑 No 順に offset 件飛ばして最大 limit 件返す。
+    /// 条件に一致した件数も合わせて返す。絞り込みとページの切り出しはリポジトリで行う。
+    pub fn search_page(
+        &self,
+        query: &PokemonQuery,
+        offset: i64,
+        limit: i64,
+    ) -> Result<(Vec<PokemonData>, i64)> {
+        let (pokemon, total) = self.pokemon_repository.search_page(query, offset, limit)?;
+        Ok((pokemon.into_iter().map(PokemonData::new).collect(), total))
+    }
+
+    /// 登録されているポケモンを 1 件ずつ読み込み、`f` に渡す。
+    /// 一覧をメモリに載せないため、件数に関わらず使用するメモリは一定になる。
+    /// `f` がエラーを返した場合は読み込みを中断する。
+    pub fn handle_each<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(PokemonData) -> Result<()>,
+    {
+        self.pokemon_repository
+            .scan(&mut |pokemon| f(PokemonData::new(pokemon)))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::models::pokemon::pokemon_repository::tests::MockPokemonRepository;
     use crate::domain::models::pokemon::{
         pokemon::Pokemon, pokemon_name::PokemonName, pokemon_number::PokemonNumber,
-        pokemon_types::PokemonTypes,
+        pokemon_type::PokemonType, pokemon_types::PokemonTypes,
     };
     use std::convert::TryFrom;
 
@@ -45,20 +81,13 @@ mod tests {
     pub struct OkMockPokemonRepositoryImpl {}
 
     impl OkMockPokemonRepositoryImpl {
-        fn new() -> impl PokemonRepository {
+        fn new() -> Self {
             OkMockPokemonRepositoryImpl {}
         }
     }
 
     /// OK モックリポジトリの振る舞い
-    impl PokemonRepository for OkMockPokemonRepositoryImpl {
-        fn find_by_number(
-            &self,
-            _number: &PokemonNumber,
-        ) -> Result<crate::domain::models::pokemon::pokemon::Pokemon> {
-            unimplemented!();
-        }
-
+    impl MockPokemonRepository for OkMockPokemonRepositoryImpl {
         fn list(&self) -> Result<Vec<crate::domain::models::pokemon::pokemon::Pokemon>> {
             let pokemon_1 = Pokemon::new(
                 PokemonNumber::try_from(1).unwrap(),
@@ -74,20 +103,48 @@ mod tests {
             Ok(result)
         }
 
-        fn insert(
+        fn search(&self, query: &PokemonQuery) -> Result<Vec<Pokemon>> {
+            let any_types = query
+                .any_types
+                .iter()
+                .cloned()
+                .map(String::from)
+                .collect::<Vec<_>>();
+            Ok(MockPokemonRepository::list(self)?
+                .into_iter()
+                .filter(|pokemon| {
+                    Vec::<String>::from(pokemon.types.clone())
+                        .iter()
+                        .any(|t| any_types.contains(t))
+                })
+                .collect())
+        }
+
+        fn search_page(
             &self,
-            _pokemon: &crate::domain::models::pokemon::pokemon::Pokemon,
-        ) -> Result<()> {
-            unimplemented!();
+            query: &PokemonQuery,
+            offset: i64,
+            limit: i64,
+        ) -> Result<(Vec<Pokemon>, i64)> {
+            let matched = MockPokemonRepository::search(self, query)?;
+            let total = matched.len() as i64;
+            Ok((
+                matched
+                    .into_iter()
+                    .skip(offset as usize)
+                    .take(limit as usize)
+                    .collect(),
+                total,
+            ))
         }
-        fn update(
+
+        fn scan(
             &self,
-            _pokemon: &crate::domain::models::pokemon::pokemon::Pokemon,
+            f: &mut dyn FnMut(crate::domain::models::pokemon::pokemon::Pokemon) -> Result<()>,
         ) -> Result<()> {
-            unimplemented!();
-        }
-        fn delete(&self, _number: &PokemonNumber) -> Result<()> {
-            unimplemented!();
+            MockPokemonRepository::list(self)?
+                .into_iter()
+                .try_for_each(f)
         }
     }
 
@@ -95,38 +152,22 @@ mod tests {
     pub struct NgMockPokemonRepositoryImpl {}
 
     impl NgMockPokemonRepositoryImpl {
-        fn new() -> impl PokemonRepository {
+        fn new() -> Self {
             NgMockPokemonRepositoryImpl {}
         }
     }
 
     /// モックリポジトリの振る舞い
-    impl PokemonRepository for NgMockPokemonRepositoryImpl {
-        fn find_by_number(
-            &self,
-            _number: &PokemonNumber,
-        ) -> Result<crate::domain::models::pokemon::pokemon::Pokemon> {
-            unimplemented!();
-        }
-
+    impl MockPokemonRepository for NgMockPokemonRepositoryImpl {
         fn list(&self) -> Result<Vec<crate::domain::models::pokemon::pokemon::Pokemon>> {
             Err(anyhow::anyhow!("Dummy Error"))
         }
 
-        fn insert(
+        fn scan(
             &self,
-            _pokemon: &crate::domain::models::pokemon::pokemon::Pokemon,
+            _f: &mut dyn FnMut(crate::domain::models::pokemon::pokemon::Pokemon) -> Result<()>,
         ) -> Result<()> {
-            unimplemented!();
-        }
-        fn update(
-            &self,
-            _pokemon: &crate::domain::models::pokemon::pokemon::Pokemon,
-        ) -> Result<()> {
-            unimplemented!();
-        }
-        fn delete(&self, _number: &PokemonNumber) -> Result<()> {
-            unimplemented!();
+            Err(anyhow::anyhow!("Dummy Error"))
         }
     }
 
@@ -150,6 +191,63 @@ mod tests {
         assert_eq!(result.unwrap(), expect);
     }
 
+    #[test]
+    fn search_ok() {
+        let repository = OkMockPokemonRepositoryImpl::new();
+        let service = PokemonListService::new(repository);
+        let query = PokemonQuery {
+            any_types: vec![PokemonType::Water],
+            ..Default::default()
+        };
+        let result = service.search(&query).unwrap();
+        assert_eq!(
+            result.iter().map(|p| *p.get_number()).collect::<Vec<_>>(),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn search_page_ok() {
+        let repository = OkMockPokemonRepositoryImpl::new();
+        let service = PokemonListService::new(repository);
+        let query = PokemonQuery {
+            any_types: vec![PokemonType::Fire, PokemonType::Water],
+            ..Default::default()
+        };
+        let (result, total) = service.search_page(&query, 1, 10).unwrap();
+        assert_eq!(
+            result.iter().map(|p| *p.get_number()).collect::<Vec<_>>(),
+            vec![2]
+        );
+        assert_eq!(total, 2);
+    }
+
+    #[test]
+    fn handle_each_ok() {
+        let repository = OkMockPokemonRepositoryImpl::new();
+        let service = PokemonListService::new(repository);
+        let mut numbers = vec![];
+        let result = service.handle_each(|pokemon| {
+            numbers.push(*pokemon.get_number());
+            Ok(())
+        });
+        assert!(result.is_ok());
+        assert_eq!(numbers, vec![1, 2]);
+    }
+
+    #[test]
+    fn handle_each_ng_aborted() {
+        let repository = OkMockPokemonRepositoryImpl::new();
+        let service = PokemonListService::new(repository);
+        let mut count = 0;
+        let result = service.handle_each(|_| {
+            count += 1;
+            Err(anyhow::anyhow!("Dummy Error"))
+        });
+        assert!(result.is_err());
+        assert_eq!(count, 1);
+    }
+
     #[test]
     fn handle_ng() {
         let repository = NgMockPokemonRepositoryImpl::new();
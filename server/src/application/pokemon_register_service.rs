@@ -5,8 +5,14 @@ use anyhow::Result;
 use std::convert::TryFrom;
 
 use crate::domain::models::pokemon::{
-    pokemon::Pokemon, pokemon_name::PokemonName, pokemon_number::PokemonNumber,
-    pokemon_repository::PokemonRepository, pokemon_types::PokemonTypes,
+    pokemon::Pokemon,
+    pokemon_error::PokemonError,
+    pokemon_event::PokemonEvent,
+    pokemon_event_bus::PokemonEventBus,
+    pokemon_name::PokemonName,
+    pokemon_number::PokemonNumber,
+    pokemon_repository::{PokemonOperation, PokemonRepository},
+    pokemon_types::PokemonTypes,
 };
 
 use super::pokemon_data::PokemonData;
@@ -15,57 +21,69 @@ use super::pokemon_data::PokemonData;
 /// generics でリポジトリへの依存を表し、trait 境界を定義することで、DI を行う。
 pub struct PokemonRegisterService<T: PokemonRepository> {
     pokemon_repository: T,
+    event_bus: PokemonEventBus,
 }
 
 impl<T: PokemonRepository> PokemonRegisterService<T> {
     // コンストラクタ
     pub fn new(pokemon_repository: T) -> Self {
-        Self { pokemon_repository }
+        Self {
+            pokemon_repository,
+            event_bus: PokemonEventBus::default(),
+        }
+    }
+
+    /// 登録に成功した際にドメインイベントを配信するイベントバスを設定する
+    pub fn with_event_bus(mut self, event_bus: PokemonEventBus) -> Self {
+        self.event_bus = event_bus;
+        self
     }
 
-    /// ポケモンの登録処理
-    pub fn handle(&self, data: PokemonData) -> Result<()> {
+    /// ポケモンの登録処理。登録したポケモンを返す。
+    /// 登録とドメインイベントの記録は同じトランザクションで行う。
+    pub fn handle(&self, data: PokemonData) -> Result<PokemonData> {
         let pokemon = Pokemon::new(
-            PokemonNumber::try_from(data.get_number().clone()).unwrap(),
+            PokemonNumber::try_from(*data.get_number()).unwrap(),
             PokemonName::try_from(data.get_name().clone()).unwrap(),
             PokemonTypes::try_from(data.get_types().clone()).unwrap(),
         );
 
         if self.pokemon_repository.exists(&pokemon) {
-            return Err(anyhow::anyhow!(
-                "作成しようとしたポケモンが既に存在しています。"
-            ));
-        } else {
-            self.pokemon_repository.insert(&pokemon).unwrap();
+            return Err(PokemonError::AlreadyExists(*data.get_number()).into());
         }
-        Ok(())
+        let events = [PokemonEvent::registered(&pokemon)];
+        self.pokemon_repository
+            .save(&PokemonOperation::Insert(pokemon.clone()), &events)?;
+        self.event_bus.publish(&events);
+        Ok(PokemonData::new(pokemon))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::models::pokemon::pokemon_event_bus::tests::RecordingSubscriber;
+    use crate::domain::models::pokemon::pokemon_repository::tests::MockPokemonRepository;
     use crate::domain::models::pokemon::{
         pokemon::Pokemon, pokemon_name::PokemonName, pokemon_types::PokemonTypes,
     };
-    use std::convert::TryInto;
 
     /// テストのためのモックリポジトリ
     pub struct MockPokemonRepositoryImpl {}
 
     impl MockPokemonRepositoryImpl {
-        fn new() -> impl PokemonRepository {
+        fn new() -> Self {
             MockPokemonRepositoryImpl {}
         }
     }
 
     /// モックリポジトリの振る舞い
-    impl PokemonRepository for MockPokemonRepositoryImpl {
+    impl MockPokemonRepository for MockPokemonRepositoryImpl {
         fn find_by_number(
             &self,
             number: &PokemonNumber,
         ) -> Result<crate::domain::models::pokemon::pokemon::Pokemon> {
-            let target_no: i32 = number.clone().try_into().unwrap();
+            let target_no: i32 = number.clone().into();
             match target_no {
                 1 => Ok(Pokemon::new(
                     number.clone(),
@@ -76,26 +94,18 @@ mod tests {
             }
         }
 
-        fn list(&self) -> Result<Vec<crate::domain::models::pokemon::pokemon::Pokemon>> {
-            unimplemented!();
-        }
-
-        fn insert(
+        fn save(
             &self,
-            _pokemon: &crate::domain::models::pokemon::pokemon::Pokemon,
+            operation: &crate::domain::models::pokemon::pokemon_repository::PokemonOperation,
+            _events: &[crate::domain::models::pokemon::pokemon_event::PokemonEvent],
         ) -> Result<()> {
-            Ok(())
-        }
-
-        fn update(
-            &self,
-            _pokemon: &crate::domain::models::pokemon::pokemon::Pokemon,
-        ) -> Result<()> {
-            unimplemented!();
-        }
-
-        fn delete(&self, _number: &PokemonNumber) -> Result<()> {
-            unimplemented!();
+            // 図鑑 No 3 は論理削除されているものとする
+            match operation {
+                PokemonOperation::Insert(pokemon) if i32::from(pokemon.number.clone()) == 3 => {
+                    Err(PokemonError::Deleted(3).into())
+                }
+                _ => Ok(()),
+            }
         }
     }
 
@@ -108,8 +118,36 @@ mod tests {
             PokemonName::try_from("TestPokemon".to_string()).unwrap(),
             PokemonTypes::try_from(vec!["Fire".to_string()]).unwrap(),
         );
-        let result = service.handle(PokemonData::new(data));
-        assert!(result.is_ok());
+        let result = service.handle(PokemonData::new(data.clone()));
+        assert_eq!(result.unwrap(), PokemonData::new(data));
+    }
+
+    #[test]
+    fn handle_ok_publish_registered() {
+        let recorder = RecordingSubscriber::default();
+        let mut event_bus = PokemonEventBus::default();
+        event_bus.subscribe(recorder.clone());
+        let service =
+            PokemonRegisterService::new(MockPokemonRepositoryImpl::new()).with_event_bus(event_bus);
+        let data = Pokemon::new(
+            PokemonNumber::try_from(2).unwrap(),
+            PokemonName::try_from("TestPokemon".to_string()).unwrap(),
+            PokemonTypes::try_from(vec!["Fire".to_string()]).unwrap(),
+        );
+        service.handle(PokemonData::new(data.clone())).unwrap();
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            vec![PokemonEvent::registered(&data)]
+        );
+
+        // 登録に失敗した場合は配信しない
+        let data = Pokemon::new(
+            PokemonNumber::try_from(1).unwrap(),
+            PokemonName::try_from("TestPokemon".to_string()).unwrap(),
+            PokemonTypes::try_from(vec!["Fire".to_string()]).unwrap(),
+        );
+        assert!(service.handle(PokemonData::new(data)).is_err());
+        assert_eq!(recorder.0.lock().unwrap().len(), 1);
     }
 
     #[test]
@@ -122,6 +160,29 @@ mod tests {
             PokemonTypes::try_from(vec!["Fire".to_string()]).unwrap(),
         );
         let result = service.handle(PokemonData::new(data));
-        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<PokemonError>(),
+            Some(&PokemonError::AlreadyExists(1))
+        );
+    }
+
+    #[test]
+    fn handle_ng_deleted_no() {
+        let recorder = RecordingSubscriber::default();
+        let mut event_bus = PokemonEventBus::default();
+        event_bus.subscribe(recorder.clone());
+        let service =
+            PokemonRegisterService::new(MockPokemonRepositoryImpl::new()).with_event_bus(event_bus);
+        let data = Pokemon::new(
+            PokemonNumber::try_from(3).unwrap(),
+            PokemonName::try_from("TestPokemon".to_string()).unwrap(),
+            PokemonTypes::try_from(vec!["Fire".to_string()]).unwrap(),
+        );
+        let result = service.handle(PokemonData::new(data));
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<PokemonError>(),
+            Some(&PokemonError::Deleted(3))
+        );
+        assert!(recorder.0.lock().unwrap().is_empty());
     }
 }
@@ -0,0 +1,137 @@
+//! ポケモン復元処理のためのアプリケーションサービス。
+//! 論理削除したポケモンを元に戻すユースケースの振る舞いを定義する。
+
+use super::pokemon_data::PokemonData;
+use crate::domain::models::pokemon::{
+    pokemon_error::PokemonError, pokemon_event::PokemonEvent, pokemon_event_bus::PokemonEventBus,
+    pokemon_number::PokemonNumber, pokemon_repository::PokemonRepository,
+};
+use anyhow::Result;
+use std::convert::TryFrom;
+
+/// アプリケーションサービスの構造体。
+/// generics でリポジトリへの依存を表し、trait 境界を定義することで、DI を行う。
+pub struct PokemonRestoreService<T>
+where
+    T: PokemonRepository,
+{
+    pokemon_repository: T,
+    event_bus: PokemonEventBus,
+}
+
+/// アプリケーションサービスの振る舞いを定義。
+impl<T: PokemonRepository> PokemonRestoreService<T> {
+    /// コンストラクタ
+    pub fn new(pokemon_repository: T) -> Self {
+        Self {
+            pokemon_repository,
+            event_bus: PokemonEventBus::default(),
+        }
+    }
+
+    /// 復元に成功した際にドメインイベントを配信するイベントバスを設定する
+    pub fn with_event_bus(mut self, event_bus: PokemonEventBus) -> Self {
+        self.event_bus = event_bus;
+        self
+    }
+
+    /// 復元処理の実行。
+    /// 論理削除されたものが存在しない場合は `PokemonError::NotFound` を返す。
+    /// 復元したポケモンは図鑑に再び登録されたものとして、登録のドメインイベントを同じトランザクションで記録する。
+    pub fn handle(&self, number: i32) -> Result<PokemonData> {
+        let target_no =
+            PokemonNumber::try_from(number).map_err(|_| PokemonError::NotFound(number))?;
+        let restored = self.pokemon_repository.restore(&target_no, &|pokemon| {
+            vec![PokemonEvent::registered(pokemon)]
+        })?;
+        match restored {
+            Some((pokemon, events)) => {
+                self.event_bus.publish(&events);
+                Ok(PokemonData::new(pokemon))
+            }
+            None => Err(PokemonError::NotFound(number).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::pokemon::pokemon_event_bus::tests::RecordingSubscriber;
+    use crate::domain::models::pokemon::pokemon_repository::tests::MockPokemonRepository;
+    use crate::domain::models::pokemon::{
+        pokemon::Pokemon, pokemon_name::PokemonName, pokemon_types::PokemonTypes,
+    };
+    use std::cell::RefCell;
+
+    /// テストのためのモックリポジトリ。復元とともに渡されたドメインイベントを記録する。
+    pub struct MockPokemonRepositoryImpl {
+        events: RefCell<Vec<PokemonEvent>>,
+    }
+
+    impl MockPokemonRepositoryImpl {
+        fn new() -> Self {
+            MockPokemonRepositoryImpl {
+                events: RefCell::new(vec![]),
+            }
+        }
+    }
+
+    /// モックリポジトリの振る舞い
+    impl MockPokemonRepository for MockPokemonRepositoryImpl {
+        fn restore(
+            &self,
+            number: &PokemonNumber,
+            events: &dyn Fn(&Pokemon) -> Vec<PokemonEvent>,
+        ) -> Result<Option<(Pokemon, Vec<PokemonEvent>)>> {
+            let target_no: i32 = number.clone().into();
+            match target_no {
+                1 => {
+                    let pokemon = Pokemon::new(
+                        number.clone(),
+                        PokemonName::try_from("TestPokemon".to_string()).unwrap(),
+                        PokemonTypes::try_from(vec!["Fire".to_string()]).unwrap(),
+                    );
+                    let events = events(&pokemon);
+                    self.events.borrow_mut().extend(events.iter().cloned());
+                    Ok(Some((pokemon, events)))
+                }
+                _ => Ok(None),
+            }
+        }
+    }
+
+    #[test]
+    fn handle_ok() {
+        let service = PokemonRestoreService::new(MockPokemonRepositoryImpl::new());
+        let result = service.handle(1);
+        assert_eq!(*result.unwrap().get_number(), 1);
+    }
+
+    #[test]
+    fn handle_ok_events() {
+        let recorder = RecordingSubscriber::default();
+        let mut event_bus = PokemonEventBus::default();
+        event_bus.subscribe(recorder.clone());
+        let service =
+            PokemonRestoreService::new(MockPokemonRepositoryImpl::new()).with_event_bus(event_bus);
+        service.handle(1).unwrap();
+
+        let expected = vec![PokemonEvent::registered(
+            &Pokemon::try_new(1, "TestPokemon".to_string(), vec!["Fire".to_string()]).unwrap(),
+        )];
+        // 復元と同じトランザクションで記録され、成功した後に配信される
+        assert_eq!(*service.pokemon_repository.events.borrow(), expected);
+        assert_eq!(*recorder.0.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn handle_ng_not_deleted() {
+        let service = PokemonRestoreService::new(MockPokemonRepositoryImpl::new());
+        let result = service.handle(2);
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<PokemonError>(),
+            Some(&PokemonError::NotFound(2))
+        );
+    }
+}
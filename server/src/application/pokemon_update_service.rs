@@ -1,9 +1,10 @@
 //! ポケモン更新処理のためのアプリケーションサービス。
 //! 更新処理のユースケースの振る舞いを定義する。
 
-use crate::domain::models::pokemon::pokemon_repository::PokemonRepository;
+use crate::domain::models::pokemon::pokemon_repository::{PokemonOperation, PokemonRepository};
 use crate::domain::models::pokemon::{
-    pokemon::Pokemon, pokemon_name::PokemonName, pokemon_number::PokemonNumber,
+    pokemon::Pokemon, pokemon_error::PokemonError, pokemon_event::PokemonEvent,
+    pokemon_event_bus::PokemonEventBus, pokemon_name::PokemonName, pokemon_number::PokemonNumber,
     pokemon_types::PokemonTypes,
 };
 use anyhow::Result;
@@ -17,29 +18,40 @@ where
     T: PokemonRepository,
 {
     pokemon_repository: T,
+    event_bus: PokemonEventBus,
 }
 
 /// アプリケーションサービスの振る舞いを定義。
 impl<T: PokemonRepository> PokemonUpdateService<T> {
     /// コンストラクタ
     pub fn new(pokemon_repository: T) -> Self {
-        Self { pokemon_repository }
+        Self {
+            pokemon_repository,
+            event_bus: PokemonEventBus::default(),
+        }
+    }
+
+    /// 更新に成功した際にドメインイベントを配信するイベントバスを設定する
+    pub fn with_event_bus(mut self, event_bus: PokemonEventBus) -> Self {
+        self.event_bus = event_bus;
+        self
     }
 
     /// 更新処理の実行。
+    /// コマンドで指定されなかった項目は現在の値のまま残す。
+    /// 版が指定されている場合、現在の版と一致しなければ `PokemonError::VersionMismatch` を返す。
+    /// 名前・タイプのうち実際に変わったものについて、更新と同じトランザクションでドメインイベントを記録し、配信する。
     pub fn handle(&self, command: PokemonUpdateCommand) -> Result<Pokemon> {
-        let target_no = PokemonNumber::try_from(*command.get_number()).unwrap();
+        let target_no = PokemonNumber::try_from(*command.get_number())
+            .map_err(|_| anyhow::anyhow!("不正な図鑑 No です: no {}", command.get_number()))?;
         match self.pokemon_repository.find_by_number(&target_no) {
-            Ok(mut result) => {
-                result.name = match command.get_name() {
-                    Some(value) => PokemonName::try_from(value.clone()).unwrap(),
-                    None => PokemonName::try_from(String::from("名前未設定")).unwrap(),
-                };
-                result.types = match command.get_types() {
-                    Some(value) => PokemonTypes::try_from(value.clone()).unwrap(),
-                    None => PokemonTypes::try_from(vec![String::from("Unknown")]).unwrap(),
-                };
-                self.pokemon_repository.update(&result).unwrap();
+            Ok(current) => {
+                let mut result = command.apply(current.clone())?;
+                let events = PokemonEvent::updated(&current, &result);
+                self.pokemon_repository
+                    .save(&PokemonOperation::Update(result.clone()), &events)?;
+                result.version += 1;
+                self.event_bus.publish(&events);
                 Ok(result)
             }
             Err(_) => Err(anyhow::anyhow!(
@@ -59,6 +71,9 @@ pub struct PokemonUpdateCommand {
     name: Option<String>,
     #[getset(get = "pub with_prefix", set = "pub with_prefix")]
     types: Option<Vec<String>>,
+    /// 更新の前提とする版（If-Match で指定されたもの）
+    #[getset(get = "pub with_prefix", set = "pub with_prefix")]
+    expected_version: Option<i32>,
 }
 
 /// ポケモン情報のアップデートコマンドオブジェクトの振る舞いを定義
@@ -69,7 +84,31 @@ impl PokemonUpdateCommand {
             number,
             name: None,
             types: None,
+            expected_version: None,
+        }
+    }
+
+    /// コマンドの内容を現在のポケモンに反映する。指定されなかった項目は現在の値のまま残す。
+    /// 版が指定されている場合、現在の版と一致しなければ `PokemonError::VersionMismatch` を返す。
+    pub fn apply(&self, mut current: Pokemon) -> Result<Pokemon, PokemonError> {
+        if let Some(expected) = self.expected_version {
+            if expected != current.version {
+                return Err(PokemonError::VersionMismatch {
+                    number: self.number,
+                    expected,
+                });
+            }
         }
+        if let Some(value) = &self.name {
+            current.name = PokemonName::try_from(value.clone())
+                .map_err(|_| PokemonError::InvalidValue(format!("不正な名前です: {:?}", value)))?;
+        }
+        if let Some(value) = &self.types {
+            current.types = PokemonTypes::try_from(value.clone()).map_err(|_| {
+                PokemonError::InvalidValue(format!("不正なタイプです: {:?}", value))
+            })?;
+        }
+        Ok(current)
     }
 }
 
@@ -77,24 +116,26 @@ impl PokemonUpdateCommand {
 mod tests {
     use super::*;
     use crate::domain::models::pokemon::pokemon::Pokemon;
-    use std::convert::TryInto;
+    use crate::domain::models::pokemon::pokemon_event::{PokemonRenamed, PokemonRetyped};
+    use crate::domain::models::pokemon::pokemon_event_bus::tests::RecordingSubscriber;
+    use crate::domain::models::pokemon::pokemon_repository::tests::MockPokemonRepository;
 
     /// テストのためのモックリポジトリ
     pub struct MockPokemonRepositoryImpl {}
 
     impl MockPokemonRepositoryImpl {
-        fn new() -> impl PokemonRepository {
+        fn new() -> Self {
             MockPokemonRepositoryImpl {}
         }
     }
 
     /// モックリポジトリの振る舞い
-    impl PokemonRepository for MockPokemonRepositoryImpl {
+    impl MockPokemonRepository for MockPokemonRepositoryImpl {
         fn find_by_number(
             &self,
             number: &PokemonNumber,
         ) -> Result<crate::domain::models::pokemon::pokemon::Pokemon> {
-            let target_no: i32 = number.clone().try_into().unwrap();
+            let target_no: i32 = number.clone().into();
             match target_no {
                 1 => Ok(Pokemon::new(
                     number.clone(),
@@ -105,27 +146,13 @@ mod tests {
             }
         }
 
-        fn list(&self) -> Result<Vec<crate::domain::models::pokemon::pokemon::Pokemon>> {
-            unimplemented!();
-        }
-
-        fn insert(
+        fn save(
             &self,
-            _pokemon: &crate::domain::models::pokemon::pokemon::Pokemon,
-        ) -> Result<()> {
-            unimplemented!();
-        }
-
-        fn update(
-            &self,
-            _pokemon: &crate::domain::models::pokemon::pokemon::Pokemon,
+            _operation: &crate::domain::models::pokemon::pokemon_repository::PokemonOperation,
+            _events: &[crate::domain::models::pokemon::pokemon_event::PokemonEvent],
         ) -> Result<()> {
             Ok(())
         }
-
-        fn delete(&self, _number: &PokemonNumber) -> Result<()> {
-            unimplemented!();
-        }
     }
 
     #[test]
@@ -137,11 +164,31 @@ mod tests {
         assert!(result.is_ok());
 
         let result_pokemon = result.unwrap();
-        let expect = Pokemon::new(
+        let mut expect = Pokemon::new(
+            PokemonNumber::try_from(1).unwrap(),
+            PokemonName::try_from("TestPokemon".to_string()).unwrap(),
+            PokemonTypes::try_from(vec!["Fire".to_string()]).unwrap(),
+        );
+        expect.version = 2;
+        assert_eq!(result_pokemon, expect);
+    }
+
+    #[test]
+    fn handle_ok_set_name_only() {
+        let repository = MockPokemonRepositoryImpl::new();
+        let service = PokemonUpdateService::new(repository);
+        let mut command = PokemonUpdateCommand::new(1);
+        command.set_name(Some("TestName".to_string()));
+        let result = service.handle(command);
+        assert!(result.is_ok());
+
+        let result_pokemon = result.unwrap();
+        let mut expect = Pokemon::new(
             PokemonNumber::try_from(1).unwrap(),
-            PokemonName::try_from("名前未設定".to_string()).unwrap(),
-            PokemonTypes::try_from(vec!["Unknown".to_string()]).unwrap(),
+            PokemonName::try_from("TestName".to_string()).unwrap(),
+            PokemonTypes::try_from(vec!["Fire".to_string()]).unwrap(),
         );
+        expect.version = 2;
         assert_eq!(result_pokemon, expect);
     }
 
@@ -156,14 +203,92 @@ mod tests {
         assert!(result.is_ok());
 
         let result_pokemon = result.unwrap();
-        let expect = Pokemon::new(
+        let mut expect = Pokemon::new(
             PokemonNumber::try_from(1).unwrap(),
             PokemonName::try_from("TestName".to_string()).unwrap(),
             PokemonTypes::try_from(vec!["Fire".to_string()]).unwrap(),
         );
+        expect.version = 2;
         assert_eq!(result_pokemon, expect);
     }
 
+    #[test]
+    fn handle_ok_publish_changed_fields() {
+        let recorder = RecordingSubscriber::default();
+        let mut event_bus = PokemonEventBus::default();
+        event_bus.subscribe(recorder.clone());
+        let service =
+            PokemonUpdateService::new(MockPokemonRepositoryImpl::new()).with_event_bus(event_bus);
+
+        // 値が変わらない項目のイベントは配信しない
+        let mut command = PokemonUpdateCommand::new(1);
+        command.set_name(Some("TestPokemon".to_string()));
+        command.set_types(Some(vec!["Fire".to_string()]));
+        service.handle(command).unwrap();
+        assert!(recorder.0.lock().unwrap().is_empty());
+
+        let mut command = PokemonUpdateCommand::new(1);
+        command.set_name(Some("TestName".to_string()));
+        command.set_types(Some(vec!["Water".to_string()]));
+        service.handle(command).unwrap();
+        let mut saved =
+            Pokemon::try_new(1, "TestName".to_string(), vec!["Water".to_string()]).unwrap();
+        saved.version = 2;
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            vec![
+                PokemonEvent::Renamed(PokemonRenamed {
+                    from: PokemonName::try_from("TestPokemon".to_string()).unwrap(),
+                    to: PokemonName::try_from("TestName".to_string()).unwrap(),
+                    pokemon: saved.clone(),
+                }),
+                PokemonEvent::Retyped(PokemonRetyped {
+                    from: PokemonTypes::try_from(vec!["Fire".to_string()]).unwrap(),
+                    to: PokemonTypes::try_from(vec!["Water".to_string()]).unwrap(),
+                    pokemon: saved,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_ng_invalid_name() {
+        let repository = MockPokemonRepositoryImpl::new();
+        let service = PokemonUpdateService::new(repository);
+        let mut command = PokemonUpdateCommand::new(1);
+        command.set_name(Some("".to_string()));
+        let result = service.handle(command);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn handle_ok_expected_version() {
+        let repository = MockPokemonRepositoryImpl::new();
+        let service = PokemonUpdateService::new(repository);
+        let mut command = PokemonUpdateCommand::new(1);
+        command.set_name(Some("TestName".to_string()));
+        command.set_expected_version(Some(1));
+        let result = service.handle(command);
+        assert_eq!(result.unwrap().version, 2);
+    }
+
+    #[test]
+    fn handle_ng_version_mismatch() {
+        let repository = MockPokemonRepositoryImpl::new();
+        let service = PokemonUpdateService::new(repository);
+        let mut command = PokemonUpdateCommand::new(1);
+        command.set_name(Some("TestName".to_string()));
+        command.set_expected_version(Some(2));
+        let result = service.handle(command);
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<PokemonError>(),
+            Some(&PokemonError::VersionMismatch {
+                number: 1,
+                expected: 2
+            })
+        );
+    }
+
     #[test]
     fn handle_ng() {
         let repository = MockPokemonRepositoryImpl::new();
@@ -0,0 +1,260 @@
+//! ポケモン登録・置き換え処理のためのアプリケーションサービス。
+//! PUT による登録または全項目の置き換えのユースケースの振る舞いを定義する。
+
+use super::pokemon_data::PokemonData;
+use crate::domain::models::pokemon::{
+    pokemon::Pokemon,
+    pokemon_error::PokemonError,
+    pokemon_event::PokemonEvent,
+    pokemon_event_bus::PokemonEventBus,
+    pokemon_repository::{PokemonOperation, PokemonRepository},
+};
+use anyhow::Result;
+use getset::{Getters, Setters};
+
+/// アプリケーションサービスの構造体。
+/// generics でリポジトリへの依存を表し、trait 境界を定義することで、DI を行う。
+pub struct PokemonUpsertService<T>
+where
+    T: PokemonRepository,
+{
+    pokemon_repository: T,
+    event_bus: PokemonEventBus,
+}
+
+/// 登録・置き換え処理の結果
+#[derive(Debug, PartialEq, Eq)]
+pub enum PokemonUpserted {
+    /// 新しく登録した
+    Created(PokemonData),
+    /// 既存のデータを置き換えた
+    Replaced(PokemonData),
+}
+
+/// アプリケーションサービスの振る舞いを定義。
+impl<T: PokemonRepository> PokemonUpsertService<T> {
+    /// コンストラクタ
+    pub fn new(pokemon_repository: T) -> Self {
+        Self {
+            pokemon_repository,
+            event_bus: PokemonEventBus::default(),
+        }
+    }
+
+    /// 登録・置き換えに成功した際にドメインイベントを配信するイベントバスを設定する
+    pub fn with_event_bus(mut self, event_bus: PokemonEventBus) -> Self {
+        self.event_bus = event_bus;
+        self
+    }
+
+    /// 登録・置き換え処理の実行。
+    /// 版が指定されている場合は、既存のデータがその版であるときだけ置き換える。
+    /// 登録・置き換えとドメインイベントの記録は同じトランザクションで行う。
+    pub fn handle(&self, command: PokemonUpsertCommand) -> Result<PokemonUpserted> {
+        let mut pokemon = Pokemon::try_new(
+            *command.get_number(),
+            command.get_name().clone(),
+            command.get_types().clone(),
+        )?;
+
+        match command.get_expected_version() {
+            Some(expected) => {
+                let mismatch = PokemonError::VersionMismatch {
+                    number: *command.get_number(),
+                    expected: *expected,
+                };
+                match self.pokemon_repository.find_by_number(&pokemon.number) {
+                    Ok(current) if current.version == *expected => {
+                        pokemon.version = *expected;
+                        let events = PokemonEvent::updated(&current, &pokemon);
+                        self.pokemon_repository
+                            .save(&PokemonOperation::Update(pokemon.clone()), &events)?;
+                        self.event_bus.publish(&events);
+                        pokemon.version += 1;
+                        Ok(PokemonUpserted::Replaced(PokemonData::new(pokemon)))
+                    }
+                    _ => Err(mismatch.into()),
+                }
+            }
+            None => {
+                let (stored, events) = self
+                    .pokemon_repository
+                    .upsert(&pokemon, &PokemonEvent::upserted)?;
+                self.event_bus.publish(&events);
+                // 置き換えた場合は版が進むため、初期値のままであれば新しく登録されている
+                if stored.version == 1 {
+                    Ok(PokemonUpserted::Created(PokemonData::new(stored)))
+                } else {
+                    Ok(PokemonUpserted::Replaced(PokemonData::new(stored)))
+                }
+            }
+        }
+    }
+}
+
+/// ポケモン情報の登録・置き換えコマンドオブジェクト
+#[derive(Getters, Setters)]
+pub struct PokemonUpsertCommand {
+    #[getset(get = "pub with_prefix")]
+    number: i32,
+    #[getset(get = "pub with_prefix")]
+    name: String,
+    #[getset(get = "pub with_prefix")]
+    types: Vec<String>,
+    /// 置き換えの前提とする版（If-Match で指定されたもの）
+    #[getset(get = "pub with_prefix", set = "pub with_prefix")]
+    expected_version: Option<i32>,
+}
+
+/// ポケモン情報の登録・置き換えコマンドオブジェクトの振る舞いを定義
+impl PokemonUpsertCommand {
+    /// コンストラクタ
+    pub fn new(number: i32, name: String, types: Vec<String>) -> Self {
+        Self {
+            number,
+            name,
+            types,
+            expected_version: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::pokemon::pokemon_event_bus::tests::RecordingSubscriber;
+    use crate::domain::models::pokemon::pokemon_repository::tests::MockPokemonRepository;
+    use crate::domain::models::pokemon::{
+        pokemon_name::PokemonName, pokemon_number::PokemonNumber, pokemon_types::PokemonTypes,
+    };
+    use std::cell::RefCell;
+    use std::convert::TryFrom;
+
+    /// テストのためのモックリポジトリ。保存とともに渡されたドメインイベントを記録する。
+    pub struct MockPokemonRepositoryImpl {
+        events: RefCell<Vec<PokemonEvent>>,
+    }
+
+    impl MockPokemonRepositoryImpl {
+        fn new() -> Self {
+            MockPokemonRepositoryImpl {
+                events: RefCell::new(vec![]),
+            }
+        }
+    }
+
+    /// モックリポジトリの振る舞い
+    impl MockPokemonRepository for MockPokemonRepositoryImpl {
+        fn find_by_number(&self, number: &PokemonNumber) -> Result<Pokemon> {
+            let target_no: i32 = number.clone().into();
+            match target_no {
+                1 => Ok(Pokemon::new(
+                    number.clone(),
+                    PokemonName::try_from("TestPokemon".to_string()).unwrap(),
+                    PokemonTypes::try_from(vec!["Fire".to_string()]).unwrap(),
+                )),
+                _ => Err(anyhow::anyhow!("Dummy Error")),
+            }
+        }
+
+        fn upsert(
+            &self,
+            pokemon: &Pokemon,
+            events: &dyn Fn(Option<&Pokemon>, &Pokemon) -> Vec<PokemonEvent>,
+        ) -> Result<(Pokemon, Vec<PokemonEvent>)> {
+            let before = MockPokemonRepository::find_by_number(self, &pokemon.number).ok();
+            let mut stored = pokemon.clone();
+            if before.is_some() {
+                stored.version += 1;
+            }
+            let events = events(before.as_ref(), &stored);
+            self.events.borrow_mut().extend(events.iter().cloned());
+            Ok((stored, events))
+        }
+
+        fn save(&self, _operation: &PokemonOperation, events: &[PokemonEvent]) -> Result<()> {
+            self.events.borrow_mut().extend(events.iter().cloned());
+            Ok(())
+        }
+    }
+
+    fn command(number: i32) -> PokemonUpsertCommand {
+        PokemonUpsertCommand::new(number, "TestName".to_string(), vec!["Water".to_string()])
+    }
+
+    #[test]
+    fn handle_ok_created() {
+        let service = PokemonUpsertService::new(MockPokemonRepositoryImpl::new());
+        let result = service.handle(command(2)).unwrap();
+        assert!(matches!(result, PokemonUpserted::Created(_)));
+    }
+
+    #[test]
+    fn handle_ok_replaced() {
+        let service = PokemonUpsertService::new(MockPokemonRepositoryImpl::new());
+        let result = service.handle(command(1)).unwrap();
+        match result {
+            PokemonUpserted::Replaced(data) => {
+                assert_eq!(data.get_name(), "TestName");
+                assert_eq!(*data.get_version(), 2);
+            }
+            _ => panic!("expected Replaced"),
+        }
+    }
+
+    #[test]
+    fn handle_ok_expected_version() {
+        let service = PokemonUpsertService::new(MockPokemonRepositoryImpl::new());
+        let mut command = command(1);
+        command.set_expected_version(Some(1));
+        let result = service.handle(command).unwrap();
+        assert!(matches!(result, PokemonUpserted::Replaced(_)));
+    }
+
+    #[test]
+    fn handle_ok_events() {
+        let recorder = RecordingSubscriber::default();
+        let mut event_bus = PokemonEventBus::default();
+        event_bus.subscribe(recorder.clone());
+        let service =
+            PokemonUpsertService::new(MockPokemonRepositoryImpl::new()).with_event_bus(event_bus);
+        service.handle(command(2)).unwrap();
+        let mut command = command(1);
+        command.set_expected_version(Some(1));
+        service.handle(command).unwrap();
+
+        let before =
+            Pokemon::try_new(1, "TestPokemon".to_string(), vec!["Fire".to_string()]).unwrap();
+        let after = Pokemon::try_new(1, "TestName".to_string(), vec!["Water".to_string()]).unwrap();
+        let mut expected = vec![PokemonEvent::registered(
+            &Pokemon::try_new(2, "TestName".to_string(), vec!["Water".to_string()]).unwrap(),
+        )];
+        expected.extend(PokemonEvent::updated(&before, &after));
+        // 登録・置き換えと同じトランザクションで記録され、成功した後に配信される
+        assert_eq!(*service.pokemon_repository.events.borrow(), expected);
+        assert_eq!(*recorder.0.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn handle_ng_version_mismatch() {
+        let service = PokemonUpsertService::new(MockPokemonRepositoryImpl::new());
+        let mut command = command(1);
+        command.set_expected_version(Some(2));
+        let result = service.handle(command);
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<PokemonError>(),
+            Some(PokemonError::VersionMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn handle_ng_invalid_types() {
+        let service = PokemonUpsertService::new(MockPokemonRepositoryImpl::new());
+        let command = PokemonUpsertCommand::new(1, "TestName".to_string(), vec![]);
+        let result = service.handle(command);
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<PokemonError>(),
+            Some(PokemonError::InvalidValue(_))
+        ));
+    }
+}
@@ -0,0 +1,223 @@
+//! ポケモンの変更を検出するためのアプリケーションサービス。
+//! 前回の状態と現在の一覧を比較し、登録・更新・削除されたポケモンを求めるユースケースの振る舞いを定義する。
+
+use super::pokemon_data::PokemonData;
+use crate::domain::models::pokemon::pokemon_repository::PokemonRepository;
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+/// アプリケーションサービスの構造体。
+/// generics でリポジトリへの依存を表し、trait 境界を定義することで、DI を行う。
+pub struct PokemonWatchService<T>
+where
+    T: PokemonRepository,
+{
+    pokemon_repository: T,
+}
+
+/// 前回検出した時点のポケモンの一覧
+#[derive(Default, Debug)]
+pub struct PokemonSnapshot(Option<BTreeMap<i32, PokemonData>>);
+
+impl PokemonSnapshot {
+    /// 通知された変更を一覧に反映し、一覧が変わったかどうかを返す。
+    /// 既に反映済みの変更（同じ版以前の登録・更新や、存在しないポケモンの削除）では false を返す。
+    pub fn apply(&mut self, change: &PokemonChange) -> bool {
+        let current = match &mut self.0 {
+            Some(current) => current,
+            None => return true,
+        };
+        match change {
+            PokemonChange::Created(pokemon) | PokemonChange::Updated(pokemon) => {
+                let number = *pokemon.get_number();
+                if let Some(before) = current.get(&number) {
+                    if before.get_version() >= pokemon.get_version() {
+                        return false;
+                    }
+                }
+                current.insert(number, pokemon.clone());
+                true
+            }
+            PokemonChange::Deleted(number) => current.remove(number).is_some(),
+        }
+    }
+}
+
+/// ポケモンの変更
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum PokemonChange {
+    Created(PokemonData),
+    Updated(PokemonData),
+    /// 削除された図鑑 No
+    Deleted(i32),
+}
+
+impl<T: PokemonRepository> PokemonWatchService<T> {
+    /// コンストラクタ
+    pub fn new(pokemon_repository: T) -> Self {
+        Self { pokemon_repository }
+    }
+
+    /// 変更の検出処理の実行。
+    /// `snapshot` と現在の一覧を比較して図鑑 No 順に変更を返し、`snapshot` を現在の一覧に置き換える。
+    /// `snapshot` が未記録の場合は現在の一覧を記録するだけで、変更は返さない。
+    /// 1 件も登録されていない場合も空の一覧として記録するため、最初の登録を検出できる。
+    pub fn handle(&self, snapshot: &mut PokemonSnapshot) -> Result<Vec<PokemonChange>> {
+        let current = self
+            .pokemon_repository
+            .list()?
+            .into_iter()
+            .map(|pokemon| {
+                let data = PokemonData::new(pokemon);
+                (*data.get_number(), data)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let changes = match &snapshot.0 {
+            None => vec![],
+            Some(previous) => {
+                let mut changes = current
+                    .iter()
+                    .filter_map(|(number, pokemon)| match previous.get(number) {
+                        None => Some(PokemonChange::Created(pokemon.clone())),
+                        Some(before) if before != pokemon => {
+                            Some(PokemonChange::Updated(pokemon.clone()))
+                        }
+                        Some(_) => None,
+                    })
+                    .collect::<Vec<_>>();
+                changes.extend(
+                    previous
+                        .keys()
+                        .filter(|number| !current.contains_key(number))
+                        .map(|number| PokemonChange::Deleted(*number)),
+                );
+                changes.sort_by_key(|change| match change {
+                    PokemonChange::Created(pokemon) | PokemonChange::Updated(pokemon) => {
+                        *pokemon.get_number()
+                    }
+                    PokemonChange::Deleted(number) => *number,
+                });
+                changes
+            }
+        };
+        snapshot.0 = Some(current);
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::pokemon::pokemon::Pokemon;
+    use crate::domain::models::pokemon::pokemon_repository::tests::MockPokemonRepository;
+    use std::cell::RefCell;
+
+    /// テストのためのモックリポジトリ。一覧の内容をテストから書き換えられる。
+    pub struct MockPokemonRepositoryImpl {
+        pokemon: RefCell<Vec<Pokemon>>,
+    }
+
+    impl MockPokemonRepositoryImpl {
+        fn new(pokemon: Vec<Pokemon>) -> Self {
+            MockPokemonRepositoryImpl {
+                pokemon: RefCell::new(pokemon),
+            }
+        }
+    }
+
+    /// モックリポジトリの振る舞い
+    impl MockPokemonRepository for MockPokemonRepositoryImpl {
+        fn list(&self) -> Result<Vec<crate::domain::models::pokemon::pokemon::Pokemon>> {
+            Ok(self.pokemon.borrow().clone())
+        }
+    }
+
+    fn pokemon(number: i32, name: &str) -> Pokemon {
+        Pokemon::try_new(number, name.to_string(), vec!["Fire".to_string()]).unwrap()
+    }
+
+    #[test]
+    fn handle_ok_first_snapshot() {
+        let repository = MockPokemonRepositoryImpl::new(vec![pokemon(1, "TestPokemon")]);
+        let service = PokemonWatchService::new(repository);
+        let mut snapshot = PokemonSnapshot::default();
+        assert!(service.handle(&mut snapshot).unwrap().is_empty());
+        assert!(service.handle(&mut snapshot).unwrap().is_empty());
+    }
+
+    #[test]
+    fn handle_ok_changes() {
+        let repository =
+            MockPokemonRepositoryImpl::new(vec![pokemon(1, "TestPokemon"), pokemon(2, "TestName")]);
+        let service = PokemonWatchService::new(repository);
+        let mut snapshot = PokemonSnapshot::default();
+        service.handle(&mut snapshot).unwrap();
+
+        let mut updated = pokemon(2, "Renamed");
+        updated.version = 2;
+        *service.pokemon_repository.pokemon.borrow_mut() = vec![updated.clone(), pokemon(3, "New")];
+        let changes = service.handle(&mut snapshot).unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                PokemonChange::Deleted(1),
+                PokemonChange::Updated(PokemonData::new(updated)),
+                PokemonChange::Created(PokemonData::new(pokemon(3, "New"))),
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_ok_from_empty() {
+        let repository = MockPokemonRepositoryImpl::new(vec![]);
+        let service = PokemonWatchService::new(repository);
+        let mut snapshot = PokemonSnapshot::default();
+        assert!(service.handle(&mut snapshot).unwrap().is_empty());
+
+        *service.pokemon_repository.pokemon.borrow_mut() = vec![pokemon(1, "TestPokemon")];
+        assert_eq!(
+            service.handle(&mut snapshot).unwrap(),
+            vec![PokemonChange::Created(PokemonData::new(pokemon(
+                1,
+                "TestPokemon"
+            )))]
+        );
+    }
+
+    #[test]
+    fn handle_ok_to_empty() {
+        let repository = MockPokemonRepositoryImpl::new(vec![pokemon(1, "TestPokemon")]);
+        let service = PokemonWatchService::new(repository);
+        let mut snapshot = PokemonSnapshot::default();
+        service.handle(&mut snapshot).unwrap();
+
+        service.pokemon_repository.pokemon.borrow_mut().clear();
+        assert_eq!(
+            service.handle(&mut snapshot).unwrap(),
+            vec![PokemonChange::Deleted(1)]
+        );
+        assert!(service.handle(&mut snapshot).unwrap().is_empty());
+    }
+
+    #[test]
+    fn apply_ok() {
+        let repository = MockPokemonRepositoryImpl::new(vec![pokemon(1, "TestPokemon")]);
+        let service = PokemonWatchService::new(repository);
+        let mut snapshot = PokemonSnapshot::default();
+        service.handle(&mut snapshot).unwrap();
+
+        let mut updated = pokemon(1, "Renamed");
+        updated.version = 2;
+        let updated = PokemonChange::Updated(PokemonData::new(updated));
+        assert!(snapshot.apply(&updated));
+        // 反映済みの変更は無視する
+        assert!(!snapshot.apply(&updated));
+        assert!(snapshot.apply(&PokemonChange::Deleted(1)));
+        assert!(!snapshot.apply(&PokemonChange::Deleted(1)));
+
+        // 反映した変更は、次の比較で再び検出しない
+        *service.pokemon_repository.pokemon.borrow_mut() = vec![];
+        assert!(service.handle(&mut snapshot).unwrap().is_empty());
+    }
+}
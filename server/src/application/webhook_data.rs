@@ -0,0 +1,67 @@
+//! Webhook のドメインオブジェクトのための DTO
+
+use crate::domain::models::webhook::webhook::{Webhook, WebhookDelivery};
+use chrono::{DateTime, Utc};
+use getset::Getters;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Webhook の情報。シークレットは含めない。
+#[derive(Serialize, Clone, Getters, PartialEq, Eq, Debug, ToSchema)]
+pub struct WebhookData {
+    #[getset(get = "pub with_prefix")]
+    id: i32,
+    #[getset(get = "pub with_prefix")]
+    url: String,
+    #[getset(get = "pub with_prefix")]
+    events: Vec<String>,
+    #[getset(get = "pub with_prefix")]
+    created_at: DateTime<Utc>,
+}
+
+impl WebhookData {
+    pub fn new(source: Webhook) -> Self {
+        Self {
+            id: source.id,
+            url: source.url,
+            events: source.events.into_iter().map(String::from).collect(),
+            created_at: source.created_at,
+        }
+    }
+}
+
+/// 送信の 1 回分の記録
+#[derive(Serialize, Clone, Getters, PartialEq, Eq, Debug, ToSchema)]
+pub struct WebhookDeliveryData {
+    #[getset(get = "pub with_prefix")]
+    id: i32,
+    #[getset(get = "pub with_prefix")]
+    delivery_id: String,
+    #[getset(get = "pub with_prefix")]
+    event: String,
+    #[getset(get = "pub with_prefix")]
+    attempt: i32,
+    #[getset(get = "pub with_prefix")]
+    status_code: Option<i32>,
+    #[getset(get = "pub with_prefix")]
+    error: Option<String>,
+    #[getset(get = "pub with_prefix")]
+    succeeded: bool,
+    #[getset(get = "pub with_prefix")]
+    attempted_at: DateTime<Utc>,
+}
+
+impl WebhookDeliveryData {
+    pub fn new(source: WebhookDelivery) -> Self {
+        Self {
+            id: source.id,
+            delivery_id: source.delivery_id,
+            event: String::from(source.event),
+            attempt: source.attempt,
+            status_code: source.status_code,
+            error: source.error,
+            succeeded: source.succeeded,
+            attempted_at: source.attempted_at,
+        }
+    }
+}
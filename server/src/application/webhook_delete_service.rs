@@ -0,0 +1,28 @@
+//! Webhook 削除処理のためのアプリケーションサービス。
+//! 削除処理のユースケースの振る舞いを定義する。
+
+use crate::domain::models::webhook::webhook_repository::WebhookRepository;
+use anyhow::Result;
+
+/// アプリケーションサービスの構造体。
+/// generics でリポジトリへの依存を表し、trait 境界を定義することで、DI を行う。
+pub struct WebhookDeleteService<T>
+where
+    T: WebhookRepository,
+{
+    webhook_repository: T,
+}
+
+/// アプリケーションサービスの振る舞いを定義。
+impl<T: WebhookRepository> WebhookDeleteService<T> {
+    /// コンストラクタ
+    pub fn new(webhook_repository: T) -> Self {
+        Self { webhook_repository }
+    }
+
+    /// 削除処理の実行。対象の Webhook が存在しない場合は false を返す。
+    /// 以降の通知は行わず、送信の記録も削除する。
+    pub fn handle(&self, id: i32) -> Result<bool> {
+        self.webhook_repository.delete(id)
+    }
+}
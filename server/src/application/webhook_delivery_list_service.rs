@@ -0,0 +1,43 @@
+//! Webhook の送信の記録を取得するためのアプリケーションサービス。
+//! 送信の記録の一覧取得のユースケースの振る舞いを定義する。
+
+use super::webhook_data::WebhookDeliveryData;
+use crate::domain::models::webhook::webhook_repository::WebhookRepository;
+use anyhow::Result;
+
+/// 返す送信の記録の最大件数
+const DELIVERY_LIMIT: i64 = 100;
+
+/// アプリケーションサービスの構造体。
+/// generics でリポジトリへの依存を表し、trait 境界を定義することで、DI を行う。
+pub struct WebhookDeliveryListService<T>
+where
+    T: WebhookRepository,
+{
+    webhook_repository: T,
+}
+
+/// アプリケーションサービスの振る舞いを定義。
+impl<T: WebhookRepository> WebhookDeliveryListService<T> {
+    /// コンストラクタ
+    pub fn new(webhook_repository: T) -> Self {
+        Self { webhook_repository }
+    }
+
+    /// 一覧取得処理の実行。直近の送信の記録を新しい順に返す。
+    /// 対象の Webhook が存在しない場合は None を返す。
+    pub fn handle(&self, webhook_id: i32) -> Result<Option<Vec<WebhookDeliveryData>>> {
+        if self.webhook_repository.find_by_id(webhook_id)?.is_none() {
+            return Ok(None);
+        }
+        let deliveries = self
+            .webhook_repository
+            .list_deliveries(webhook_id, DELIVERY_LIMIT)?;
+        Ok(Some(
+            deliveries
+                .into_iter()
+                .map(WebhookDeliveryData::new)
+                .collect(),
+        ))
+    }
+}
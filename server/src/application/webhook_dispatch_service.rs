@@ -0,0 +1,334 @@
+//! Webhook による通知のためのアプリケーションサービス。
+//! アウトボックスのドメインイベントから Webhook ごとの通知を作ってキューに入れ、送信の結果を記録するユースケースの振る舞いを定義する。
+
+use super::pokemon_watch_service::PokemonChange;
+use crate::domain::models::outbox::outbox::OutboxMessage;
+use crate::domain::models::webhook::{
+    webhook::{NewWebhookDelivery, NewWebhookJob, Webhook, WebhookEvent, WebhookJob},
+    webhook_repository::WebhookRepository,
+};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde_json::json;
+use std::cell::RefCell;
+
+/// アプリケーションサービスの構造体。
+/// generics でリポジトリへの依存を表し、trait 境界を定義することで、DI を行う。
+pub struct WebhookDispatchService<T>
+where
+    T: WebhookRepository,
+{
+    webhook_repository: T,
+}
+
+/// アプリケーションサービスの振る舞いを定義。
+impl<T: WebhookRepository> WebhookDispatchService<T> {
+    /// コンストラクタ
+    pub fn new(webhook_repository: T) -> Self {
+        Self { webhook_repository }
+    }
+
+    /// 通知を作る処理の実行。アウトボックスのイベントを最大 limit 件取り出し、
+    /// 変更の種類を購読している Webhook ごとの通知をキューに入れて、取り出したイベントの件数を返す。
+    /// `change_of` はイベントを通知する変更に読み替えるもので、通知しないイベントには None を返す。
+    /// 名前とタイプを同時に変更した場合など、続けて同じ変更に読み替えたものは 1 回の通知にまとめる。
+    pub fn handle(
+        &self,
+        limit: i64,
+        change_of: impl Fn(&OutboxMessage) -> Result<Option<PokemonChange>>,
+    ) -> Result<usize> {
+        let webhooks = self.webhook_repository.list()?;
+        let last = RefCell::new(None);
+        self.webhook_repository.enqueue(limit, &|message| {
+            let change = match change_of(message)? {
+                Some(change) if last.borrow().as_ref() != Some(&change) => change,
+                _ => return Ok(vec![]),
+            };
+            let jobs = jobs(&webhooks, &change, message.created_at);
+            *last.borrow_mut() = Some(change);
+            Ok(jobs)
+        })
+    }
+
+    /// 送信の時刻になった通知を最大 limit 件取り出す。取り出した通知は locked_until まで他から取り出されない。
+    pub fn claim(&self, limit: i64, locked_until: DateTime<Utc>) -> Result<Vec<WebhookJob>> {
+        self.webhook_repository.claim(limit, locked_until)
+    }
+
+    /// 送信の結果を記録する。
+    /// retry_at を指定した場合はその時刻に再送し、指定しない場合は通知をキューから除く。
+    pub fn record(
+        &self,
+        job: &WebhookJob,
+        delivery: &NewWebhookDelivery,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        self.webhook_repository.insert_delivery(delivery)?;
+        match retry_at {
+            Some(retry_at) => self.webhook_repository.retry(job.id, retry_at),
+            None => self.webhook_repository.complete(job.id),
+        }
+    }
+}
+
+/// 変更の種類を購読している Webhook ごとに通知を作る
+fn jobs(
+    webhooks: &[Webhook],
+    change: &PokemonChange,
+    occurred_at: DateTime<Utc>,
+) -> Vec<NewWebhookJob> {
+    let (event, data) = match change {
+        PokemonChange::Created(pokemon) | PokemonChange::Updated(pokemon) => (
+            if matches!(change, PokemonChange::Created(_)) {
+                WebhookEvent::Created
+            } else {
+                WebhookEvent::Updated
+            },
+            json!({
+                "number": pokemon.get_number(),
+                "name": pokemon.get_name(),
+                "types": pokemon.get_types(),
+                "version": pokemon.get_version(),
+                "updated_at": pokemon.get_updated_at(),
+            }),
+        ),
+        PokemonChange::Deleted(number) => (WebhookEvent::Deleted, json!({ "number": number })),
+    };
+    webhooks
+        .iter()
+        .filter(|webhook| webhook.subscribes(event))
+        .map(|webhook| {
+            let delivery_id = delivery_id();
+            let body = json!({
+                "id": delivery_id,
+                "event": String::from(event),
+                "occurred_at": occurred_at,
+                "data": data,
+            })
+            .to_string();
+            NewWebhookJob {
+                webhook_id: webhook.id,
+                delivery_id,
+                event,
+                body,
+            }
+        })
+        .collect()
+}
+
+/// 通知ごとに一意な ID を生成する
+fn delivery_id() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::pokemon_data::PokemonData;
+    use crate::domain::models::pokemon::pokemon::Pokemon;
+    use crate::domain::models::webhook::webhook::{NewWebhook, WebhookDelivery};
+
+    /// テストのためのモックリポジトリ。
+    /// アウトボックスのイベントを保持し、キューに入れた通知と、送信後の扱い（再送の時刻）を記録する。
+    pub struct MockWebhookRepositoryImpl {
+        messages: Vec<OutboxMessage>,
+        jobs: RefCell<Vec<NewWebhookJob>>,
+        scheduled: RefCell<Vec<(i64, Option<DateTime<Utc>>)>>,
+    }
+
+    impl MockWebhookRepositoryImpl {
+        fn new(event_types: &[&str]) -> Self {
+            let messages = event_types
+                .iter()
+                .enumerate()
+                .map(|(i, event_type)| OutboxMessage {
+                    id: i as i64 + 1,
+                    event_type: event_type.to_string(),
+                    aggregate_id: 1,
+                    payload: "{}".to_string(),
+                    created_at: Utc::now(),
+                    attempts: 0,
+                })
+                .collect();
+            MockWebhookRepositoryImpl {
+                messages,
+                jobs: RefCell::new(vec![]),
+                scheduled: RefCell::new(vec![]),
+            }
+        }
+    }
+
+    /// モックリポジトリの振る舞い
+    impl WebhookRepository for MockWebhookRepositoryImpl {
+        fn find_by_id(&self, _id: i32) -> Result<Option<Webhook>> {
+            unimplemented!();
+        }
+
+        fn list(&self) -> Result<Vec<Webhook>> {
+            let webhook = |id: i32, events: Vec<WebhookEvent>| Webhook {
+                id,
+                url: format!("http://localhost/hooks/{}", id),
+                secret: "whsec_test".to_string(),
+                events,
+                created_at: Utc::now(),
+            };
+            Ok(vec![
+                webhook(1, vec![]),
+                webhook(2, vec![WebhookEvent::Deleted]),
+            ])
+        }
+
+        fn insert(&self, _webhook: &NewWebhook) -> Result<Webhook> {
+            unimplemented!();
+        }
+
+        fn delete(&self, _id: i32) -> Result<bool> {
+            unimplemented!();
+        }
+
+        fn insert_delivery(&self, delivery: &NewWebhookDelivery) -> Result<WebhookDelivery> {
+            Ok(WebhookDelivery {
+                id: 1,
+                webhook_id: delivery.webhook_id,
+                delivery_id: delivery.delivery_id.clone(),
+                event: delivery.event,
+                attempt: delivery.attempt,
+                status_code: delivery.status_code,
+                error: delivery.error.clone(),
+                succeeded: delivery.succeeded,
+                attempted_at: Utc::now(),
+            })
+        }
+
+        fn list_deliveries(&self, _webhook_id: i32, _limit: i64) -> Result<Vec<WebhookDelivery>> {
+            unimplemented!();
+        }
+
+        fn enqueue(
+            &self,
+            limit: i64,
+            jobs: &dyn Fn(&OutboxMessage) -> Result<Vec<NewWebhookJob>>,
+        ) -> Result<usize> {
+            let messages = self
+                .messages
+                .iter()
+                .take(limit as usize)
+                .collect::<Vec<_>>();
+            for message in &messages {
+                self.jobs.borrow_mut().extend(jobs(message)?);
+            }
+            Ok(messages.len())
+        }
+
+        fn claim(&self, _limit: i64, _locked_until: DateTime<Utc>) -> Result<Vec<WebhookJob>> {
+            unimplemented!();
+        }
+
+        fn complete(&self, id: i64) -> Result<()> {
+            self.scheduled.borrow_mut().push((id, None));
+            Ok(())
+        }
+
+        fn retry(&self, id: i64, next_attempt_at: DateTime<Utc>) -> Result<()> {
+            self.scheduled
+                .borrow_mut()
+                .push((id, Some(next_attempt_at)));
+            Ok(())
+        }
+    }
+
+    fn pokemon(name: &str) -> PokemonData {
+        PokemonData::new(Pokemon::try_new(1, name.to_string(), vec!["Fire".to_string()]).unwrap())
+    }
+
+    #[test]
+    fn handle_ok_filtered() {
+        let service =
+            WebhookDispatchService::new(MockWebhookRepositoryImpl::new(&["pokemon.registered"]));
+        let count = service
+            .handle(10, |_| {
+                Ok(Some(PokemonChange::Created(pokemon("TestPokemon"))))
+            })
+            .unwrap();
+        assert_eq!(count, 1);
+
+        let jobs = service.webhook_repository.jobs.borrow();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].webhook_id, 1);
+        let body: serde_json::Value = serde_json::from_str(&jobs[0].body).unwrap();
+        assert_eq!(body["event"], "pokemon.created");
+        assert_eq!(body["id"], jobs[0].delivery_id.as_str());
+        assert_eq!(body["data"]["name"], "TestPokemon");
+        // 通知の時刻は送信時ではなく、イベントを記録した時刻とする
+        assert_eq!(
+            body["occurred_at"],
+            json!(service.webhook_repository.messages[0].created_at)
+        );
+    }
+
+    #[test]
+    fn handle_ok_deleted() {
+        let service =
+            WebhookDispatchService::new(MockWebhookRepositoryImpl::new(&["pokemon.deleted"]));
+        service
+            .handle(10, |_| Ok(Some(PokemonChange::Deleted(1))))
+            .unwrap();
+        let jobs = service.webhook_repository.jobs.borrow();
+        assert_eq!(jobs.len(), 2);
+        assert_ne!(jobs[0].delivery_id, jobs[1].delivery_id);
+        let body: serde_json::Value = serde_json::from_str(&jobs[1].body).unwrap();
+        assert_eq!(body["data"], json!({ "number": 1 }));
+    }
+
+    #[test]
+    fn handle_ok_merges_same_change() {
+        let service = WebhookDispatchService::new(MockWebhookRepositoryImpl::new(&[
+            "pokemon.renamed",
+            "pokemon.retyped",
+            "pokemon.unknown",
+        ]));
+        let count = service
+            .handle(10, |message| match message.event_type.as_str() {
+                "pokemon.unknown" => Ok(None),
+                _ => Ok(Some(PokemonChange::Updated(pokemon("TestName")))),
+            })
+            .unwrap();
+        // 通知しないイベントも取り出したものとして数える
+        assert_eq!(count, 3);
+        assert_eq!(service.webhook_repository.jobs.borrow().len(), 1);
+    }
+
+    #[test]
+    fn record_ok() {
+        let service = WebhookDispatchService::new(MockWebhookRepositoryImpl::new(&[]));
+        let job = WebhookJob {
+            id: 7,
+            webhook_id: 1,
+            url: "http://localhost/hooks/1".to_string(),
+            secret: "whsec_test".to_string(),
+            delivery_id: "d1".to_string(),
+            event: WebhookEvent::Deleted,
+            body: r#"{"number":1}"#.to_string(),
+            attempt: 0,
+        };
+        let delivery = NewWebhookDelivery {
+            webhook_id: 1,
+            delivery_id: "d1".to_string(),
+            event: WebhookEvent::Deleted,
+            attempt: 1,
+            status_code: Some(500),
+            error: None,
+            succeeded: false,
+        };
+        let retry_at = Utc::now();
+        service.record(&job, &delivery, Some(retry_at)).unwrap();
+        service.record(&job, &delivery, None).unwrap();
+        assert_eq!(
+            *service.webhook_repository.scheduled.borrow(),
+            vec![(7, Some(retry_at)), (7, None)]
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! Webhook 登録処理のためのアプリケーションサービス。
+//! 登録処理のユースケースの振る舞いを定義する。
+
+use super::webhook_data::WebhookData;
+use crate::domain::models::webhook::{
+    webhook::{generate_secret, NewWebhook, WebhookEvent},
+    webhook_repository::WebhookRepository,
+};
+use anyhow::Result;
+use getset::Getters;
+use serde::Serialize;
+use std::convert::TryFrom;
+use utoipa::ToSchema;
+
+/// アプリケーションサービスの構造体。
+/// generics でリポジトリへの依存を表し、trait 境界を定義することで、DI を行う。
+pub struct WebhookRegisterService<T>
+where
+    T: WebhookRepository,
+{
+    webhook_repository: T,
+}
+
+/// アプリケーションサービスの振る舞いを定義。
+impl<T: WebhookRepository> WebhookRegisterService<T> {
+    /// コンストラクタ
+    pub fn new(webhook_repository: T) -> Self {
+        Self { webhook_repository }
+    }
+
+    /// 登録処理の実行。署名用のシークレットを生成し、登録結果とともに返すのはこの時だけとする。
+    pub fn handle(&self, command: WebhookRegisterCommand) -> Result<RegisteredWebhook> {
+        let url = command.get_url().trim();
+        let host = url
+            .strip_prefix("https://")
+            .or_else(|| url.strip_prefix("http://"))
+            .unwrap_or_default();
+        if host.is_empty() || host.starts_with('/') {
+            return Err(anyhow::anyhow!("不正な URL です: {}", url));
+        }
+        let mut events = vec![];
+        for event in command.get_events().iter() {
+            match WebhookEvent::try_from(event.clone()) {
+                Ok(event) if !events.contains(&event) => events.push(event),
+                Ok(_) => {}
+                Err(_) => return Err(anyhow::anyhow!("不正なイベントです: {}", event)),
+            }
+        }
+
+        let secret = generate_secret();
+        let webhook = self.webhook_repository.insert(&NewWebhook {
+            url: url.to_string(),
+            secret: secret.clone(),
+            events,
+        })?;
+        Ok(RegisteredWebhook {
+            secret,
+            webhook: WebhookData::new(webhook),
+        })
+    }
+}
+
+/// Webhook 登録のコマンドオブジェクト
+#[derive(Getters)]
+pub struct WebhookRegisterCommand {
+    #[getset(get = "pub with_prefix")]
+    url: String,
+    /// 通知するイベントの種類。空の場合は全てのイベントを通知する。
+    #[getset(get = "pub with_prefix")]
+    events: Vec<String>,
+}
+
+/// Webhook 登録のコマンドオブジェクトの振る舞いを定義
+impl WebhookRegisterCommand {
+    /// コンストラクタ
+    pub fn new(url: String, events: Vec<String>) -> Self {
+        Self { url, events }
+    }
+}
+
+/// 登録した Webhook。署名用のシークレットを含む。
+#[derive(Serialize, Debug, ToSchema)]
+pub struct RegisteredWebhook {
+    pub secret: String,
+    #[serde(flatten)]
+    pub webhook: WebhookData,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::webhook::webhook::{NewWebhookDelivery, Webhook, WebhookDelivery};
+    use chrono::Utc;
+
+    /// テストのためのモックリポジトリ
+    pub struct MockWebhookRepositoryImpl {}
+
+    impl MockWebhookRepositoryImpl {
+        fn new() -> Self {
+            MockWebhookRepositoryImpl {}
+        }
+    }
+
+    /// モックリポジトリの振る舞い
+    impl WebhookRepository for MockWebhookRepositoryImpl {
+        fn find_by_id(&self, _id: i32) -> Result<Option<Webhook>> {
+            unimplemented!();
+        }
+
+        fn list(&self) -> Result<Vec<Webhook>> {
+            unimplemented!();
+        }
+
+        fn insert(&self, webhook: &NewWebhook) -> Result<Webhook> {
+            Ok(Webhook {
+                id: 1,
+                url: webhook.url.clone(),
+                secret: webhook.secret.clone(),
+                events: webhook.events.clone(),
+                created_at: Utc::now(),
+            })
+        }
+
+        fn delete(&self, _id: i32) -> Result<bool> {
+            unimplemented!();
+        }
+
+        fn insert_delivery(&self, _delivery: &NewWebhookDelivery) -> Result<WebhookDelivery> {
+            unimplemented!();
+        }
+
+        fn list_deliveries(&self, _webhook_id: i32, _limit: i64) -> Result<Vec<WebhookDelivery>> {
+            unimplemented!();
+        }
+
+        fn enqueue(
+            &self,
+            _limit: i64,
+            _jobs: &dyn Fn(
+                &crate::domain::models::outbox::outbox::OutboxMessage,
+            )
+                -> Result<Vec<crate::domain::models::webhook::webhook::NewWebhookJob>>,
+        ) -> Result<usize> {
+            unimplemented!();
+        }
+
+        fn claim(
+            &self,
+            _limit: i64,
+            _locked_until: chrono::DateTime<Utc>,
+        ) -> Result<Vec<crate::domain::models::webhook::webhook::WebhookJob>> {
+            unimplemented!();
+        }
+
+        fn complete(&self, _id: i64) -> Result<()> {
+            unimplemented!();
+        }
+
+        fn retry(&self, _id: i64, _next_attempt_at: chrono::DateTime<Utc>) -> Result<()> {
+            unimplemented!();
+        }
+    }
+
+    #[test]
+    fn handle_ok() {
+        let service = WebhookRegisterService::new(MockWebhookRepositoryImpl::new());
+        let command = WebhookRegisterCommand::new(
+            "https://example.com/hooks/pokemon".to_string(),
+            vec!["pokemon.created".to_string(), "pokemon.created".to_string()],
+        );
+        let registered = service.handle(command).unwrap();
+        assert!(registered.secret.starts_with("whsec_"));
+        assert_eq!(
+            registered.webhook.get_events(),
+            &vec!["pokemon.created".to_string()]
+        );
+    }
+
+    #[test]
+    fn handle_ng_invalid_url() {
+        let service = WebhookRegisterService::new(MockWebhookRepositoryImpl::new());
+        for url in ["ftp://example.com", "https://", "example.com"] {
+            let command = WebhookRegisterCommand::new(url.to_string(), vec![]);
+            assert!(service.handle(command).is_err());
+        }
+    }
+
+    #[test]
+    fn handle_ng_invalid_event() {
+        let service = WebhookRegisterService::new(MockWebhookRepositoryImpl::new());
+        let command = WebhookRegisterCommand::new(
+            "https://example.com".to_string(),
+            vec!["pokemon.renamed".to_string()],
+        );
+        assert!(service.handle(command).is_err());
+    }
+}
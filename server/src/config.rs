@@ -9,6 +9,207 @@ pub struct Config {
     pub server_address: String,
     pub server_port: u16,
     pub database_url: String,
+    /// シャットダウン時に処理中のリクエストの完了を待つ最大秒数
+    #[serde(default = "default_shutdown_timeout")]
+    pub shutdown_timeout: u64,
+    /// シグナル受信後、readiness を失敗にしてから新規接続の受付を止めるまでの待機秒数
+    #[serde(default)]
+    pub shutdown_drain_delay: u64,
+    /// アクセストークン検証用の JWKS の URL
+    pub auth_jwks_url: Option<String>,
+    /// アクセストークン検証用の JWKS のファイルパス（オフラインでのテスト用）
+    pub auth_jwks_path: Option<String>,
+    /// アクセストークンの発行者 (iss)
+    pub auth_issuer: Option<String>,
+    /// アクセストークンの対象者 (aud)
+    pub auth_audience: Option<String>,
+    /// 役割の一覧を格納するアクセストークンのカスタムクレーム名
+    pub auth_roles_claim: Option<String>,
+    /// 参照系のリクエストを認証なしで許可するかどうか
+    #[serde(default = "default_auth_public_read")]
+    pub auth_public_read: bool,
+    /// レート制限を有効にするかどうか。
+    /// プロキシ配下では全てのクライアントが同じ IP アドレスになるため、RATE_LIMIT_TRUST_PROXY とあわせて設定する。
+    #[serde(default)]
+    pub rate_limit_enabled: bool,
+    /// ルートごとの指定がない場合の制限値（`<回数>/<秒数>`）
+    #[serde(default = "default_rate_limit_default")]
+    pub rate_limit_default: String,
+    /// ルートごとの制限値（`<METHOD|*> <パターン>=<回数>/<秒数>` のカンマ区切り）
+    #[serde(default)]
+    pub rate_limit_routes: String,
+    /// プロキシが付与する X-Forwarded-For などからクライアントの IP アドレスを求めるかどうか
+    #[serde(default)]
+    pub rate_limit_trust_proxy: bool,
+    /// プロセス内に保持するバケット数の上限
+    #[serde(default = "default_rate_limit_max_buckets")]
+    pub rate_limit_max_buckets: usize,
+    /// PUT / PATCH / DELETE で If-Match ヘッダーを必須にするかどうか
+    #[serde(default)]
+    pub require_if_match: bool,
+    /// 参照系のレスポンスに付与する Cache-Control ヘッダーの値。
+    /// 未設定の場合は AUTH_PUBLIC_READ に応じて決め、空文字の場合は付与しない。
+    pub cache_control: Option<String>,
+    /// DELETE で物理削除の代わりに論理削除（deleted_at の記録）を行うかどうか
+    #[serde(default)]
+    pub soft_delete: bool,
+    /// 存在しない図鑑 No の DELETE を 404 ではなく 204 とするかどうか
+    #[serde(default)]
+    pub delete_idempotent: bool,
+    /// CORS で許可するオリジン（カンマ区切り。`*` で全て許可）
+    #[serde(default)]
+    pub cors_allowed_origins: String,
+    /// CORS で許可するメソッド（カンマ区切り）
+    #[serde(default = "default_cors_allowed_methods")]
+    pub cors_allowed_methods: String,
+    /// CORS で許可するリクエストヘッダー（カンマ区切り）
+    #[serde(default = "default_cors_allowed_headers")]
+    pub cors_allowed_headers: String,
+    /// CORS でブラウザに公開するレスポンスヘッダー（カンマ区切り）
+    #[serde(default = "default_cors_exposed_headers")]
+    pub cors_exposed_headers: String,
+    /// Cookie などの認証情報付きリクエストを許可するかどうか
+    #[serde(default)]
+    pub cors_allow_credentials: bool,
+    /// プリフライトの結果をキャッシュしてよい秒数
+    #[serde(default = "default_cors_max_age")]
+    pub cors_max_age: usize,
+    /// `/v1` とバージョンのないパスを廃止予定とした日時（RFC 3339）。空の場合は Deprecation ヘッダーを付与しない。
+    #[serde(default)]
+    pub api_v1_deprecated_at: String,
+    /// `/v1` とバージョンのないパスの提供を終了する予定日時（RFC 3339）。空の場合は Sunset ヘッダーを付与しない。
+    #[serde(default)]
+    pub api_v1_sunset: String,
+    /// gRPC サーバーのポート
+    #[serde(default = "default_grpc_port")]
+    pub grpc_port: u16,
+    /// SSE の再開用にメモリ上に保持するイベントの件数
+    #[serde(default = "default_sse_event_log_size")]
+    pub sse_event_log_size: usize,
+    /// SSE で接続を維持するためのコメントを送る間隔（秒）
+    #[serde(default = "default_sse_heartbeat_interval")]
+    pub sse_heartbeat_interval: u64,
+    /// WebSocket で Ping を送る間隔（秒）。2 回分の間隔の間に応答がなければ切断する。
+    #[serde(default = "default_ws_heartbeat_interval")]
+    pub ws_heartbeat_interval: u64,
+    /// WebSocket の接続ごとに送信待ちとして保持する最大メッセージ数
+    #[serde(default = "default_ws_send_buffer")]
+    pub ws_send_buffer: usize,
+    /// Webhook の初回を含めた最大の送信回数
+    #[serde(default = "default_webhook_max_attempts")]
+    pub webhook_max_attempts: u32,
+    /// Webhook の 1 回目の再送までの待ち時間（秒）。以降は再送のたびに 2 倍にする。
+    #[serde(default = "default_webhook_retry_base_delay")]
+    pub webhook_retry_base_delay: u64,
+    /// Webhook の 1 回の送信のタイムアウト（秒）
+    #[serde(default = "default_webhook_timeout")]
+    pub webhook_timeout: u64,
+    /// Webhook の通知を作るイベントと、送信の時刻になった通知を確認する間隔（秒）
+    #[serde(default = "default_webhook_poll_interval")]
+    pub webhook_poll_interval: u64,
+    /// アウトボックスの配信先（log, file, http）
+    #[serde(default = "default_outbox_sink")]
+    pub outbox_sink: String,
+    /// 配信先が file の場合に追記するファイルのパス
+    #[serde(default = "default_outbox_file_path")]
+    pub outbox_file_path: String,
+    /// 配信先が http の場合に POST する URL
+    #[serde(default)]
+    pub outbox_http_url: String,
+    /// 未配信のメッセージを確認する間隔（秒）
+    #[serde(default = "default_outbox_poll_interval")]
+    pub outbox_poll_interval: u64,
+    /// 一度に取り出して配信するメッセージの最大数
+    #[serde(default = "default_outbox_batch_size")]
+    pub outbox_batch_size: i64,
+}
+
+fn default_shutdown_timeout() -> u64 {
+    30
+}
+
+fn default_auth_public_read() -> bool {
+    true
+}
+
+fn default_rate_limit_default() -> String {
+    String::from("120/60")
+}
+
+fn default_rate_limit_max_buckets() -> usize {
+    10_000
+}
+
+fn default_cors_allowed_methods() -> String {
+    String::from("GET,POST,PUT,PATCH,DELETE")
+}
+
+fn default_cors_allowed_headers() -> String {
+    String::from(
+        "Authorization,Content-Type,Accept,If-Match,If-None-Match,If-Modified-Since,Last-Event-ID",
+    )
+}
+
+fn default_cors_exposed_headers() -> String {
+    String::from(
+        "ETag,Location,RateLimit-Limit,RateLimit-Remaining,RateLimit-Reset,Retry-After,Deprecation,Sunset,Link",
+    )
+}
+
+fn default_cors_max_age() -> usize {
+    3600
+}
+
+fn default_grpc_port() -> u16 {
+    50051
+}
+
+fn default_sse_event_log_size() -> usize {
+    1000
+}
+
+fn default_sse_heartbeat_interval() -> u64 {
+    15
+}
+
+fn default_ws_heartbeat_interval() -> u64 {
+    15
+}
+
+fn default_ws_send_buffer() -> usize {
+    64
+}
+
+fn default_webhook_max_attempts() -> u32 {
+    5
+}
+
+fn default_webhook_retry_base_delay() -> u64 {
+    5
+}
+
+fn default_webhook_timeout() -> u64 {
+    10
+}
+
+fn default_webhook_poll_interval() -> u64 {
+    1
+}
+
+fn default_outbox_sink() -> String {
+    "log".to_string()
+}
+
+fn default_outbox_file_path() -> String {
+    "outbox.ndjson".to_string()
+}
+
+fn default_outbox_poll_interval() -> u64 {
+    1
+}
+
+fn default_outbox_batch_size() -> i64 {
+    100
 }
 
 impl Config {
@@ -0,0 +1,103 @@
+//! 機械クライアント向けの API キーのエンティティの定義
+
+use crate::domain::models::role::permission::Permission;
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// 発行する API キーの接頭辞
+const API_KEY_PREFIX: &str = "pk_";
+
+/// API キー。平文のキーは保持せず、ハッシュ値のみを永続化する。
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ApiKey {
+    pub id: i32,
+    pub name: String,
+    pub key_hash: String,
+    pub scopes: Vec<Permission>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKey {
+    /// 指定した時刻に利用可能かどうか。失効済み、または有効期限切れの場合は利用できない。
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none() && self.expires_at.is_none_or(|expires_at| now < expires_at)
+    }
+}
+
+/// 永続化前の API キー
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct NewApiKey {
+    pub name: String,
+    pub key_hash: String,
+    pub scopes: Vec<Permission>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// 推測できない平文の API キーを生成する。
+pub fn generate_api_key() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("{}{}", API_KEY_PREFIX, hex::encode(bytes))
+}
+
+/// 平文の API キーを保存用のハッシュ値に変換する。
+/// キー自体が十分なエントロピーを持つため、ソルトなしの SHA-256 で照合できるようにしている。
+pub fn hash_api_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn api_key() -> ApiKey {
+        ApiKey {
+            id: 1,
+            name: "batch".to_string(),
+            key_hash: hash_api_key("pk_test"),
+            scopes: vec![Permission::PokemonWrite],
+            expires_at: None,
+            last_used_at: None,
+            created_at: Utc::now(),
+            revoked_at: None,
+        }
+    }
+
+    #[test]
+    fn generate_api_key_unique() {
+        let key1 = generate_api_key();
+        let key2 = generate_api_key();
+        assert!(key1.starts_with(API_KEY_PREFIX));
+        assert_ne!(key1, key2);
+    }
+
+    #[test]
+    fn hash_api_key_stable() {
+        assert_eq!(hash_api_key("pk_test"), hash_api_key("pk_test"));
+        assert_ne!(hash_api_key("pk_test"), hash_api_key("pk_test2"));
+    }
+
+    #[test]
+    fn is_active_ok() {
+        assert!(api_key().is_active(Utc::now()));
+    }
+
+    #[test]
+    fn is_active_ng_expired() {
+        let mut key = api_key();
+        key.expires_at = Some(Utc::now() - Duration::hours(1));
+        assert!(!key.is_active(Utc::now()));
+    }
+
+    #[test]
+    fn is_active_ng_revoked() {
+        let mut key = api_key();
+        key.revoked_at = Some(Utc::now());
+        assert!(!key.is_active(Utc::now()));
+    }
+}
@@ -0,0 +1,23 @@
+//! API キーに関するリポジトリを定義する。
+
+use crate::domain::models::api_key::api_key::{ApiKey, NewApiKey};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+/// ApiKey のリポジトリインタフェース
+pub trait ApiKeyRepository {
+    /// ハッシュ値から API キーを探す
+    fn find_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>>;
+
+    /// ID から API キーを探す
+    fn find_by_id(&self, id: i32) -> Result<Option<ApiKey>>;
+
+    /// API キーを永続化（保存）し、採番された API キーを返す
+    fn insert(&self, api_key: &NewApiKey) -> Result<ApiKey>;
+
+    /// API キーを失効させる
+    fn revoke(&self, id: i32, revoked_at: DateTime<Utc>) -> Result<()>;
+
+    /// 最終利用日時を記録する
+    fn touch(&self, id: i32, used_at: DateTime<Utc>) -> Result<()>;
+}
@@ -0,0 +1,3 @@
+#[allow(clippy::module_inception)]
+pub mod api_key;
+pub mod api_key_repository;
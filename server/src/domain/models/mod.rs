@@ -1 +1,5 @@
+pub mod api_key;
+pub mod outbox;
 pub mod pokemon;
+pub mod role;
+pub mod webhook;
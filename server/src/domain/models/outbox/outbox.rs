@@ -0,0 +1,116 @@
+//! 送信待ちのドメインイベント（アウトボックス）のエンティティの定義。
+//! ドメインイベントはポケモンの変更と同じトランザクションで記録し、後から外部へ配信する。
+
+use crate::domain::models::pokemon::pokemon_event::PokemonEvent;
+use chrono::{DateTime, Utc};
+use serde_json::json;
+
+/// アウトボックスに記録したドメインイベント
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct OutboxMessage {
+    /// 記録順に採番される ID。配信先での重複の排除にも使う。
+    pub id: i64,
+    /// イベントの種類（pokemon.registered など）
+    pub event_type: String,
+    /// 対象のポケモンの図鑑 No
+    pub aggregate_id: i32,
+    /// イベントの内容（JSON）
+    pub payload: String,
+    pub created_at: DateTime<Utc>,
+    /// これまでに配信を試みて失敗した回数
+    pub attempts: i32,
+}
+
+/// 永続化前のアウトボックスのメッセージ
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct NewOutboxMessage {
+    pub event_type: String,
+    pub aggregate_id: i32,
+    pub payload: String,
+}
+
+/// ドメインイベントからアウトボックスのメッセージへの変換処理
+impl From<&PokemonEvent> for NewOutboxMessage {
+    fn from(event: &PokemonEvent) -> Self {
+        let number: i32 = event.number().clone().into();
+        let (event_type, payload) = match event {
+            PokemonEvent::Registered(e) => (
+                "pokemon.registered",
+                json!({
+                    "number": number,
+                    "name": String::from(e.pokemon.name.clone()),
+                    "types": Vec::<String>::from(e.pokemon.types.clone()),
+                }),
+            ),
+            PokemonEvent::Renamed(e) => (
+                "pokemon.renamed",
+                json!({
+                    "number": number,
+                    "from": String::from(e.from.clone()),
+                    "to": String::from(e.to.clone()),
+                }),
+            ),
+            PokemonEvent::Retyped(e) => (
+                "pokemon.retyped",
+                json!({
+                    "number": number,
+                    "from": Vec::<String>::from(e.from.clone()),
+                    "to": Vec::<String>::from(e.to.clone()),
+                }),
+            ),
+            PokemonEvent::Deleted(e) => (
+                "pokemon.deleted",
+                json!({ "number": number, "soft": e.soft }),
+            ),
+        };
+        Self {
+            event_type: event_type.to_string(),
+            aggregate_id: number,
+            payload: payload.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::pokemon::{
+        pokemon::Pokemon,
+        pokemon_event::{PokemonDeleted, PokemonRenamed},
+        pokemon_name::PokemonName,
+        pokemon_number::PokemonNumber,
+    };
+    use std::convert::TryFrom;
+
+    #[test]
+    fn from_event_ok() {
+        let pokemon =
+            Pokemon::try_new(25, "TestPokemon".to_string(), vec!["Electric".to_string()]).unwrap();
+        let message = NewOutboxMessage::from(&PokemonEvent::registered(&pokemon));
+        assert_eq!(message.event_type, "pokemon.registered");
+        assert_eq!(message.aggregate_id, 25);
+        assert_eq!(
+            message.payload,
+            r#"{"name":"TestPokemon","number":25,"types":["Electric"]}"#
+        );
+
+        let message = NewOutboxMessage::from(&PokemonEvent::Renamed(PokemonRenamed {
+            from: PokemonName::try_from("TestPokemon".to_string()).unwrap(),
+            to: PokemonName::try_from("TestName".to_string()).unwrap(),
+            pokemon: Pokemon::try_new(25, "TestName".to_string(), vec!["Electric".to_string()])
+                .unwrap(),
+        }));
+        assert_eq!(message.event_type, "pokemon.renamed");
+        assert_eq!(
+            message.payload,
+            r#"{"from":"TestPokemon","number":25,"to":"TestName"}"#
+        );
+
+        let message = NewOutboxMessage::from(&PokemonEvent::Deleted(PokemonDeleted {
+            number: PokemonNumber::try_from(25).unwrap(),
+            soft: true,
+        }));
+        assert_eq!(message.event_type, "pokemon.deleted");
+        assert_eq!(message.payload, r#"{"number":25,"soft":true}"#);
+    }
+}
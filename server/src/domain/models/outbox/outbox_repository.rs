@@ -0,0 +1,22 @@
+//! アウトボックスに関するリポジトリを定義する。
+//! メッセージの記録はポケモンの変更と同じトランザクションで行うため、`PokemonRepository::save` が担う。
+
+use crate::domain::models::outbox::outbox::OutboxMessage;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+/// アウトボックスのリポジトリインタフェース
+pub trait OutboxRepository {
+    /// 未配信のメッセージを記録順に最大 limit 件取り出し、locked_until まで他から取り出せないようにする。
+    /// 記録順を保つため、先頭の未配信のメッセージを他が取り出している間は何も返さない。
+    fn claim(&self, limit: i64, locked_until: DateTime<Utc>) -> Result<Vec<OutboxMessage>>;
+
+    /// メッセージを配信済みにする
+    fn mark_sent(&self, id: i64) -> Result<()>;
+
+    /// 配信に失敗した回数を 1 つ進め、エラーの内容を記録する
+    fn mark_failed(&self, id: i64, error: &str) -> Result<()>;
+
+    /// 取り出したまま配信しなかったメッセージを、他から取り出せるように戻す
+    fn release(&self, ids: &[i64]) -> Result<()>;
+}
@@ -1,6 +1,11 @@
+#[allow(clippy::module_inception)]
 pub mod pokemon;
+pub mod pokemon_error;
+pub mod pokemon_event;
+pub mod pokemon_event_bus;
 pub mod pokemon_name;
 pub mod pokemon_number;
+pub mod pokemon_query;
 pub mod pokemon_repository;
 pub mod pokemon_type;
 pub mod pokemon_types;
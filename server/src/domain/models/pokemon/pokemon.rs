@@ -1,14 +1,21 @@
 //! ポケモンのエンティティの定義
 
 use crate::domain::models::pokemon::{
-    pokemon_name::PokemonName, pokemon_number::PokemonNumber, pokemon_types::PokemonTypes,
+    pokemon_error::PokemonError, pokemon_name::PokemonName, pokemon_number::PokemonNumber,
+    pokemon_types::PokemonTypes,
 };
+use chrono::{DateTime, Utc};
+use std::convert::TryFrom;
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Pokemon {
     pub number: PokemonNumber,
     pub name: PokemonName,
     pub types: PokemonTypes,
+    /// 楽観的排他制御のための版。更新のたびに 1 つ進む。
+    pub version: i32,
+    /// 最終更新日時。永続化されていない場合は None。
+    pub updated_at: Option<DateTime<Utc>>,
 }
 
 impl Pokemon {
@@ -17,6 +24,37 @@ impl Pokemon {
             number,
             name,
             types,
+            version: 1,
+            updated_at: None,
         }
     }
+
+    /// 図鑑 No・名前・タイプの値を検証してエンティティを作成する。
+    pub fn try_new(number: i32, name: String, types: Vec<String>) -> Result<Self, PokemonError> {
+        let number = PokemonNumber::try_from(number).map_err(|_| {
+            PokemonError::InvalidValue(format!("不正な図鑑 No です: no {}", number))
+        })?;
+        let name = PokemonName::try_from(name.clone())
+            .map_err(|_| PokemonError::InvalidValue(format!("不正な名前です: {:?}", name)))?;
+        let types = PokemonTypes::try_from(types.clone())
+            .map_err(|_| PokemonError::InvalidValue(format!("不正なタイプです: {:?}", types)))?;
+        Ok(Self::new(number, name, types))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pokemon_try_new_ok() {
+        let result = Pokemon::try_new(1, "TestPokemon".to_string(), vec!["Fire".to_string()]);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn pokemon_try_new_ng() {
+        let result = Pokemon::try_new(1, "TestPokemon".to_string(), vec!["Hoge".to_string()]);
+        assert!(matches!(result, Err(PokemonError::InvalidValue(_))));
+    }
 }
@@ -0,0 +1,46 @@
+//! ポケモンの操作で発生するエラーの定義。
+//! アプリケーションサービスからは anyhow のエラーとして返すため、呼び出し側で downcast して判別する。
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PokemonError {
+    /// 図鑑 No・名前・タイプのいずれかが不正
+    InvalidValue(String),
+    /// 対象の図鑑 No のポケモンが存在しない
+    NotFound(i32),
+    /// 登録しようとした図鑑 No が既に存在する
+    AlreadyExists(i32),
+    /// 更新しようとした時点の版が、保存されている版と一致しない
+    VersionMismatch { number: i32, expected: i32 },
+    /// 登録しようとした図鑑 No のポケモンが論理削除されている。復元してから更新する必要がある。
+    Deleted(i32),
+}
+
+impl fmt::Display for PokemonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PokemonError::InvalidValue(message) => write!(f, "{}", message),
+            PokemonError::NotFound(number) => {
+                write!(f, "ポケモンが存在しません: no {}", number)
+            }
+            PokemonError::AlreadyExists(number) => write!(
+                f,
+                "作成しようとしたポケモンが既に存在しています: no {}",
+                number
+            ),
+            PokemonError::VersionMismatch { number, expected } => write!(
+                f,
+                "ポケモンが他の更新で変更されています: no {}, version {}",
+                number, expected
+            ),
+            PokemonError::Deleted(number) => write!(
+                f,
+                "ポケモンは論理削除されています。復元してから更新してください: no {}",
+                number
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PokemonError {}
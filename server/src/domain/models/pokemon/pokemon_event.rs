@@ -0,0 +1,148 @@
+//! ポケモンのドメインイベントの定義。
+//! アプリケーションサービスが登録・更新・削除に成功した際に、何が起きたかを表す。
+
+use crate::domain::models::pokemon::{
+    pokemon::Pokemon, pokemon_name::PokemonName, pokemon_number::PokemonNumber,
+    pokemon_types::PokemonTypes,
+};
+
+/// ポケモンが登録された
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PokemonRegistered {
+    /// 登録したポケモン
+    pub pokemon: Pokemon,
+}
+
+/// ポケモンの名前が変更された
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PokemonRenamed {
+    pub from: PokemonName,
+    pub to: PokemonName,
+    /// 変更後のポケモン。版は保存後のもの
+    pub pokemon: Pokemon,
+}
+
+/// ポケモンのタイプが変更された
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PokemonRetyped {
+    pub from: PokemonTypes,
+    pub to: PokemonTypes,
+    /// 変更後のポケモン。版は保存後のもの
+    pub pokemon: Pokemon,
+}
+
+/// ポケモンが削除された
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PokemonDeleted {
+    pub number: PokemonNumber,
+    /// 論理削除かどうか
+    pub soft: bool,
+}
+
+/// ポケモンのドメインイベント
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum PokemonEvent {
+    Registered(PokemonRegistered),
+    Renamed(PokemonRenamed),
+    Retyped(PokemonRetyped),
+    Deleted(PokemonDeleted),
+}
+
+impl PokemonEvent {
+    /// 登録されたポケモンのイベント
+    pub fn registered(pokemon: &Pokemon) -> Self {
+        Self::Registered(PokemonRegistered {
+            pokemon: pokemon.clone(),
+        })
+    }
+
+    /// 更新前後のポケモンを比較し、変更された項目ごとのイベントを返す。変更がなければ空。
+    /// 更新すると版が 1 つ進むため、イベントが持つ変更後のポケモンは更新前の次の版とする。
+    pub fn updated(before: &Pokemon, after: &Pokemon) -> Vec<Self> {
+        let mut saved = after.clone();
+        saved.version = before.version + 1;
+        let mut events = vec![];
+        if before.name != after.name {
+            events.push(Self::Renamed(PokemonRenamed {
+                from: before.name.clone(),
+                to: after.name.clone(),
+                pokemon: saved.clone(),
+            }));
+        }
+        if before.types != after.types {
+            events.push(Self::Retyped(PokemonRetyped {
+                from: before.types.clone(),
+                to: after.types.clone(),
+                pokemon: saved,
+            }));
+        }
+        events
+    }
+
+    /// 登録または置き換えのイベントを返す。
+    /// 置き換え前（`before`）がなければ登録、あれば変更された項目ごとのイベントとする。
+    pub fn upserted(before: Option<&Pokemon>, after: &Pokemon) -> Vec<Self> {
+        match before {
+            Some(before) => Self::updated(before, after),
+            None => vec![Self::registered(after)],
+        }
+    }
+
+    /// 対象のポケモンの図鑑 No
+    pub fn number(&self) -> &PokemonNumber {
+        match self {
+            Self::Registered(e) => &e.pokemon.number,
+            Self::Renamed(e) => &e.pokemon.number,
+            Self::Retyped(e) => &e.pokemon.number,
+            Self::Deleted(e) => &e.number,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn updated_ok() {
+        let before =
+            Pokemon::try_new(1, "TestPokemon".to_string(), vec!["Fire".to_string()]).unwrap();
+        let mut after = before.clone();
+        assert!(PokemonEvent::updated(&before, &after).is_empty());
+
+        after.name = PokemonName::try_from("TestName".to_string()).unwrap();
+        let events = PokemonEvent::updated(&before, &after);
+        let mut saved = after.clone();
+        saved.version = before.version + 1;
+        assert_eq!(
+            events,
+            vec![PokemonEvent::Renamed(PokemonRenamed {
+                from: before.name.clone(),
+                to: after.name.clone(),
+                pokemon: saved,
+            })]
+        );
+
+        after.types = PokemonTypes::try_from(vec!["Water".to_string()]).unwrap();
+        let events = PokemonEvent::updated(&before, &after);
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[1], PokemonEvent::Retyped(_)));
+    }
+
+    #[test]
+    fn upserted_ok() {
+        let before =
+            Pokemon::try_new(1, "TestPokemon".to_string(), vec!["Fire".to_string()]).unwrap();
+        assert_eq!(
+            PokemonEvent::upserted(None, &before),
+            vec![PokemonEvent::registered(&before)]
+        );
+        let mut after = before.clone();
+        after.name = PokemonName::try_from("TestName".to_string()).unwrap();
+        assert_eq!(
+            PokemonEvent::upserted(Some(&before), &after),
+            PokemonEvent::updated(&before, &after)
+        );
+    }
+}
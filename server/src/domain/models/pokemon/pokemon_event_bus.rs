@@ -0,0 +1,87 @@
+//! ポケモンのドメインイベントをプロセス内で配信するイベントバスの定義。
+//! 監査やキャッシュ、通知などの処理は購読者として登録し、各アプリケーションサービスを変更せずに追加する。
+
+use crate::domain::models::pokemon::pokemon_event::PokemonEvent;
+use anyhow::Result;
+use std::sync::Arc;
+
+/// ドメインイベントの購読者の振る舞い
+pub trait PokemonEventSubscriber: Send + Sync {
+    /// イベントの処理。変更は完了しているため、失敗してもユースケースの結果は変わらない。
+    fn handle(&self, event: &PokemonEvent) -> Result<()>;
+
+    /// 1 回の変更で起きたイベントをまとめて処理する。既定では 1 件ずつ `handle` に渡す。
+    /// 名前とタイプを同時に変更した場合など、1 回の変更を 1 つとして扱う購読者が上書きする。
+    fn handle_all(&self, events: &[PokemonEvent]) -> Result<()> {
+        events.iter().try_for_each(|event| self.handle(event))
+    }
+}
+
+/// プロセス内のイベントバス。購読者は起動時に登録し、全ワーカーで共有する。
+#[derive(Clone, Default)]
+pub struct PokemonEventBus {
+    subscribers: Vec<Arc<dyn PokemonEventSubscriber>>,
+}
+
+impl PokemonEventBus {
+    /// 購読者を登録する
+    pub fn subscribe(&mut self, subscriber: impl PokemonEventSubscriber + 'static) {
+        self.subscribers.push(Arc::new(subscriber));
+    }
+
+    /// 1 回の変更で起きたイベントを、登録順に全ての購読者へまとめて配信する。
+    /// ある購読者が失敗しても、残りの購読者への配信は続ける。
+    pub fn publish(&self, events: &[PokemonEvent]) {
+        if events.is_empty() {
+            return;
+        }
+        for subscriber in &self.subscribers {
+            if let Err(e) = subscriber.handle_all(events) {
+                log::error!("Pokemon event subscriber failed: {:?}: {:?}", events, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::domain::models::pokemon::pokemon_event::PokemonDeleted;
+    use crate::domain::models::pokemon::pokemon_number::PokemonNumber;
+    use std::convert::TryFrom;
+    use std::sync::Mutex;
+
+    /// 受け取ったイベントを記録するテストのための購読者
+    #[derive(Clone, Default)]
+    pub struct RecordingSubscriber(pub Arc<Mutex<Vec<PokemonEvent>>>);
+
+    impl PokemonEventSubscriber for RecordingSubscriber {
+        fn handle(&self, event: &PokemonEvent) -> Result<()> {
+            self.0.lock().unwrap().push(event.clone());
+            Ok(())
+        }
+    }
+
+    struct FailingSubscriber;
+
+    impl PokemonEventSubscriber for FailingSubscriber {
+        fn handle(&self, _event: &PokemonEvent) -> Result<()> {
+            Err(anyhow::anyhow!("Dummy Error"))
+        }
+    }
+
+    #[test]
+    fn publish_continues_after_failure() {
+        let recorder = RecordingSubscriber::default();
+        let mut bus = PokemonEventBus::default();
+        bus.subscribe(FailingSubscriber);
+        bus.subscribe(recorder.clone());
+
+        let event = PokemonEvent::Deleted(PokemonDeleted {
+            number: PokemonNumber::try_from(1).unwrap(),
+            soft: false,
+        });
+        bus.publish(std::slice::from_ref(&event));
+        assert_eq!(*recorder.0.lock().unwrap(), vec![event]);
+    }
+}
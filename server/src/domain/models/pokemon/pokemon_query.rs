@@ -0,0 +1,19 @@
+//! ポケモンの一覧の絞り込み条件の定義。
+//! リポジトリは条件をデータベースのクエリに変換し、一致するものだけを読み込む。
+
+use crate::domain::models::pokemon::pokemon_type::PokemonType;
+
+/// ポケモンの一覧の絞り込み条件。指定した条件を全て満たすものを図鑑 No 順に返す。
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct PokemonQuery {
+    /// いずれかのタイプを持つ。空の場合は絞り込まない
+    pub any_types: Vec<PokemonType>,
+    /// このタイプが弱点である
+    pub weak_to: Option<PokemonType>,
+    /// 名前にこの文字列を含む（大文字・小文字を区別しない）
+    pub name_contains: Option<String>,
+    /// 図鑑 No がこの値以上
+    pub number_from: Option<i32>,
+    /// 図鑑 No がこの値以下
+    pub number_to: Option<i32>,
+}
@@ -1,30 +1,203 @@
 //! ポケモンに関するドメインサービスを定義する。
 
-use crate::domain::models::pokemon::{pokemon::Pokemon, pokemon_number::PokemonNumber};
+use crate::domain::models::pokemon::{
+    pokemon::Pokemon, pokemon_event::PokemonEvent, pokemon_number::PokemonNumber,
+    pokemon_query::PokemonQuery,
+};
 use anyhow::Result;
 
+/// まとめて永続化するための操作。
+/// 削除の操作は前提とする版を持ち、指定した場合は保存されている版と一致するときだけ削除する。
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum PokemonOperation {
+    Insert(Pokemon),
+    Update(Pokemon),
+    Upsert(Pokemon),
+    Delete(PokemonNumber, Option<i32>),
+    SoftDelete(PokemonNumber, Option<i32>),
+}
+
 /// Pokemon のリポジトリインタフェース
 pub trait PokemonRepository {
-    /// 番号からポケモンを探す
+    /// 番号からポケモンを探す。論理削除されたものは含めない。
     fn find_by_number(&self, number: &PokemonNumber) -> Result<Pokemon>;
 
-    /// ポケモン一覧を表示する
+    /// ポケモン一覧を表示する。論理削除されたものは含めない。
+    /// 1 件も登録されていない場合は空の一覧を返す。
     fn list(&self) -> Result<Vec<Pokemon>>;
 
-    /// オブジェクトを永続化（保存）する振る舞い
-    fn insert(&self, pokemon: &Pokemon) -> Result<()>;
+    /// 条件に一致するポケモンを図鑑 No 順に返す。論理削除されたものは含めない。
+    fn search(&self, query: &PokemonQuery) -> Result<Vec<Pokemon>>;
+
+    /// 条件に一致するポケモンを図鑑 No 順に offset 件飛ばして最大 limit 件返す。
+    /// 条件に一致した件数も合わせて返す。論理削除されたものは含めない。
+    fn search_page(
+        &self,
+        query: &PokemonQuery,
+        offset: i64,
+        limit: i64,
+    ) -> Result<(Vec<Pokemon>, i64)>;
+
+    /// オブジェクトを永続化（登録または置き換え）する振る舞い。
+    /// 同じ図鑑 No の論理削除されたものがあれば、上書きせずに `PokemonError::Deleted` を返す。
+    /// 既に存在する場合は版を 1 つ進めて置き換える。
+    /// 置き換え前（新しく登録した場合は None）と保存後の状態を `events` に渡してドメインイベントを求め、
+    /// 同じトランザクションでアウトボックスに記録する。保存後の状態と記録したイベントを返す。
+    fn upsert(
+        &self,
+        pokemon: &Pokemon,
+        events: &dyn Fn(Option<&Pokemon>, &Pokemon) -> Vec<PokemonEvent>,
+    ) -> Result<(Pokemon, Vec<PokemonEvent>)>;
+
+    /// 論理削除したオブジェクトを元に戻す振る舞い。
+    /// 元に戻した状態を `events` に渡してドメインイベントを求め、同じトランザクションでアウトボックスに記録する。
+    /// 論理削除されたものが存在しない場合は None を返す。
+    fn restore(
+        &self,
+        number: &PokemonNumber,
+        events: &dyn Fn(&Pokemon) -> Vec<PokemonEvent>,
+    ) -> Result<Option<(Pokemon, Vec<PokemonEvent>)>>;
+
+    /// 複数の操作をまとめて永続化する振る舞い。操作ごとの結果を返す。
+    /// 操作ごとのドメインイベントは、その操作と同じトランザクションでアウトボックスに記録する。
+    /// `atomic` が true の場合は 1 つのトランザクションで実行し、失敗した操作までの結果を返して全て取り消す。
+    /// false の場合は操作ごとに確定する。
+    fn batch(
+        &self,
+        operations: &[(PokemonOperation, Vec<PokemonEvent>)],
+        atomic: bool,
+    ) -> Result<Vec<Result<()>>>;
 
-    /// オブジェクトを再構築する振る舞い
-    fn update(&self, pokemon: &Pokemon) -> Result<()>;
+    /// 操作と、その結果として起きたドメインイベントを 1 つのトランザクションで永続化する振る舞い。
+    /// イベントは送信待ちとしてアウトボックスに記録し、操作が取り消された場合は記録も取り消す。
+    /// 削除の操作で対象が存在しない場合は `PokemonError::NotFound` を、
+    /// 前提とする版が保存されている版と異なる場合は `PokemonError::VersionMismatch` を返す。
+    fn save(&self, operation: &PokemonOperation, events: &[PokemonEvent]) -> Result<()>;
 
-    /// オブジェクトを永続化（破棄）する振る舞い
-    fn delete(&self, number: &PokemonNumber) -> Result<()>;
+    /// ポケモンの一覧を図鑑 No 順に 1 件ずつ読み込み、`f` に渡す振る舞い。
+    /// 一覧をまとめて読み込まないため、件数の多い出力に使う。`f` がエラーを返した場合は読み込みを中断する。
+    fn scan(&self, f: &mut dyn FnMut(Pokemon) -> Result<()>) -> Result<()>;
 
     /// 作成したポケモンの重複確認を行う。
     fn exists(&self, pokemon: &Pokemon) -> bool {
-        match self.find_by_number(&pokemon.number) {
-            Ok(_) => true,
-            Err(_) => false,
+        self.find_by_number(&pokemon.number).is_ok()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// テストのためのモックリポジトリの雛形。
+    /// 実装した型はそのまま PokemonRepository として使えるため、テストで呼び出す振る舞いだけを実装すればよい。
+    /// 実装していない振る舞いを呼び出した場合は panic する。
+    pub trait MockPokemonRepository {
+        fn find_by_number(&self, _number: &PokemonNumber) -> Result<Pokemon> {
+            unimplemented!();
+        }
+
+        fn list(&self) -> Result<Vec<Pokemon>> {
+            unimplemented!();
+        }
+
+        fn search(&self, _query: &PokemonQuery) -> Result<Vec<Pokemon>> {
+            unimplemented!();
+        }
+
+        fn search_page(
+            &self,
+            _query: &PokemonQuery,
+            _offset: i64,
+            _limit: i64,
+        ) -> Result<(Vec<Pokemon>, i64)> {
+            unimplemented!();
+        }
+
+        fn upsert(
+            &self,
+            _pokemon: &Pokemon,
+            _events: &dyn Fn(Option<&Pokemon>, &Pokemon) -> Vec<PokemonEvent>,
+        ) -> Result<(Pokemon, Vec<PokemonEvent>)> {
+            unimplemented!();
+        }
+
+        fn restore(
+            &self,
+            _number: &PokemonNumber,
+            _events: &dyn Fn(&Pokemon) -> Vec<PokemonEvent>,
+        ) -> Result<Option<(Pokemon, Vec<PokemonEvent>)>> {
+            unimplemented!();
+        }
+
+        fn batch(
+            &self,
+            _operations: &[(PokemonOperation, Vec<PokemonEvent>)],
+            _atomic: bool,
+        ) -> Result<Vec<Result<()>>> {
+            unimplemented!();
+        }
+
+        fn save(&self, _operation: &PokemonOperation, _events: &[PokemonEvent]) -> Result<()> {
+            unimplemented!();
+        }
+
+        fn scan(&self, _f: &mut dyn FnMut(Pokemon) -> Result<()>) -> Result<()> {
+            unimplemented!();
+        }
+    }
+
+    impl<T: MockPokemonRepository> PokemonRepository for T {
+        fn find_by_number(&self, number: &PokemonNumber) -> Result<Pokemon> {
+            MockPokemonRepository::find_by_number(self, number)
+        }
+
+        fn list(&self) -> Result<Vec<Pokemon>> {
+            MockPokemonRepository::list(self)
+        }
+
+        fn search(&self, query: &PokemonQuery) -> Result<Vec<Pokemon>> {
+            MockPokemonRepository::search(self, query)
+        }
+
+        fn search_page(
+            &self,
+            query: &PokemonQuery,
+            offset: i64,
+            limit: i64,
+        ) -> Result<(Vec<Pokemon>, i64)> {
+            MockPokemonRepository::search_page(self, query, offset, limit)
+        }
+
+        fn upsert(
+            &self,
+            pokemon: &Pokemon,
+            events: &dyn Fn(Option<&Pokemon>, &Pokemon) -> Vec<PokemonEvent>,
+        ) -> Result<(Pokemon, Vec<PokemonEvent>)> {
+            MockPokemonRepository::upsert(self, pokemon, events)
+        }
+
+        fn restore(
+            &self,
+            number: &PokemonNumber,
+            events: &dyn Fn(&Pokemon) -> Vec<PokemonEvent>,
+        ) -> Result<Option<(Pokemon, Vec<PokemonEvent>)>> {
+            MockPokemonRepository::restore(self, number, events)
+        }
+
+        fn batch(
+            &self,
+            operations: &[(PokemonOperation, Vec<PokemonEvent>)],
+            atomic: bool,
+        ) -> Result<Vec<Result<()>>> {
+            MockPokemonRepository::batch(self, operations, atomic)
+        }
+
+        fn save(&self, operation: &PokemonOperation, events: &[PokemonEvent]) -> Result<()> {
+            MockPokemonRepository::save(self, operation, events)
+        }
+
+        fn scan(&self, f: &mut dyn FnMut(Pokemon) -> Result<()>) -> Result<()> {
+            MockPokemonRepository::scan(self, f)
         }
     }
 }
@@ -13,6 +13,37 @@ pub enum PokemonType {
     Unknown,  // 不明
 }
 
+impl PokemonType {
+    /// 攻撃に使われるタイプの一覧
+    pub const ALL: [PokemonType; 6] = [
+        PokemonType::Fire,
+        PokemonType::Water,
+        PokemonType::Grass,
+        PokemonType::Electric,
+        PokemonType::Flying,
+        PokemonType::Unknown,
+    ];
+
+    /// このタイプの技で defender のタイプを攻撃した場合のダメージ倍率。
+    /// 定義しているタイプ同士の相性のみを扱い、それ以外は等倍とする。
+    pub fn effectiveness(&self, defender: &PokemonType) -> f32 {
+        use PokemonType::*;
+        match (self, defender) {
+            (Fire, Grass) => 2.0,
+            (Fire, Fire) | (Fire, Water) => 0.5,
+            (Water, Fire) => 2.0,
+            (Water, Water) | (Water, Grass) => 0.5,
+            (Grass, Water) => 2.0,
+            (Grass, Fire) | (Grass, Grass) | (Grass, Flying) => 0.5,
+            (Electric, Water) | (Electric, Flying) => 2.0,
+            (Electric, Grass) | (Electric, Electric) => 0.5,
+            (Flying, Grass) => 2.0,
+            (Flying, Electric) => 0.5,
+            _ => 1.0,
+        }
+    }
+}
+
 /// ポケモンのタイプの振る舞い: 文字列からタイプへの変換。
 /// 指定の文字列以外は NG とする。
 impl TryFrom<String> for PokemonType {
@@ -94,6 +125,13 @@ mod tests {
         assert!(result.eq(&expect));
     }
 
+    #[test]
+    fn pokemon_type_effectiveness() {
+        assert_eq!(PokemonType::Water.effectiveness(&PokemonType::Fire), 2.0);
+        assert_eq!(PokemonType::Fire.effectiveness(&PokemonType::Water), 0.5);
+        assert_eq!(PokemonType::Unknown.effectiveness(&PokemonType::Fire), 1.0);
+    }
+
     #[test]
     fn pokemon_type_try_from_ng() {
         let bad_type = String::from("Hoge");
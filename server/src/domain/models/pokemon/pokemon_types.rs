@@ -7,6 +7,24 @@ use std::convert::TryFrom;
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct PokemonTypes(Vec<PokemonType>);
 
+impl PokemonTypes {
+    /// 弱点となるタイプの一覧。
+    /// 攻撃側のタイプごとに、各タイプに対する倍率を掛け合わせて等倍を超えるものを返す。
+    pub fn weaknesses(&self) -> Vec<PokemonType> {
+        PokemonType::ALL
+            .iter()
+            .filter(|attacker| {
+                self.0
+                    .iter()
+                    .map(|defender| attacker.effectiveness(defender))
+                    .product::<f32>()
+                    > 1.0
+            })
+            .cloned()
+            .collect()
+    }
+}
+
 /// ポケモンの複合タイプの振る舞い：Vec<String> から PokemonTypes への変換。
 /// タイプに定義されていないものは複合タイプに含めない。
 impl TryFrom<Vec<String>> for PokemonTypes {
@@ -54,6 +72,16 @@ mod tests {
         assert!(result.eq(&expect));
     }
 
+    #[test]
+    fn pokemon_types_weaknesses() {
+        let types = PokemonTypes(vec![PokemonType::Water, PokemonType::Flying]);
+        assert_eq!(types.weaknesses(), vec![PokemonType::Electric]);
+
+        // くさはほのおに弱いが、みずとの複合では等倍になる
+        let types = PokemonTypes(vec![PokemonType::Grass, PokemonType::Water]);
+        assert_eq!(types.weaknesses(), vec![PokemonType::Flying]);
+    }
+
     #[test]
     fn pokemon_number_try_from_ng() {
         let bad_type1 = String::from("Hoge");
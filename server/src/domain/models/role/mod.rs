@@ -0,0 +1,4 @@
+pub mod permission;
+#[allow(clippy::module_inception)]
+pub mod role;
+pub mod role_repository;
@@ -0,0 +1,75 @@
+//! API の操作に対する権限を表す値オブジェクト。
+
+use std::convert::TryFrom;
+
+/// 権限を表す。
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum Permission {
+    PokemonRead,   // ポケモンの参照
+    PokemonWrite,  // ポケモンの登録・更新
+    PokemonDelete, // ポケモンの削除
+    Admin,         // 全ての操作
+}
+
+impl Permission {
+    /// この権限を持っていれば required の操作が許可されるかどうか。
+    /// Admin は全ての操作を許可する。
+    pub fn grants(&self, required: Permission) -> bool {
+        *self == Permission::Admin || *self == required
+    }
+}
+
+/// 権限の振る舞い: 文字列から権限への変換。
+/// トークンの `permissions` / `scope` に含まれる文字列を想定し、指定の文字列以外は NG とする。
+impl TryFrom<String> for Permission {
+    type Error = ();
+
+    fn try_from(p: String) -> Result<Self, Self::Error> {
+        match p.as_str() {
+            "pokemon:read" => Ok(Self::PokemonRead),
+            "pokemon:write" => Ok(Self::PokemonWrite),
+            "pokemon:delete" => Ok(Self::PokemonDelete),
+            "admin" => Ok(Self::Admin),
+            _ => Err(()),
+        }
+    }
+}
+
+/// 権限から String への変換処理の振る舞いを定義。
+impl From<Permission> for String {
+    fn from(p: Permission) -> Self {
+        String::from(match p {
+            Permission::PokemonRead => "pokemon:read",
+            Permission::PokemonWrite => "pokemon:write",
+            Permission::PokemonDelete => "pokemon:delete",
+            Permission::Admin => "admin",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permission_try_from_ok() {
+        let result = Permission::try_from(String::from("pokemon:write"));
+        let expect = Ok(Permission::PokemonWrite);
+
+        assert!(result.eq(&expect));
+    }
+
+    #[test]
+    fn permission_try_from_ng() {
+        let result = Permission::try_from(String::from("pokemon:hoge"));
+        let expect = Err(());
+
+        assert!(result.eq(&expect));
+    }
+
+    #[test]
+    fn permission_grants_admin() {
+        assert!(Permission::Admin.grants(Permission::PokemonDelete));
+        assert!(!Permission::PokemonWrite.grants(Permission::PokemonDelete));
+    }
+}
@@ -0,0 +1,89 @@
+//! 利用者の役割を表す値オブジェクト。
+
+use crate::domain::models::role::permission::Permission;
+use std::convert::TryFrom;
+
+/// 利用者の役割を表す。
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Role {
+    Viewer, // 参照のみ
+    Editor, // 登録・更新まで
+    Admin,  // 削除を含む全ての操作
+}
+
+impl Role {
+    /// 役割に付与される権限の一覧
+    pub fn permissions(&self) -> Vec<Permission> {
+        match self {
+            Role::Viewer => vec![Permission::PokemonRead],
+            Role::Editor => vec![Permission::PokemonRead, Permission::PokemonWrite],
+            Role::Admin => vec![Permission::Admin],
+        }
+    }
+}
+
+/// 役割の振る舞い: 文字列から役割への変換。
+/// 指定の文字列以外は NG とする。
+impl TryFrom<String> for Role {
+    type Error = ();
+
+    fn try_from(r: String) -> Result<Self, Self::Error> {
+        match r.as_str() {
+            "viewer" => Ok(Self::Viewer),
+            "editor" => Ok(Self::Editor),
+            "admin" => Ok(Self::Admin),
+            _ => Err(()),
+        }
+    }
+}
+
+/// 役割から String への変換処理の振る舞いを定義。
+impl From<Role> for String {
+    fn from(r: Role) -> Self {
+        String::from(match r {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn role_try_from_ok() {
+        let result = Role::try_from(String::from("editor"));
+        let expect = Ok(Role::Editor);
+
+        assert!(result.eq(&expect));
+    }
+
+    #[test]
+    fn role_try_from_ng() {
+        let result = Role::try_from(String::from("hoge"));
+        let expect = Err(());
+
+        assert!(result.eq(&expect));
+    }
+
+    #[test]
+    fn role_editor_cannot_delete() {
+        let permissions = Role::Editor.permissions();
+        assert!(permissions
+            .iter()
+            .any(|p| p.grants(Permission::PokemonWrite)));
+        assert!(!permissions
+            .iter()
+            .any(|p| p.grants(Permission::PokemonDelete)));
+    }
+
+    #[test]
+    fn role_admin_can_delete() {
+        let permissions = Role::Admin.permissions();
+        assert!(permissions
+            .iter()
+            .any(|p| p.grants(Permission::PokemonDelete)));
+    }
+}
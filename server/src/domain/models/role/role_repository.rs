@@ -0,0 +1,10 @@
+//! 利用者の役割に関するリポジトリを定義する。
+
+use crate::domain::models::role::role::Role;
+use anyhow::Result;
+
+/// Role のリポジトリインタフェース
+pub trait RoleRepository {
+    /// 利用者 (トークンの sub) に割り当てられた役割を探す
+    fn find_by_subject(&self, subject: &str) -> Result<Vec<Role>>;
+}
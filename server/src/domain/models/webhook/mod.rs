@@ -0,0 +1,3 @@
+#[allow(clippy::module_inception)]
+pub mod webhook;
+pub mod webhook_repository;
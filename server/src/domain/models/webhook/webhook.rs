@@ -0,0 +1,176 @@
+//! ポケモンの変更を外部へ通知する Webhook のエンティティの定義
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use std::convert::TryFrom;
+
+/// 生成する署名用シークレットの接頭辞
+const SECRET_PREFIX: &str = "whsec_";
+
+/// Webhook で通知するイベントの種類
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum WebhookEvent {
+    Created, // ポケモンの登録
+    Updated, // ポケモンの更新
+    Deleted, // ポケモンの削除
+}
+
+/// イベントの種類の振る舞い: 文字列からイベントの種類への変換。
+/// 指定の文字列以外は NG とする。
+impl TryFrom<String> for WebhookEvent {
+    type Error = ();
+
+    fn try_from(e: String) -> Result<Self, Self::Error> {
+        match e.as_str() {
+            "pokemon.created" => Ok(Self::Created),
+            "pokemon.updated" => Ok(Self::Updated),
+            "pokemon.deleted" => Ok(Self::Deleted),
+            _ => Err(()),
+        }
+    }
+}
+
+/// イベントの種類から String への変換処理の振る舞いを定義。
+impl From<WebhookEvent> for String {
+    fn from(e: WebhookEvent) -> Self {
+        String::from(match e {
+            WebhookEvent::Created => "pokemon.created",
+            WebhookEvent::Updated => "pokemon.updated",
+            WebhookEvent::Deleted => "pokemon.deleted",
+        })
+    }
+}
+
+/// Webhook。シークレットは署名に使うため平文で保持する。
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Webhook {
+    pub id: i32,
+    pub url: String,
+    pub secret: String,
+    /// 通知するイベントの種類。空の場合は全てのイベントを通知する。
+    pub events: Vec<WebhookEvent>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Webhook {
+    /// 指定したイベントを通知するかどうか
+    pub fn subscribes(&self, event: WebhookEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+}
+
+/// 永続化前の Webhook
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct NewWebhook {
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<WebhookEvent>,
+}
+
+/// Webhook の送信の 1 回分の記録
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub webhook_id: i32,
+    /// 再送を含めて同じ通知であることを示す ID
+    pub delivery_id: String,
+    pub event: WebhookEvent,
+    /// 何回目の送信か（1 始まり）
+    pub attempt: i32,
+    /// 受信側が返したステータスコード。接続できなかった場合は None
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub succeeded: bool,
+    pub attempted_at: DateTime<Utc>,
+}
+
+/// 永続化前の送信の記録
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct NewWebhookDelivery {
+    pub webhook_id: i32,
+    pub delivery_id: String,
+    pub event: WebhookEvent,
+    pub attempt: i32,
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub succeeded: bool,
+}
+
+/// 送信待ちの通知。送信に成功するか再送を諦めるまでキューに保持する。
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct WebhookJob {
+    pub id: i64,
+    pub webhook_id: i32,
+    pub url: String,
+    pub secret: String,
+    /// 再送を含めて同じ通知であることを示す ID
+    pub delivery_id: String,
+    pub event: WebhookEvent,
+    /// 送信する JSON の本文
+    pub body: String,
+    /// これまでに送信した回数
+    pub attempt: i32,
+}
+
+/// 永続化前の送信待ちの通知
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct NewWebhookJob {
+    pub webhook_id: i32,
+    pub delivery_id: String,
+    pub event: WebhookEvent,
+    pub body: String,
+}
+
+/// 推測できない署名用のシークレットを生成する。
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("{}{}", SECRET_PREFIX, hex::encode(bytes))
+}
+
+/// 本文の署名。送信時刻（UNIX 時間）と本文を `.` でつないだ文字列の HMAC-SHA256 を 16 進数で返す。
+/// 時刻を含めることで、受信側は古い通知の再送（リプレイ）を拒否できる。
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subscribes_ok() {
+        let mut webhook = Webhook {
+            id: 1,
+            url: "http://localhost/hook".to_string(),
+            secret: generate_secret(),
+            events: vec![],
+            created_at: Utc::now(),
+        };
+        assert!(webhook.subscribes(WebhookEvent::Deleted));
+        webhook.events = vec![WebhookEvent::Created];
+        assert!(webhook.subscribes(WebhookEvent::Created));
+        assert!(!webhook.subscribes(WebhookEvent::Deleted));
+    }
+
+    #[test]
+    fn sign_ok() {
+        let signature = sign("whsec_test", 1792368000, r#"{"number":1}"#);
+        assert_eq!(
+            signature,
+            "6cbd412e8e714ceb32bbb12e41487331eb631d3aec6191f9a8520117d92ca392"
+        );
+        assert_ne!(
+            signature,
+            sign("whsec_test2", 1792368000, r#"{"number":1}"#)
+        );
+        assert_ne!(signature, sign("whsec_test", 1792368001, r#"{"number":1}"#));
+    }
+}
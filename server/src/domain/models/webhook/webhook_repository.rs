@@ -0,0 +1,46 @@
+//! Webhook に関するリポジトリを定義する。
+
+use crate::domain::models::outbox::outbox::OutboxMessage;
+use crate::domain::models::webhook::webhook::{
+    NewWebhook, NewWebhookDelivery, NewWebhookJob, Webhook, WebhookDelivery, WebhookJob,
+};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+/// Webhook のリポジトリインタフェース
+pub trait WebhookRepository {
+    /// ID から Webhook を探す
+    fn find_by_id(&self, id: i32) -> Result<Option<Webhook>>;
+
+    /// 全ての Webhook を返す
+    fn list(&self) -> Result<Vec<Webhook>>;
+
+    /// Webhook を永続化（保存）し、採番された Webhook を返す
+    fn insert(&self, webhook: &NewWebhook) -> Result<Webhook>;
+
+    /// Webhook を削除する。削除した場合は true を返す。送信の記録も合わせて削除する。
+    fn delete(&self, id: i32) -> Result<bool>;
+
+    /// 送信の記録を永続化（保存）する
+    fn insert_delivery(&self, delivery: &NewWebhookDelivery) -> Result<WebhookDelivery>;
+
+    /// Webhook の送信の記録を新しい順に最大 limit 件返す
+    fn list_deliveries(&self, webhook_id: i32, limit: i64) -> Result<Vec<WebhookDelivery>>;
+
+    /// アウトボックスのうち通知を作っていないイベントを記録順に最大 limit 件取り出し、`jobs` で作った通知をキューに入れる。
+    /// イベントを通知済みにすることと合わせて 1 つのトランザクションで行い、取り出したイベントの件数を返す。
+    fn enqueue(
+        &self,
+        limit: i64,
+        jobs: &dyn Fn(&OutboxMessage) -> Result<Vec<NewWebhookJob>>,
+    ) -> Result<usize>;
+
+    /// 送信の時刻になった通知を最大 limit 件取り出し、locked_until まで他から取り出せないようにする
+    fn claim(&self, limit: i64, locked_until: DateTime<Utc>) -> Result<Vec<WebhookJob>>;
+
+    /// 通知をキューから除く
+    fn complete(&self, id: i64) -> Result<()>;
+
+    /// 送信した回数を 1 つ進め、next_attempt_at に再送を予約する
+    fn retry(&self, id: i64, next_attempt_at: DateTime<Utc>) -> Result<()>;
+}
@@ -0,0 +1,259 @@
+//! 条件付きリクエスト (RFC 7232) と HTTP キャッシュのためのヘッダー処理。
+//! ポケモンの版を ETag として返し、If-Match で更新・削除の前提とする版を受け取る。
+//! 参照系では If-None-Match / If-Modified-Since を評価し、変更がなければ 304 を返す。
+
+use crate::config::Config;
+use crate::infra::actix::negotiation::Format;
+use crate::infra::actix::problem::Problem;
+use actix_web::http::header::{
+    ETag, EntityTag, HttpDate, IfMatch, IfModifiedSince, IfNoneMatch, LastModified, CACHE_CONTROL,
+    VARY,
+};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 版と表現形式から ETag を作成する。
+/// 強い ETag は表現ごとに一意である必要があるため、JSON 以外は形式を付加する（`"3-msgpack"` など）。
+pub fn etag(version: i32, format: Format) -> ETag {
+    let tag = match format.etag_suffix() {
+        Some(suffix) => format!("{}-{}", version, suffix),
+        None => version.to_string(),
+    };
+    ETag(EntityTag::new_strong(tag))
+}
+
+/// 強い ETag から版を取り出す。形式が付加されていれば取り除く。
+fn version_of(tag: &EntityTag) -> Option<i32> {
+    if tag.weak {
+        return None;
+    }
+    tag.tag().split('-').next()?.parse().ok()
+}
+
+/// 本文のハッシュ値から弱い ETag を作成する。一覧のように版を持たないレスポンスに使う。
+pub fn weak_etag(body: &[u8]) -> ETag {
+    let digest = hex::encode(Sha256::digest(body));
+    ETag(EntityTag::new_weak(digest[..32].to_string()))
+}
+
+/// 設定から Cache-Control ヘッダーの値を決める。
+/// 認証なしで参照できる場合は CDN でのキャッシュを許可し、そうでない場合はブラウザのみとする。
+pub fn cache_control(config: &Config) -> Option<String> {
+    match &config.cache_control {
+        Some(value) if value.trim().is_empty() => None,
+        Some(value) => Some(value.clone()),
+        None if config.auth_public_read => Some(String::from("public, max-age=60")),
+        None => Some(String::from("private, no-cache")),
+    }
+}
+
+/// If-None-Match / If-Modified-Since を評価し、クライアントが持つキャッシュが最新かどうかを返す。
+/// If-None-Match がある場合、If-Modified-Since は評価しない。
+pub fn is_fresh(req: &HttpRequest, etag: &ETag, last_modified: Option<DateTime<Utc>>) -> bool {
+    if let Some(if_none_match) = req.get_header::<IfNoneMatch>() {
+        return match if_none_match {
+            IfNoneMatch::Any => true,
+            IfNoneMatch::Items(tags) => tags.iter().any(|tag| tag.weak_eq(&etag.0)),
+        };
+    }
+    match (req.get_header::<IfModifiedSince>(), last_modified) {
+        (Some(IfModifiedSince(since)), Some(last_modified)) => {
+            let since = SystemTime::from(since)
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0);
+            // HTTP の日時は秒単位のため、秒未満を切り捨てて比較する
+            last_modified.timestamp() <= since
+        }
+        _ => false,
+    }
+}
+
+/// ETag / Last-Modified / Cache-Control を付与した参照系のレスポンスを作成する。
+/// クライアントが持つキャッシュが最新であれば、本文なしの 304 を返す。
+/// 本文は `format` で符号化する。
+pub fn cached<T: Serialize>(
+    req: &HttpRequest,
+    etag: ETag,
+    last_modified: Option<DateTime<Utc>>,
+    cache_control: Option<&str>,
+    format: Format,
+    body: &T,
+) -> HttpResponse {
+    let fresh = is_fresh(req, &etag, last_modified);
+    let mut builder = if fresh {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    builder.insert_header(etag);
+    if let Some(last_modified) = last_modified {
+        builder.insert_header(LastModified(HttpDate::from(SystemTime::from(
+            last_modified,
+        ))));
+    }
+    if let Some(value) = cache_control {
+        builder.insert_header((CACHE_CONTROL, value));
+    }
+    if fresh {
+        builder.insert_header((VARY, "Accept")).finish()
+    } else {
+        format.respond(builder, body)
+    }
+}
+
+/// If-Match ヘッダーを取得する。
+/// `required` が true の場合、ヘッダーがなければ 428 とする。
+pub fn if_match(req: &HttpRequest, required: bool) -> Result<Option<IfMatch>, Problem> {
+    match req.get_header::<IfMatch>() {
+        Some(value) => Ok(Some(value)),
+        None if required => Err(Problem::precondition_required(
+            "If-Match ヘッダーで更新対象の ETag を指定してください",
+        )),
+        None => Ok(None),
+    }
+}
+
+/// If-Match を現在の版と比較し、一致すればその版を返す。
+/// 一致しない場合や、対象が存在しない場合は 412 とする。
+pub fn expected_version(if_match: &IfMatch, current: Option<i32>) -> Result<i32, Problem> {
+    let current = current.ok_or_else(|| Problem::precondition_failed("対象が存在しません"))?;
+    let matched = match if_match {
+        IfMatch::Any => true,
+        IfMatch::Items(tags) => tags.iter().any(|tag| version_of(tag) == Some(current)),
+    };
+    if matched {
+        Ok(current)
+    } else {
+        Err(Problem::precondition_failed(format!(
+            "ETag が一致しません: 現在の ETag は {}",
+            etag(current, Format::Json)
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test as actix_test, ResponseError};
+    use chrono::TimeZone;
+
+    #[test]
+    fn if_match_ng_required() {
+        let req = actix_test::TestRequest::default().to_http_request();
+        let result = if_match(&req, true);
+        assert_eq!(
+            result.unwrap_err().status_code(),
+            StatusCode::PRECONDITION_REQUIRED
+        );
+    }
+
+    #[test]
+    fn expected_version_ok() {
+        let req = actix_test::TestRequest::default()
+            .insert_header((header::IF_MATCH, r#""1", "3""#))
+            .to_http_request();
+        let value = if_match(&req, true).unwrap().unwrap();
+        assert_eq!(expected_version(&value, Some(3)).unwrap(), 3);
+        assert_eq!(expected_version(&IfMatch::Any, Some(2)).unwrap(), 2);
+    }
+
+    #[test]
+    fn expected_version_ng() {
+        let req = actix_test::TestRequest::default()
+            .insert_header((header::IF_MATCH, r#"W/"3""#))
+            .to_http_request();
+        let value = if_match(&req, false).unwrap().unwrap();
+        assert_eq!(
+            expected_version(&value, Some(3)).unwrap_err().status_code(),
+            StatusCode::PRECONDITION_FAILED
+        );
+        assert_eq!(
+            expected_version(&IfMatch::Any, None)
+                .unwrap_err()
+                .status_code(),
+            StatusCode::PRECONDITION_FAILED
+        );
+    }
+
+    #[test]
+    fn is_fresh_ok_if_none_match() {
+        let req = actix_test::TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, r#"W/"2""#))
+            .to_http_request();
+        assert!(is_fresh(&req, &etag(2, Format::Json), None));
+        assert!(!is_fresh(&req, &etag(3, Format::Json), None));
+    }
+
+    #[test]
+    fn etag_ok_per_format() {
+        assert_eq!(etag(3, Format::Json).to_string(), r#""3""#);
+        assert_eq!(etag(3, Format::MessagePack).to_string(), r#""3-msgpack""#);
+        assert_ne!(etag(3, Format::Cbor), etag(3, Format::Yaml));
+
+        // JSON の ETag を持つクライアントの If-None-Match では他の形式の 304 にならない
+        let req = actix_test::TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, r#""3""#))
+            .to_http_request();
+        assert!(is_fresh(&req, &etag(3, Format::Json), None));
+        assert!(!is_fresh(&req, &etag(3, Format::MessagePack), None));
+
+        // If-Match はどの形式の ETag でも版が一致すれば受け付ける
+        let req = actix_test::TestRequest::default()
+            .insert_header((header::IF_MATCH, r#""3-cbor""#))
+            .to_http_request();
+        let value = if_match(&req, true).unwrap().unwrap();
+        assert_eq!(expected_version(&value, Some(3)).unwrap(), 3);
+        assert!(expected_version(&value, Some(4)).is_err());
+    }
+
+    #[test]
+    fn is_fresh_ok_if_modified_since() {
+        let last_modified = Utc.with_ymd_and_hms(2026, 10, 19, 0, 0, 0).unwrap()
+            + chrono::Duration::milliseconds(500);
+        let req = actix_test::TestRequest::default()
+            .insert_header((header::IF_MODIFIED_SINCE, "Mon, 19 Oct 2026 00:00:00 GMT"))
+            .to_http_request();
+        assert!(is_fresh(&req, &etag(1, Format::Json), Some(last_modified)));
+        assert!(!is_fresh(
+            &req,
+            &etag(1, Format::Json),
+            Some(last_modified + chrono::Duration::seconds(1))
+        ));
+    }
+
+    #[test]
+    fn is_fresh_ng_if_none_match_takes_precedence() {
+        let req = actix_test::TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, r#""1""#))
+            .insert_header((header::IF_MODIFIED_SINCE, "Mon, 19 Oct 2026 00:00:00 GMT"))
+            .to_http_request();
+        let last_modified = Utc.with_ymd_and_hms(2026, 10, 18, 0, 0, 0).unwrap();
+        assert!(!is_fresh(&req, &etag(2, Format::Json), Some(last_modified)));
+    }
+
+    #[test]
+    fn cached_ok_not_modified() {
+        let req = actix_test::TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, r#""1""#))
+            .to_http_request();
+        let res = cached(
+            &req,
+            etag(1, Format::Json),
+            None,
+            Some("public, max-age=60"),
+            Format::Json,
+            &"body",
+        );
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers().get(header::ETAG).unwrap(), r#""1""#);
+        assert_eq!(
+            res.headers().get(header::CACHE_CONTROL).unwrap(),
+            "public, max-age=60"
+        );
+    }
+}
@@ -0,0 +1,185 @@
+//! 別オリジンで動くフロントエンドからのリクエストを許可するための CORS 設定。
+
+use crate::config::Config;
+use actix_cors::Cors;
+use actix_web::http::{header::HeaderName, Method, Uri};
+use anyhow::{Context, Result};
+
+/// カンマ区切りの設定値を分割する。
+fn split_list(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+/// 設定から CORS ミドルウェアを作成する。
+/// 許可するオリジンが設定されていない場合は、別オリジンからのリクエストを全て拒否する。
+pub fn cors(config: &Config) -> Result<Cors> {
+    let origins = split_list(&config.cors_allowed_origins);
+    let any_origin = origins.iter().any(|o| o == "*");
+    if any_origin && config.cors_allow_credentials {
+        return Err(anyhow::anyhow!(
+            "CORS_ALLOWED_ORIGINS=* cannot be combined with CORS_ALLOW_CREDENTIALS=true"
+        ));
+    }
+
+    let mut cors = Cors::default();
+    if any_origin {
+        cors = cors.allow_any_origin();
+    } else {
+        for origin in origins.iter() {
+            let uri: Uri = origin
+                .parse()
+                .with_context(|| format!("invalid CORS origin {:?}", origin))?;
+            if uri.scheme().is_none() || uri.host().is_none() {
+                return Err(anyhow::anyhow!("invalid CORS origin {:?}", origin));
+            }
+            cors = cors.allowed_origin(origin);
+        }
+    }
+
+    let methods = split_list(&config.cors_allowed_methods)
+        .iter()
+        .map(|m| {
+            Method::from_bytes(m.to_uppercase().as_bytes())
+                .with_context(|| format!("invalid CORS method {:?}", m))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let headers = split_list(&config.cors_allowed_headers)
+        .iter()
+        .map(|h| {
+            HeaderName::from_bytes(h.as_bytes())
+                .with_context(|| format!("invalid CORS header {:?}", h))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let exposed_headers = split_list(&config.cors_exposed_headers)
+        .iter()
+        .map(|h| {
+            HeaderName::from_bytes(h.as_bytes())
+                .with_context(|| format!("invalid CORS header {:?}", h))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    cors = cors
+        .allowed_methods(methods)
+        .allowed_headers(headers)
+        .max_age(config.cors_max_age);
+    if !exposed_headers.is_empty() {
+        cors = cors.expose_headers(exposed_headers);
+    }
+    if config.cors_allow_credentials {
+        cors = cors.supports_credentials();
+    }
+    Ok(cors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::header, test as actix_test, web, App, HttpResponse};
+
+    fn test_config(origins: &str, credentials: bool) -> Config {
+        serde_json::from_value(serde_json::json!({
+            "server_address": "127.0.0.1",
+            "server_port": 8080,
+            "database_url": "postgres://localhost/test",
+            "cors_allowed_origins": origins,
+            "cors_allow_credentials": credentials,
+        }))
+        .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn cors_preflight_ok() {
+        let config = test_config("http://localhost:3000", true);
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(cors(&config).unwrap())
+                .route("/pokemon", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = actix_test::TestRequest::default()
+            .method(Method::OPTIONS)
+            .uri("/pokemon")
+            .insert_header((header::ORIGIN, "http://localhost:3000"))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "POST"))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization"))
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert!(res.status().is_success());
+        let headers = res.headers();
+        assert_eq!(
+            headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "http://localhost:3000"
+        );
+        assert_eq!(
+            headers
+                .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+                .unwrap(),
+            "true"
+        );
+        assert_eq!(headers.get(header::ACCESS_CONTROL_MAX_AGE).unwrap(), "3600");
+    }
+
+    #[actix_web::test]
+    async fn cors_exposes_location() {
+        let config = test_config("http://localhost:3000", false);
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(cors(&config).unwrap())
+                .route("/pokemon", web::post().to(HttpResponse::Created)),
+        )
+        .await;
+
+        let req = actix_test::TestRequest::post()
+            .uri("/pokemon")
+            .insert_header((header::ORIGIN, "http://localhost:3000"))
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        let exposed = res
+            .headers()
+            .get(header::ACCESS_CONTROL_EXPOSE_HEADERS)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_ascii_lowercase();
+        assert!(exposed.split(',').any(|h| h.trim() == "location"));
+    }
+
+    #[actix_web::test]
+    async fn cors_preflight_ng_unknown_origin() {
+        let config = test_config("http://localhost:3000", false);
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(cors(&config).unwrap())
+                .route("/pokemon", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = actix_test::TestRequest::default()
+            .method(Method::OPTIONS)
+            .uri("/pokemon")
+            .insert_header((header::ORIGIN, "http://evil.example.com"))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "POST"))
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert!(res
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+    }
+
+    #[test]
+    fn cors_ng_any_origin_with_credentials() {
+        let config = test_config("*", true);
+        assert!(cors(&config).is_err());
+    }
+
+    #[test]
+    fn cors_ng_invalid_origin() {
+        let config = test_config("localhost", false);
+        assert!(cors(&config).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+//! ポケモン図鑑の CSV（`number,name,types`）の読み書き。
+//! タイプは 1 つのセルに `|` 区切りで格納する。
+
+use crate::application::pokemon_data::PokemonData;
+use crate::application::pokemon_import_service::PokemonImportRow;
+use crate::infra::actix::problem::Problem;
+use actix_web::http::{header, StatusCode};
+use actix_web::HttpRequest;
+
+pub const TEXT_CSV: &str = "text/csv; charset=utf-8";
+
+/// 1 つのセルに格納したタイプの区切り文字
+const TYPES_SEPARATOR: char = '|';
+
+const COLUMNS: [&str; 3] = ["number", "name", "types"];
+
+/// 1 行分のフィールドを CSV の 1 行に変換する
+fn write_record<I, S>(fields: I) -> Vec<u8>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<[u8]>,
+{
+    let mut writer = csv::Writer::from_writer(vec![]);
+    // Vec への書き込みは失敗しない
+    writer.write_record(fields).unwrap();
+    writer.into_inner().unwrap_or_default()
+}
+
+/// 見出し行
+pub fn header() -> Vec<u8> {
+    write_record(COLUMNS)
+}
+
+/// ポケモン 1 件分の行
+pub fn record(pokemon: &PokemonData) -> Vec<u8> {
+    write_record([
+        pokemon.get_number().to_string(),
+        pokemon.get_name().clone(),
+        pokemon.get_types().join(&TYPES_SEPARATOR.to_string()),
+    ])
+}
+
+fn invalid_csv(detail: impl Into<String>) -> Problem {
+    Problem::new(StatusCode::BAD_REQUEST, "invalid_csv", "Bad Request").with_detail(detail)
+}
+
+/// Content-Type が CSV であることを確認する
+pub fn require_csv(req: &HttpRequest) -> Result<(), Problem> {
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+    match content_type.as_str() {
+        "text/csv" | "application/csv" => Ok(()),
+        _ => Err(Problem::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "unsupported_media_type",
+            "Unsupported Media Type",
+        )
+        .with_detail(format!(
+            "Content-Type は text/csv を指定してください: {:?}",
+            content_type
+        ))),
+    }
+}
+
+/// CSV を読み込み、取り込む行の一覧に変換する。
+/// 見出し行の列は順不同で、`number`・`name`・`types` 以外の列は無視する。
+pub fn read(body: &[u8]) -> Result<Vec<PokemonImportRow>, Problem> {
+    // 表計算ソフトが出力する BOM を取り除く
+    let body = body.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(body);
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(body);
+    let headers = reader
+        .headers()
+        .map_err(|e| invalid_csv(e.to_string()))?
+        .clone();
+    let mut positions = [0; 3];
+    for (position, column) in positions.iter_mut().zip(COLUMNS) {
+        *position = headers
+            .iter()
+            .position(|h| h.eq_ignore_ascii_case(column))
+            .ok_or_else(|| invalid_csv(format!("見出し行に {} の列がありません", column)))?;
+    }
+
+    let mut rows = vec![];
+    for record in reader.records() {
+        let record = record.map_err(|e| invalid_csv(e.to_string()))?;
+        let field = |i: usize| record.get(positions[i]).unwrap_or_default().to_string();
+        rows.push(PokemonImportRow {
+            line: record.position().map(|p| p.line() as usize).unwrap_or(0),
+            number: field(0),
+            name: field(1),
+            types: field(2)
+                .split(TYPES_SEPARATOR)
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .collect(),
+        });
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::pokemon::{
+        pokemon::Pokemon, pokemon_name::PokemonName, pokemon_number::PokemonNumber,
+        pokemon_types::PokemonTypes,
+    };
+    use std::convert::TryFrom;
+
+    #[test]
+    fn record_ok() {
+        let pokemon = PokemonData::new(Pokemon::new(
+            PokemonNumber::try_from(6).unwrap(),
+            PokemonName::try_from("Test, Pokemon".to_string()).unwrap(),
+            PokemonTypes::try_from(vec!["Fire".to_string(), "Flying".to_string()]).unwrap(),
+        ));
+        assert_eq!(record(&pokemon), b"6,\"Test, Pokemon\",Fire|Flying\n");
+    }
+
+    #[test]
+    fn read_ok() {
+        let body = "\u{FEFF}name,number,types\nTestPokemon,1,Fire | Flying\n";
+        let rows = read(body.as_bytes()).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].line, 2);
+        assert_eq!(rows[0].number, "1");
+        assert_eq!(rows[0].name, "TestPokemon");
+        assert_eq!(rows[0].types, vec!["Fire", "Flying"]);
+    }
+
+    #[test]
+    fn read_ng_missing_column() {
+        let result = read(b"number,name\n1,TestPokemon\n");
+        assert!(result.is_err());
+    }
+}
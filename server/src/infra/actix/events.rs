@@ -0,0 +1,475 @@
+//! ポケモンの変更を Server-Sent Events で配信する。
+//! イベントログはドメインイベントのバスを購読し、登録・更新・削除が成功するたびに変更をメモリ上に記録して、
+//! 接続中のクライアントへ送る。
+//! ログは直近の一定件数だけを保持し、`Last-Event-ID` で切断中に発生したイベントから再開できる。
+//! イベントの ID はプロセスごとのエポックを含むため、再起動後に以前の ID を指定された場合は再開できないものとして扱う。
+
+use super::auth::ReadAccess;
+use super::router::RequestContext;
+use super::shutdown::Readiness;
+use super::versioning::ApiVersion;
+use crate::application::pokemon_data::PokemonData;
+use crate::application::pokemon_watch_service::PokemonChange;
+use crate::config::CONFIG;
+use crate::domain::models::pokemon::{
+    pokemon_event::{PokemonEvent, PokemonRegistered, PokemonRenamed, PokemonRetyped},
+    pokemon_event_bus::PokemonEventSubscriber,
+};
+use actix_web::{get, http::header, web, HttpRequest, HttpResponse, Responder};
+use anyhow::Result;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, mpsc};
+
+/// SSE のメディアタイプ
+const EVENT_STREAM: &str = "text/event-stream";
+
+/// クライアントへの送信待ちとして保持する最大イベント数
+const SSE_BUFFER: usize = 64;
+
+/// イベントの ID。`<エポック>-<連番>` の形式で表す。
+/// エポックはイベントログを作成したプロセスの起動時刻（ミリ秒）で、連番は起動するたびに 1 から数え直す。
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct EventId {
+    pub epoch: u64,
+    pub seq: u64,
+}
+
+impl fmt::Display for EventId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.epoch, self.seq)
+    }
+}
+
+impl FromStr for EventId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (epoch, seq) = s
+            .split_once('-')
+            .ok_or_else(|| anyhow::anyhow!("invalid event id: {}", s))?;
+        Ok(Self {
+            epoch: epoch.parse()?,
+            seq: seq.parse()?,
+        })
+    }
+}
+
+/// イベントログに記録した変更
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ChangeEvent {
+    pub id: EventId,
+    pub change: PokemonChange,
+}
+
+/// 変更のイベントログ。全ワーカーで共有する。
+#[derive(Clone)]
+pub struct ChangeFeed(Arc<Inner>);
+
+struct Inner {
+    epoch: u64,
+    log: Mutex<EventLog>,
+    sender: broadcast::Sender<ChangeEvent>,
+}
+
+struct EventLog {
+    next_id: u64,
+    events: VecDeque<ChangeEvent>,
+    capacity: usize,
+}
+
+/// 購読を開始した時点の状態
+pub struct Subscription {
+    /// `Last-Event-ID` より後に記録され、ログに残っているイベント
+    pub backlog: Vec<ChangeEvent>,
+    /// `Last-Event-ID` より後のイベントがログから失われているかどうか
+    pub missed: bool,
+    /// 最後に記録されたイベントの ID。まだ記録がなければ連番を 0 とする
+    pub last_id: EventId,
+    pub receiver: broadcast::Receiver<ChangeEvent>,
+}
+
+impl ChangeFeed {
+    /// コンストラクタ。直近の `capacity` 件のイベントを保持する。
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        let epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        Self(Arc::new(Inner {
+            epoch,
+            log: Mutex::new(EventLog {
+                next_id: 1,
+                events: VecDeque::with_capacity(capacity),
+                capacity,
+            }),
+            sender,
+        }))
+    }
+
+    /// 変更をログに記録し、購読中のクライアントに送る
+    pub fn publish(&self, change: PokemonChange) {
+        let mut log = self.0.log.lock().unwrap();
+        let event = ChangeEvent {
+            id: self.id(log.next_id),
+            change,
+        };
+        log.next_id += 1;
+        if log.capacity > 0 {
+            if log.events.len() == log.capacity {
+                log.events.pop_front();
+            }
+            log.events.push_back(event.clone());
+        }
+        // 購読者がいない場合のエラーは無視する
+        let _ = self.0.sender.send(event);
+    }
+
+    /// 購読を開始する。`last_event_id` には `Last-Event-ID` の値をそのまま渡す。
+    /// 記録と送信は同じロックの中で行うため、backlog と receiver の間でイベントが重複・欠落しない。
+    pub fn subscribe(&self, last_event_id: Option<&str>) -> Subscription {
+        let log = self.0.log.lock().unwrap();
+        let receiver = self.0.sender.subscribe();
+        let last_seq = log.next_id - 1;
+        let (backlog, missed) = match last_event_id.map(str::parse::<EventId>) {
+            None => (vec![], false),
+            Some(Ok(last)) if last.epoch == self.0.epoch => {
+                let oldest = log.events.front().map_or(log.next_id, |event| event.id.seq);
+                let backlog = log
+                    .events
+                    .iter()
+                    .filter(|event| event.id.seq > last.seq)
+                    .cloned()
+                    .collect();
+                // 未知の ID を指定された場合も、失われたものとして扱う
+                (backlog, last.seq > last_seq || last.seq + 1 < oldest)
+            }
+            // 再起動前のプロセスの ID や不正な ID からは再開できない
+            Some(_) => (vec![], true),
+        };
+        Subscription {
+            backlog,
+            missed,
+            last_id: self.id(last_seq),
+            receiver,
+        }
+    }
+
+    fn id(&self, seq: u64) -> EventId {
+        EventId {
+            epoch: self.0.epoch,
+            seq,
+        }
+    }
+}
+
+/// ドメインイベントを変更としてイベントログに記録する購読者。
+/// リポジトリを読み直さず、イベントが持つ変更後のポケモンから記録する。
+pub struct ChangeFeedSubscriber {
+    change_feed: ChangeFeed,
+}
+
+impl ChangeFeedSubscriber {
+    /// コンストラクタ
+    pub fn new(change_feed: ChangeFeed) -> Self {
+        Self { change_feed }
+    }
+}
+
+/// ドメインイベントを変更に変換する
+fn change_of(event: &PokemonEvent) -> PokemonChange {
+    match event {
+        // 論理削除から戻ったポケモンも、購読者からは新たに登録されたものとして扱う
+        PokemonEvent::Registered(PokemonRegistered { pokemon }) => {
+            PokemonChange::Created(PokemonData::new(pokemon.clone()))
+        }
+        PokemonEvent::Renamed(PokemonRenamed { pokemon, .. })
+        | PokemonEvent::Retyped(PokemonRetyped { pokemon, .. }) => {
+            PokemonChange::Updated(PokemonData::new(pokemon.clone()))
+        }
+        PokemonEvent::Deleted(e) => PokemonChange::Deleted(e.number.clone().into()),
+    }
+}
+
+impl PokemonEventSubscriber for ChangeFeedSubscriber {
+    fn handle(&self, event: &PokemonEvent) -> Result<()> {
+        self.change_feed.publish(change_of(event));
+        Ok(())
+    }
+
+    /// 1 回の変更を 1 件の変更として記録する。
+    /// 名前とタイプを同時に変更した場合、どちらのイベントも同じ変更後のポケモンを持つため、最後のものだけを記録する。
+    fn handle_all(&self, events: &[PokemonEvent]) -> Result<()> {
+        match events.last() {
+            Some(event) => self.handle(event),
+            None => Ok(()),
+        }
+    }
+}
+
+/// 変更を SSE のイベントに変換する。データは API のバージョンに応じた表現とする。
+fn format_event(version: ApiVersion, event: &ChangeEvent) -> web::Bytes {
+    let (name, data) = match &event.change {
+        PokemonChange::Created(pokemon) => ("created", version.represent(pokemon.clone())),
+        PokemonChange::Updated(pokemon) => ("updated", version.represent(pokemon.clone())),
+        PokemonChange::Deleted(number) => {
+            return web::Bytes::from(format!(
+                "id: {}\nevent: deleted\ndata: {}\n\n",
+                event.id,
+                serde_json::json!({ "number": number })
+            ))
+        }
+    };
+    web::Bytes::from(format!(
+        "id: {}\nevent: {}\ndata: {}\n\n",
+        event.id,
+        name,
+        serde_json::to_string(&data).unwrap_or_default()
+    ))
+}
+
+/// 再開できないことを伝えるイベント。クライアントは一覧を取得し直す。
+fn reset_event(last_id: EventId) -> web::Bytes {
+    web::Bytes::from(format!("id: {}\nevent: reset\ndata: {{}}\n\n", last_id))
+}
+
+/// 購読したイベントをクライアントへ送る。
+/// クライアントの切断、送信の遅れによる取りこぼし、停止処理の開始のいずれかで終了する。
+async fn send_events(
+    mut subscription: Subscription,
+    version: ApiVersion,
+    readiness: Readiness,
+    tx: mpsc::Sender<Result<web::Bytes, Infallible>>,
+) {
+    let mut initial = vec![web::Bytes::from_static(b": connected\n\n")];
+    if subscription.missed {
+        initial.push(reset_event(subscription.last_id));
+    }
+    initial.extend(
+        subscription
+            .backlog
+            .iter()
+            .map(|event| format_event(version, event)),
+    );
+    for message in initial {
+        if tx.send(Ok(message)).await.is_err() {
+            return;
+        }
+    }
+
+    let mut heartbeat =
+        actix_web::rt::time::interval(Duration::from_secs(CONFIG.sse_heartbeat_interval.max(1)));
+    // 最初の tick はすぐに完了するため読み捨てる
+    heartbeat.tick().await;
+    loop {
+        let message = tokio::select! {
+            event = subscription.receiver.recv() => match event {
+                Ok(event) => format_event(version, &event),
+                // 送信が追いつかずに取りこぼした場合は接続を閉じ、Last-Event-ID から再開させる
+                Err(_) => break,
+            },
+            _ = heartbeat.tick() => {
+                // 停止処理中は接続を閉じ、他のインスタンスへ再接続させる
+                if !readiness.is_ready() {
+                    break;
+                }
+                web::Bytes::from_static(b": heartbeat\n\n")
+            }
+            _ = tx.closed() => break,
+        };
+        if tx.send(Ok(message)).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[utoipa::path(
+    tag = "pokemon",
+    params(("Last-Event-ID" = Option<String>, Header, description = "最後に受け取ったイベントの ID（`<エポック>-<連番>`）。以降のイベントから再開する")),
+    responses(
+        (status = 200, description = "`created`・`updated`・`deleted` のイベントのストリーム。再開できない場合は先に `reset` を送る",
+            content_type = "text/event-stream", body = String),
+    )
+)]
+#[get("/pokemon/events")]
+async fn pokemon_events(
+    _access: ReadAccess,
+    version: ApiVersion,
+    data: web::Data<RequestContext>,
+    readiness: web::Data<Readiness>,
+    req: HttpRequest,
+) -> impl Responder {
+    let last_event_id = req
+        .headers()
+        .get("Last-Event-ID")
+        .map(|value| value.to_str().unwrap_or_default().trim());
+    let subscription = data.change_feed().subscribe(last_event_id);
+    let (tx, mut rx) = mpsc::channel(SSE_BUFFER);
+    actix_web::rt::spawn(send_events(
+        subscription,
+        version,
+        readiness.get_ref().clone(),
+        tx,
+    ));
+    let stream = futures_util::stream::poll_fn(move |cx| rx.poll_recv(cx));
+    HttpResponse::Ok()
+        .content_type(EVENT_STREAM)
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::pokemon::pokemon::Pokemon;
+    use crate::domain::models::pokemon::pokemon_event::PokemonDeleted;
+    use crate::domain::models::pokemon::pokemon_event_bus::PokemonEventBus;
+    use crate::domain::models::pokemon::pokemon_number::PokemonNumber;
+    use std::convert::TryFrom;
+
+    fn created(number: i32) -> PokemonChange {
+        PokemonChange::Created(PokemonData::new(
+            Pokemon::try_new(number, "TestPokemon".to_string(), vec!["Fire".to_string()]).unwrap(),
+        ))
+    }
+
+    /// 連番 `seq` のイベントまで受け取ったものとして購読する
+    fn resume(feed: &ChangeFeed, seq: u64) -> Subscription {
+        feed.subscribe(Some(&feed.id(seq).to_string()))
+    }
+
+    #[test]
+    fn subscribe_resumes_from_last_event_id() {
+        let feed = ChangeFeed::new(2);
+        feed.publish(created(1));
+        feed.publish(created(2));
+        feed.publish(PokemonChange::Deleted(1));
+
+        let subscription = resume(&feed, 2);
+        assert!(!subscription.missed);
+        assert_eq!(subscription.last_id, feed.id(3));
+        assert_eq!(
+            subscription.backlog,
+            vec![ChangeEvent {
+                id: feed.id(3),
+                change: PokemonChange::Deleted(1)
+            }]
+        );
+
+        // 連番 1 の次のイベント（連番 2）はログに残っている
+        assert!(!resume(&feed, 1).missed);
+        // 連番 1 のイベントはログから押し出されている
+        assert!(resume(&feed, 0).missed);
+        // 未知の ID
+        assert!(resume(&feed, 10).missed);
+        assert!(feed.subscribe(None).backlog.is_empty());
+    }
+
+    #[test]
+    fn subscribe_resets_after_restart() {
+        let feed = ChangeFeed::new(10);
+        feed.publish(created(1));
+
+        // 再起動前のプロセスでは、同じ連番が別のイベントを指している
+        let previous = EventId {
+            epoch: feed.id(0).epoch - 1,
+            seq: 0,
+        };
+        let subscription = feed.subscribe(Some(&previous.to_string()));
+        assert!(subscription.missed);
+        assert!(subscription.backlog.is_empty());
+        assert!(feed.subscribe(Some("1")).missed);
+        assert!(feed.subscribe(Some("")).missed);
+    }
+
+    #[test]
+    fn event_id_ok() {
+        let id = EventId {
+            epoch: 1760000000000,
+            seq: 3,
+        };
+        assert_eq!(id.to_string(), "1760000000000-3");
+        assert_eq!("1760000000000-3".parse::<EventId>().unwrap(), id);
+        assert!("3".parse::<EventId>().is_err());
+        assert!("a-3".parse::<EventId>().is_err());
+    }
+
+    #[test]
+    fn subscribe_receives_published_events() {
+        let feed = ChangeFeed::new(10);
+        let mut subscription = feed.subscribe(None);
+        feed.publish(PokemonChange::Deleted(1));
+        assert_eq!(subscription.receiver.try_recv().unwrap().id, feed.id(1));
+    }
+
+    #[test]
+    fn publish_records_repeated_change() {
+        let feed = ChangeFeed::new(10);
+        feed.publish(created(1));
+        feed.publish(created(1));
+        assert_eq!(feed.subscribe(None).last_id.seq, 2);
+    }
+
+    #[test]
+    fn subscriber_records_one_change_per_publish() {
+        let feed = ChangeFeed::new(10);
+        let mut event_bus = PokemonEventBus::default();
+        event_bus.subscribe(ChangeFeedSubscriber::new(feed.clone()));
+        let before =
+            Pokemon::try_new(1, "TestPokemon".to_string(), vec!["Fire".to_string()]).unwrap();
+        let after = Pokemon::try_new(1, "TestName".to_string(), vec!["Water".to_string()]).unwrap();
+        event_bus.publish(&[PokemonEvent::registered(&before)]);
+        // 名前とタイプを同時に変更しても、1 件の変更として記録する
+        event_bus.publish(&PokemonEvent::updated(&before, &after));
+        // 同じ内容に戻す変更も記録する
+        event_bus.publish(&PokemonEvent::updated(&after, &before));
+        event_bus.publish(&[PokemonEvent::Deleted(PokemonDeleted {
+            number: PokemonNumber::try_from(1).unwrap(),
+            soft: true,
+        })]);
+
+        let changes: Vec<PokemonChange> = resume(&feed, 0)
+            .backlog
+            .into_iter()
+            .map(|event| event.change)
+            .collect();
+        let mut updated = after.clone();
+        updated.version = 2;
+        let mut reverted = before.clone();
+        reverted.version = 2;
+        assert_eq!(
+            changes,
+            vec![
+                PokemonChange::Created(PokemonData::new(before)),
+                PokemonChange::Updated(PokemonData::new(updated)),
+                PokemonChange::Updated(PokemonData::new(reverted)),
+                PokemonChange::Deleted(1)
+            ]
+        );
+    }
+
+    #[test]
+    fn format_event_ok() {
+        let id = |seq| EventId { epoch: 100, seq };
+        let event = ChangeEvent {
+            id: id(5),
+            change: created(1),
+        };
+        assert_eq!(
+            format_event(ApiVersion::V1, &event),
+            "id: 100-5\nevent: created\ndata: {\"number\":1,\"name\":\"TestPokemon\",\"types\":[\"Fire\"]}\n\n"
+        );
+        let event = ChangeEvent {
+            id: id(6),
+            change: PokemonChange::Deleted(1),
+        };
+        assert_eq!(
+            format_event(ApiVersion::V2, &event),
+            "id: 100-6\nevent: deleted\ndata: {\"number\":1}\n\n"
+        );
+    }
+}
@@ -0,0 +1,549 @@
+//! ポケモン図鑑の GraphQL エンドポイント。
+//! クライアントが必要な項目だけを 1 回の往復で取得できるよう、REST と同じアプリケーションサービスを
+//! GraphQL のスキーマとして公開する。`GET /graphql` では GraphiQL を返す。
+
+use super::auth::{AuthenticatedUser, Authenticator};
+use super::problem::Problem;
+use super::router::RequestContext;
+use crate::application::pokemon_data::PokemonData;
+use crate::application::pokemon_delete_service::PokemonDeleteService;
+use crate::application::pokemon_get_service::PokemonGetService;
+use crate::application::pokemon_list_service::PokemonListService;
+use crate::application::pokemon_register_service::PokemonRegisterService;
+use crate::application::pokemon_update_service::{PokemonUpdateCommand, PokemonUpdateService};
+use crate::config::CONFIG;
+use crate::domain::models::pokemon::{
+    pokemon::Pokemon, pokemon_error::PokemonError, pokemon_query::PokemonQuery,
+    pokemon_type::PokemonType, pokemon_types::PokemonTypes,
+};
+use crate::domain::models::role::permission::Permission;
+use actix_web::{
+    get,
+    http::{header, StatusCode},
+    post, web, FromRequest, HttpRequest, HttpResponse, Responder, ResponseError,
+};
+use async_graphql::http::GraphiQLSource;
+use async_graphql::{
+    BatchRequest, Context, EmptySubscription, ErrorExtensions, InputObject, Object, Schema,
+    SimpleObject,
+};
+use chrono::{DateTime, Utc};
+use std::convert::TryFrom;
+
+pub type PokedexSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+
+/// 一覧で 1 回に返す件数の既定値と上限
+const DEFAULT_LIMIT: i32 = 20;
+const MAX_LIMIT: i32 = 100;
+
+/// クエリの入れ子の深さの上限
+const MAX_DEPTH: usize = 8;
+
+/// クエリの複雑さの上限。一覧の項目は `limit` 件分として数える。
+const MAX_COMPLEXITY: usize = 500;
+
+/// 1 回のリクエストでまとめて受け付けるクエリの最大件数
+const MAX_BATCH_SIZE: usize = 10;
+
+/// スキーマを作成する。リポジトリはスキーマに持たせた RequestContext から取得する。
+pub fn schema(context: RequestContext) -> PokedexSchema {
+    Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+        .data(context)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+/// まとめて送られたクエリの件数を確認し、上限を超える場合は 400 とする
+fn check_batch_size(request: &BatchRequest) -> Result<(), Problem> {
+    match request {
+        BatchRequest::Batch(requests) if requests.len() > MAX_BATCH_SIZE => Err(Problem::new(
+            StatusCode::BAD_REQUEST,
+            "graphql_batch_too_large",
+            "Bad Request",
+        )
+        .with_detail(format!(
+            "1 回に送れるクエリは {} 件までです",
+            MAX_BATCH_SIZE
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// GraphQL のエラーを作成する。`extensions.code` でクライアントが原因を判別できるようにする。
+fn error(code: &'static str, message: impl Into<String>) -> async_graphql::Error {
+    async_graphql::Error::new(message).extend_with(|_, e| e.set("code", code))
+}
+
+/// アプリケーションサービスのエラーを GraphQL のエラーに変換する
+fn service_error(e: anyhow::Error) -> async_graphql::Error {
+    match e.downcast_ref::<PokemonError>() {
+        Some(PokemonError::InvalidValue(_)) => error("INVALID_VALUE", e.to_string()),
+        Some(PokemonError::NotFound(_)) => error("NOT_FOUND", e.to_string()),
+        Some(PokemonError::AlreadyExists(_)) => error("ALREADY_EXISTS", e.to_string()),
+        Some(PokemonError::VersionMismatch { .. }) => error("VERSION_MISMATCH", e.to_string()),
+        Some(PokemonError::Deleted(_)) => error("DELETED", e.to_string()),
+        None => {
+            log::error!("GraphQL resolver failed: {:?}", e);
+            error("INTERNAL_SERVER_ERROR", "Internal Server Error")
+        }
+    }
+}
+
+/// Diesel の呼び出しはブロッキングするため、REST と同じくスレッドプールで実行する
+async fn blocking<F, R>(f: F) -> async_graphql::Result<R>
+where
+    F: FnOnce() -> anyhow::Result<R> + Send + 'static,
+    R: Send + 'static,
+{
+    web::block(f)
+        .await
+        .map_err(|e| service_error(e.into()))?
+        .map_err(service_error)
+}
+
+fn request_context<'a>(ctx: &Context<'a>) -> async_graphql::Result<&'a RequestContext> {
+    ctx.data::<RequestContext>()
+}
+
+/// リクエストの送信者。リゾルバーで操作の権限を確認するために使う。
+#[derive(Debug, Clone, Default)]
+pub struct Viewer {
+    user: Option<AuthenticatedUser>,
+    public_read: bool,
+}
+
+impl Viewer {
+    /// 指定した操作の権限を持っていなければエラーを返す
+    fn require(&self, required: Permission) -> async_graphql::Result<&AuthenticatedUser> {
+        match &self.user {
+            None => Err(error("UNAUTHENTICATED", "認証情報がありません。")),
+            Some(user) if user.has(required) => Ok(user),
+            Some(_) => Err(error(
+                "FORBIDDEN",
+                format!("この操作には {} の権限が必要です。", String::from(required)),
+            )),
+        }
+    }
+
+    /// 参照が公開されていなければ pokemon:read の権限を要求する
+    fn require_read(&self) -> async_graphql::Result<()> {
+        if self.public_read {
+            Ok(())
+        } else {
+            self.require(Permission::PokemonRead).map(|_| ())
+        }
+    }
+}
+
+fn viewer<'a>(ctx: &Context<'a>) -> &'a Viewer {
+    static ANONYMOUS: Viewer = Viewer {
+        user: None,
+        public_read: false,
+    };
+    ctx.data_opt::<Viewer>().unwrap_or(&ANONYMOUS)
+}
+
+/// ポケモン
+pub struct PokemonObject(PokemonData);
+
+#[Object(name = "Pokemon")]
+impl PokemonObject {
+    /// 図鑑 No
+    async fn number(&self) -> i32 {
+        *self.0.get_number()
+    }
+
+    async fn name(&self) -> &str {
+        self.0.get_name()
+    }
+
+    async fn types(&self) -> &[String] {
+        self.0.get_types()
+    }
+
+    /// 弱点となるタイプ。タイプの相性から求める。
+    async fn weaknesses(&self) -> Vec<String> {
+        weaknesses(&self.0)
+    }
+
+    /// 楽観的排他制御のための版。更新・削除の expectedVersion に指定する。
+    async fn version(&self) -> i32 {
+        *self.0.get_version()
+    }
+
+    async fn updated_at(&self) -> Option<DateTime<Utc>> {
+        *self.0.get_updated_at()
+    }
+}
+
+fn weaknesses(pokemon: &PokemonData) -> Vec<String> {
+    PokemonTypes::try_from(pokemon.get_types().clone())
+        .map(|types| types.weaknesses().into_iter().map(String::from).collect())
+        .unwrap_or_default()
+}
+
+/// 一覧の絞り込み条件。指定した条件を全て満たすものを返す。
+#[derive(InputObject, Default)]
+pub struct PokemonFilter {
+    /// このタイプを持つ
+    #[graphql(name = "type")]
+    type_: Option<String>,
+    /// このタイプが弱点である
+    weak_to: Option<String>,
+    /// 名前にこの文字列を含む（大文字・小文字を区別しない）
+    name_contains: Option<String>,
+    /// 図鑑 No がこの値以上
+    number_from: Option<i32>,
+    /// 図鑑 No がこの値以下
+    number_to: Option<i32>,
+}
+
+impl TryFrom<PokemonFilter> for PokemonQuery {
+    type Error = async_graphql::Error;
+
+    /// リポジトリの絞り込み条件に変換する。存在しないタイプは INVALID_VALUE とする。
+    fn try_from(filter: PokemonFilter) -> Result<Self, Self::Error> {
+        let pokemon_type = |t: Option<String>| {
+            t.map(|t| {
+                PokemonType::try_from(t.clone())
+                    .map_err(|_| error("INVALID_VALUE", format!("{} は存在しないタイプです", t)))
+            })
+            .transpose()
+        };
+        Ok(PokemonQuery {
+            any_types: pokemon_type(filter.type_)?.into_iter().collect(),
+            weak_to: pokemon_type(filter.weak_to)?,
+            name_contains: filter.name_contains,
+            number_from: filter.number_from,
+            number_to: filter.number_to,
+        })
+    }
+}
+
+/// 一覧の 1 ページ分
+#[derive(SimpleObject)]
+pub struct PokemonPage {
+    items: Vec<PokemonObject>,
+    /// 絞り込み条件に一致した件数
+    total_count: i32,
+    offset: i32,
+    limit: i32,
+    has_next_page: bool,
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// 図鑑 No を指定してポケモンを取得する。存在しない場合は null を返す。
+    async fn pokemon(
+        &self,
+        ctx: &Context<'_>,
+        number: i32,
+    ) -> async_graphql::Result<Option<PokemonObject>> {
+        viewer(ctx).require_read()?;
+        let repository = request_context(ctx)?.pokemon_repository();
+        let pokemon =
+            blocking(move || Ok(PokemonGetService::new(repository).handle(number).ok())).await?;
+        Ok(pokemon.map(PokemonObject))
+    }
+
+    /// ポケモンの一覧を図鑑 No 順に取得する
+    #[graphql(complexity = "limit.max(1) as usize * child_complexity")]
+    async fn pokemons(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<PokemonFilter>,
+        #[graphql(default = 0)] offset: i32,
+        #[graphql(default_with = "DEFAULT_LIMIT")] limit: i32,
+    ) -> async_graphql::Result<PokemonPage> {
+        viewer(ctx).require_read()?;
+        if offset < 0 || !(1..=MAX_LIMIT).contains(&limit) {
+            return Err(error(
+                "INVALID_VALUE",
+                format!(
+                    "offset は 0 以上、limit は 1 以上 {} 以下を指定してください",
+                    MAX_LIMIT
+                ),
+            ));
+        }
+        let query = PokemonQuery::try_from(filter.unwrap_or_default())?;
+        let repository = request_context(ctx)?.pokemon_repository();
+        let (items, total_count) = blocking(move || {
+            PokemonListService::new(repository).search_page(&query, offset.into(), limit.into())
+        })
+        .await?;
+        let items = items.into_iter().map(PokemonObject).collect::<Vec<_>>();
+        let total_count = total_count as i32;
+        Ok(PokemonPage {
+            has_next_page: offset + (items.len() as i32) < total_count,
+            items,
+            total_count,
+            offset,
+            limit,
+        })
+    }
+}
+
+/// 登録するポケモン
+#[derive(InputObject)]
+pub struct PokemonInput {
+    number: i32,
+    name: String,
+    types: Vec<String>,
+}
+
+/// 更新する項目。指定しなかった項目は現在の値のまま残す。
+#[derive(InputObject)]
+pub struct PokemonPatchInput {
+    name: Option<String>,
+    types: Option<Vec<String>>,
+}
+
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    /// ポケモンを登録する。pokemon:write の権限が必要。
+    async fn create_pokemon(
+        &self,
+        ctx: &Context<'_>,
+        input: PokemonInput,
+    ) -> async_graphql::Result<PokemonObject> {
+        let user = viewer(ctx).require(Permission::PokemonWrite)?;
+        log::info!("Register Pokemon requested by {} via GraphQL", user.subject);
+        let pokemon = Pokemon::try_new(input.number, input.name, input.types)
+            .map_err(|e| service_error(e.into()))?;
+        let context = request_context(ctx)?;
+        let service = PokemonRegisterService::new(context.pokemon_repository())
+            .with_event_bus(context.event_bus());
+        let pokemon = blocking(move || service.handle(PokemonData::new(pokemon))).await?;
+        Ok(PokemonObject(pokemon))
+    }
+
+    /// ポケモンを更新する。pokemon:write の権限が必要。
+    /// expectedVersion を指定した場合、現在の版と一致しなければ VERSION_MISMATCH とする。
+    async fn update_pokemon(
+        &self,
+        ctx: &Context<'_>,
+        number: i32,
+        input: PokemonPatchInput,
+        expected_version: Option<i32>,
+    ) -> async_graphql::Result<PokemonObject> {
+        let user = viewer(ctx).require(Permission::PokemonWrite)?;
+        log::info!(
+            "Update Pokemon requested by {} via GraphQL: no {}",
+            user.subject,
+            number
+        );
+        let context = request_context(ctx)?;
+        let get_service = PokemonGetService::new(context.pokemon_repository());
+        let update_service = PokemonUpdateService::new(context.pokemon_repository())
+            .with_event_bus(context.event_bus());
+        let mut command = PokemonUpdateCommand::new(number);
+        command.set_name(input.name);
+        command.set_types(input.types);
+        command.set_expected_version(expected_version);
+        let pokemon = blocking(move || {
+            // 更新サービスは存在しない場合を区別しないため、先に存在を確認する
+            if get_service.handle(number).is_err() {
+                return Err(PokemonError::NotFound(number).into());
+            }
+            update_service.handle(command).map(PokemonData::new)
+        })
+        .await?;
+        Ok(PokemonObject(pokemon))
+    }
+
+    /// ポケモンを削除する。pokemon:delete の権限が必要。
+    async fn delete_pokemon(
+        &self,
+        ctx: &Context<'_>,
+        number: i32,
+        expected_version: Option<i32>,
+    ) -> async_graphql::Result<bool> {
+        let user = viewer(ctx).require(Permission::PokemonDelete)?;
+        log::info!(
+            "Delete Pokemon requested by {} via GraphQL: no {}",
+            user.subject,
+            number
+        );
+        let context = request_context(ctx)?;
+        let service = PokemonDeleteService::new(context.pokemon_repository())
+            .with_soft_delete(CONFIG.soft_delete)
+            .with_event_bus(context.event_bus());
+        blocking(move || match service.handle(number, expected_version) {
+            Ok(_) => Ok(true),
+            Err(e)
+                if CONFIG.delete_idempotent
+                    && matches!(
+                        e.downcast_ref::<PokemonError>(),
+                        Some(PokemonError::NotFound(_))
+                    ) =>
+            {
+                Ok(true)
+            }
+            Err(e) => Err(e),
+        })
+        .await
+    }
+}
+
+/// GraphQL のクエリを実行する。
+/// 認証情報が指定されている場合のみ検証し、不正であれば REST と同じく 401 を返す。
+#[post("/graphql")]
+async fn graphql(
+    schema: web::Data<PokedexSchema>,
+    authenticator: Option<web::Data<Authenticator>>,
+    req: HttpRequest,
+    request: web::Json<BatchRequest>,
+) -> impl Responder {
+    let user = if req.headers().contains_key(header::AUTHORIZATION) {
+        match AuthenticatedUser::extract(&req).await {
+            Ok(user) => Some(user),
+            Err(problem) => return problem.error_response(),
+        }
+    } else {
+        None
+    };
+    let viewer = Viewer {
+        user,
+        public_read: authenticator.is_some_and(|a| a.public_read()),
+    };
+    let request = request.into_inner();
+    if let Err(problem) = check_batch_size(&request) {
+        return problem.error_response();
+    }
+    let response = schema.execute_batch(request.data(viewer)).await;
+    HttpResponse::Ok().json(response)
+}
+
+/// GraphiQL を返す
+#[get("/graphql")]
+async fn graphiql() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// データベースに接続しないスキーマ。権限の確認など、リポジトリを使う前に終わる処理を確認する。
+    fn offline_schema() -> PokedexSchema {
+        Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+            .limit_depth(MAX_DEPTH)
+            .limit_complexity(MAX_COMPLEXITY)
+            .finish()
+    }
+
+    fn user(permissions: &[Permission]) -> Viewer {
+        Viewer {
+            user: Some(AuthenticatedUser {
+                subject: "test".to_string(),
+                permissions: permissions.iter().copied().collect::<HashSet<_>>(),
+            }),
+            public_read: false,
+        }
+    }
+
+    fn code(response: &async_graphql::Response) -> Option<String> {
+        let extensions = response.errors.first()?.extensions.as_ref()?;
+        match extensions.get("code")? {
+            async_graphql::Value::String(code) => Some(code.clone()),
+            _ => None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn create_pokemon_ng_unauthenticated() {
+        let query = r#"mutation { createPokemon(input: {number: 1, name: "a", types: ["Fire"]}) { number } }"#;
+        let response = offline_schema().execute(query).await;
+        assert_eq!(code(&response).as_deref(), Some("UNAUTHENTICATED"));
+    }
+
+    #[actix_web::test]
+    async fn delete_pokemon_ng_forbidden() {
+        let query = async_graphql::Request::new("mutation { deletePokemon(number: 1) }")
+            .data(user(&[Permission::PokemonWrite]));
+        let response = offline_schema().execute(query).await;
+        assert_eq!(code(&response).as_deref(), Some("FORBIDDEN"));
+    }
+
+    #[actix_web::test]
+    async fn pokemons_ng_read_not_public() {
+        let response = offline_schema()
+            .execute("{ pokemons { totalCount } }")
+            .await;
+        assert_eq!(code(&response).as_deref(), Some("UNAUTHENTICATED"));
+    }
+
+    #[actix_web::test]
+    async fn pokemons_ng_too_complex() {
+        let query = "{ pokemons(limit: 100) { totalCount items { number name types version weaknesses updatedAt } } }";
+        let response = offline_schema().execute(query).await;
+        assert!(response.errors[0].message.contains("too complex"));
+
+        // 件数を絞れば受け付け、権限の確認まで進む
+        let query =
+            "{ pokemons(limit: 10) { totalCount items { number name types version weaknesses updatedAt } } }";
+        let response = offline_schema().execute(query).await;
+        assert_eq!(code(&response).as_deref(), Some("UNAUTHENTICATED"));
+    }
+
+    #[test]
+    fn check_batch_size_ng() {
+        let batch = |n| {
+            BatchRequest::Batch(
+                (0..n)
+                    .map(|_| async_graphql::Request::new("{ __typename }"))
+                    .collect(),
+            )
+        };
+        assert!(check_batch_size(&batch(MAX_BATCH_SIZE)).is_ok());
+        assert_eq!(
+            check_batch_size(&batch(MAX_BATCH_SIZE + 1))
+                .unwrap_err()
+                .status_code(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[test]
+    fn filter_into_query() {
+        let filter = PokemonFilter {
+            type_: Some("Water".to_string()),
+            weak_to: Some("Electric".to_string()),
+            name_contains: Some("squir".to_string()),
+            number_from: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            PokemonQuery::try_from(filter).unwrap(),
+            PokemonQuery {
+                any_types: vec![PokemonType::Water],
+                weak_to: Some(PokemonType::Electric),
+                name_contains: Some("squir".to_string()),
+                number_from: Some(1),
+                number_to: None,
+            }
+        );
+        assert_eq!(
+            PokemonQuery::try_from(PokemonFilter::default()).unwrap(),
+            PokemonQuery::default()
+        );
+    }
+
+    #[actix_web::test]
+    async fn pokemons_ng_unknown_type() {
+        let query = async_graphql::Request::new(
+            r#"{ pokemons(filter: {weakTo: "Psychic"}) { totalCount } }"#,
+        )
+        .data(user(&[Permission::PokemonRead]));
+        let response = offline_schema().execute(query).await;
+        assert_eq!(code(&response).as_deref(), Some("INVALID_VALUE"));
+    }
+}
@@ -1,33 +1,173 @@
+use super::auth::{AuthenticatedUser, ReadAccess};
 use super::router::RequestContext;
+use super::shutdown::Readiness;
+use crate::application::api_key_issue_service::{ApiKeyIssueService, IssuedApiKey};
+use crate::application::api_key_revoke_service::ApiKeyRevokeService;
+use crate::application::pokemon_batch_service::{PokemonBatchOutcome, PokemonBatchService};
 use crate::application::pokemon_delete_service::PokemonDeleteService;
 use crate::application::pokemon_get_service::PokemonGetService;
+use crate::application::pokemon_import_service::{PokemonImportReport, PokemonImportService};
 use crate::application::pokemon_list_service::PokemonListService;
-use crate::application::pokemon_update_service::{PokemonUpdateCommand, PokemonUpdateService};
+use crate::application::pokemon_restore_service::PokemonRestoreService;
+use crate::application::pokemon_update_service::PokemonUpdateService;
+use crate::application::pokemon_upsert_service::{
+    PokemonUpsertCommand, PokemonUpsertService, PokemonUpserted,
+};
+use crate::application::webhook_data::WebhookDeliveryData;
+use crate::application::webhook_delete_service::WebhookDeleteService;
+use crate::application::webhook_delivery_list_service::WebhookDeliveryListService;
+use crate::application::webhook_register_service::{RegisteredWebhook, WebhookRegisterService};
 use crate::application::{
     pokemon_data::PokemonData, pokemon_register_service::PokemonRegisterService,
 };
-use crate::infra::actix::request::PokemonRequest;
-use actix_web::{delete, get, post, put, web, web::Json, HttpResponse, Responder};
+use crate::config::CONFIG;
+use crate::domain::models::pokemon::pokemon_error::PokemonError;
+use crate::domain::models::role::permission::Permission;
+use crate::infra::actix::conditional;
+use crate::infra::actix::csv_format;
+use crate::infra::actix::negotiation::{self, Accepted, Body, Format};
+use crate::infra::actix::patch::{self, PatchFormat};
+use crate::infra::actix::problem::Problem;
+use crate::infra::actix::request::{
+    ApiKeyRequest, BatchMode, PokemonBatchRequest, PokemonExportQuery, PokemonImportQuery,
+    PokemonOperationRequest, PokemonRequest, WebhookRequest,
+};
+use crate::infra::actix::versioning::{ApiVersion, PokemonRepresentation};
+use actix_web::{
+    delete, get,
+    http::{header, StatusCode},
+    patch, post, put, web,
+    web::Json,
+    HttpRequest, HttpResponse, Responder, ResponseError,
+};
 use serde::Serialize;
+use utoipa::ToSchema;
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct ErrorResponse {
     message: String,
     r#type: String,
 }
 
+/// If-Match を評価し、更新・削除の前提とする版を求める。
+/// If-Match が指定されていない場合は None を返す。
+fn expected_version(
+    req: &HttpRequest,
+    data: &RequestContext,
+    no: i32,
+) -> Result<Option<i32>, Problem> {
+    match conditional::if_match(req, CONFIG.require_if_match)? {
+        Some(if_match) => {
+            let current = PokemonGetService::new(data.pokemon_repository())
+                .handle(no)
+                .ok()
+                .map(|pokemon| *pokemon.get_version());
+            conditional::expected_version(&if_match, current).map(Some)
+        }
+        None => Ok(None),
+    }
+}
+
+/// 対象が存在しないことによるエラーかどうか
+fn is_not_found(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<PokemonError>(),
+        Some(PokemonError::NotFound(_))
+    )
+}
+
+/// 版の不一致によるエラーかどうか
+fn is_version_mismatch(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<PokemonError>(),
+        Some(PokemonError::VersionMismatch { .. })
+    )
+}
+
+/// 論理削除された図鑑 No への登録に対する 409
+fn deleted_pokemon(detail: String) -> Problem {
+    Problem::new(StatusCode::CONFLICT, "pokemon_deleted", "Conflict").with_detail(detail)
+}
+
+/// 不正な値を含むリクエストに対する 400
+fn invalid_pokemon(detail: String) -> Problem {
+    Problem::new(StatusCode::BAD_REQUEST, "invalid_pokemon", "Bad Request").with_detail(detail)
+}
+
+/// 登録したポケモンを Location・ETag ヘッダー付きの 201 で返す
+fn created(format: Format, version: ApiVersion, pokemon: PokemonData) -> HttpResponse {
+    let mut builder = HttpResponse::Created();
+    builder
+        .insert_header((
+            header::LOCATION,
+            version.pokemon_location(*pokemon.get_number()),
+        ))
+        .insert_header(conditional::etag(*pokemon.get_version(), format));
+    format.respond(builder, &version.pokemon_body(&pokemon))
+}
+
+/// 更新したポケモンを ETag ヘッダー付きの 200 で返す
+fn updated(format: Format, version: ApiVersion, pokemon: PokemonData) -> HttpResponse {
+    let mut builder = HttpResponse::Ok();
+    builder.insert_header(conditional::etag(*pokemon.get_version(), format));
+    format.respond(builder, &version.pokemon_body(&pokemon))
+}
+
+#[utoipa::path(
+    tag = "pokemon",
+    request_body = PokemonRequest,
+    responses(
+        (status = 201, description = "登録したポケモン", body = PokemonData,
+            headers(("Location" = String), ("ETag" = String))),
+        (status = 400, description = "不正な値", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "認証されていない", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "権限がない", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "既に存在する、または論理削除されている", body = Problem, content_type = "application/problem+json"),
+        (status = 415, description = "対応していない Content-Type", body = Problem, content_type = "application/problem+json"),
+    ),
+    security(("bearer" = ["pokemon:write"]), ("api_key" = ["pokemon:write"]))
+)]
 #[post("/pokemon")]
 async fn post_pokemon(
+    user: AuthenticatedUser,
+    Accepted(format): Accepted,
+    version: ApiVersion,
     data: web::Data<RequestContext>,
-    request: Json<PokemonRequest>,
+    request: Body<PokemonRequest>,
 ) -> impl Responder {
-    let pokemon_application = PokemonRegisterService::new(data.pokemon_repository());
-    let data = PokemonData::new(request.of());
-    match pokemon_application.handle(data.clone()) {
-        Ok(_) => HttpResponse::Ok().body("SUCCESS Register Pokemon"),
+    log::info!("Register Pokemon requested by {}", user.subject);
+    if let Err(problem) = user.require(Permission::PokemonWrite) {
+        return problem.error_response();
+    }
+    let pokemon_application =
+        PokemonRegisterService::new(data.pokemon_repository()).with_event_bus(data.event_bus());
+    let pokemon = match request.of() {
+        Ok(pokemon) => pokemon,
+        Err(e) => return invalid_pokemon(e.to_string()).error_response(),
+    };
+    match pokemon_application.handle(PokemonData::new(pokemon)) {
+        Ok(pokemon) => created(format, version, pokemon),
+        Err(e)
+            if matches!(
+                e.downcast_ref::<PokemonError>(),
+                Some(PokemonError::AlreadyExists(_))
+            ) =>
+        {
+            Problem::new(StatusCode::CONFLICT, "already_exists", "Conflict")
+                .with_detail(e.to_string())
+                .error_response()
+        }
+        Err(e)
+            if matches!(
+                e.downcast_ref::<PokemonError>(),
+                Some(PokemonError::Deleted(_))
+            ) =>
+        {
+            deleted_pokemon(e.to_string()).error_response()
+        }
         Err(_) => {
             let response = ErrorResponse {
-                message: format!("FAILURE Register Pokemon: {:?}", data.clone()),
+                message: format!("FAILURE Register Pokemon: {:?}", request.0),
                 r#type: "get_pokemon_error".to_string(),
             };
             HttpResponse::InternalServerError().json(response)
@@ -35,30 +175,134 @@ async fn post_pokemon(
     }
 }
 
+#[utoipa::path(
+    tag = "pokemon",
+    params(("number" = i32, Path, description = "図鑑 No")),
+    responses(
+        (status = 200, description = "ポケモン", body = PokemonData,
+            headers(("ETag" = String), ("Last-Modified" = String), ("Cache-Control" = String))),
+        (status = 304, description = "キャッシュが最新"),
+        (status = 404, description = "存在しない、または論理削除されている", body = Problem, content_type = "application/problem+json"),
+        (status = 406, description = "対応していない Accept", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "取得に失敗した", body = ErrorResponse),
+    )
+)]
 #[get("/pokemon/{number}")]
 async fn get_pokemon(
+    _access: ReadAccess,
+    Accepted(format): Accepted,
+    version: ApiVersion,
     data: web::Data<RequestContext>,
     path_params: web::Path<(i32,)>,
+    req: HttpRequest,
 ) -> impl Responder {
     let pokemon_application = PokemonGetService::new(data.pokemon_repository());
-    let no = path_params.into_inner().0.into();
+    let no = path_params.into_inner().0;
     match pokemon_application.handle(no) {
-        Ok(pokemon) => HttpResponse::Ok().json(pokemon),
+        Ok(pokemon) => conditional::cached(
+            &req,
+            conditional::etag(*pokemon.get_version(), format),
+            *pokemon.get_updated_at(),
+            conditional::cache_control(&CONFIG).as_deref(),
+            format,
+            &version.pokemon_body(&pokemon),
+        ),
+        Err(e) if is_not_found(&e) => Problem::not_found(e.to_string()).error_response(),
         Err(_) => {
             let response = ErrorResponse {
                 message: format!("FAILURE Get Pokemon: no {:?}", no),
-                r#type: "get_pokemon_list_error".to_string(),
+                r#type: "get_pokemon_error".to_string(),
             };
             HttpResponse::InternalServerError().json(response)
         }
     }
 }
 
+/// NDJSON のメディアタイプ
+const NDJSON: &str = "application/x-ndjson";
+
+/// NDJSON の送信待ちとして保持する最大行数
+const NDJSON_BUFFER: usize = 64;
+
+/// 一覧を 1 行 1 件の NDJSON で返す。
+/// データベースのカーソルから読み込んだ行を順に送信するため、件数に関わらず使用するメモリは一定になる。
+fn stream_pokemon_list(data: &RequestContext, version: ApiVersion) -> HttpResponse {
+    let repository = data.pokemon_repository();
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<std::io::Result<web::Bytes>>(NDJSON_BUFFER);
+    // Diesel の呼び出しはブロッキングするため、専用のスレッドで読み込む
+    actix_web::rt::task::spawn_blocking(move || {
+        let result = PokemonListService::new(repository).handle_each(|pokemon| {
+            let mut line = serde_json::to_vec(&version.represent(pokemon))?;
+            line.push(b'\n');
+            // 送信先がなくなった場合（クライアントの切断）は読み込みを中断する
+            tx.blocking_send(Ok(web::Bytes::from(line)))
+                .map_err(|_| anyhow::anyhow!("client disconnected"))
+        });
+        match result {
+            Ok(_) => {}
+            Err(_) if tx.is_closed() => log::info!("Stream Pokemon List aborted by client"),
+            Err(e) => {
+                log::error!("Stream Pokemon List failed: {:?}", e);
+                // ステータスは送信済みのため、応答を途中で打ち切って失敗を伝える
+                let _ = tx.blocking_send(Err(std::io::Error::other(e.to_string())));
+            }
+        }
+    });
+    let stream = futures_util::stream::poll_fn(move |cx| rx.poll_recv(cx));
+    HttpResponse::Ok()
+        .content_type(NDJSON)
+        .insert_header((header::VARY, "Accept"))
+        .streaming(stream)
+}
+
+#[utoipa::path(
+    tag = "pokemon",
+    responses(
+        (status = 200, description = "ポケモンの一覧。`Accept: application/x-ndjson` の場合は 1 行 1 件で返す", content(
+            (Vec<PokemonData> = "application/json"),
+            (PokemonData = "application/x-ndjson"),
+        )),
+        (status = 304, description = "キャッシュが最新"),
+        (status = 406, description = "対応していない Accept", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "取得に失敗した", body = ErrorResponse),
+    )
+)]
 #[get("/pokemon")]
-async fn get_pokemon_list(data: web::Data<RequestContext>) -> impl Responder {
+async fn get_pokemon_list(
+    _access: ReadAccess,
+    version: ApiVersion,
+    data: web::Data<RequestContext>,
+    req: HttpRequest,
+) -> impl Responder {
+    // NDJSON は、他の形式より優先して指定されている場合に選ぶ
+    let ndjson = negotiation::quality(&req, NDJSON);
+    let format = match Format::from_accept(&req) {
+        Ok(format) if ndjson <= 0.0 || negotiation::quality(&req, format.media_type()) > ndjson => {
+            format
+        }
+        _ if ndjson > 0.0 => return stream_pokemon_list(&data, version),
+        Ok(format) => format,
+        Err(problem) => return problem.error_response(),
+    };
     let pokemon_application = PokemonListService::new(data.pokemon_repository());
     match pokemon_application.handle() {
-        Ok(pokemon) => HttpResponse::Ok().json(pokemon),
+        Ok(pokemon) => {
+            // 一覧は版を持たないため、本文のハッシュ値を ETag とする。
+            // 削除では最も新しい更新日時が進まないため、Last-Modified は返さない
+            let list = version.pokemon_list_body(&pokemon);
+            let body = match format.encode(&list) {
+                Ok(body) => body,
+                Err(problem) => return problem.error_response(),
+            };
+            conditional::cached(
+                &req,
+                conditional::weak_etag(&body),
+                None,
+                conditional::cache_control(&CONFIG).as_deref(),
+                format,
+                &list,
+            )
+        }
         Err(_) => {
             let response = ErrorResponse {
                 message: "FAILURE Get Pokemon List".to_string(),
@@ -69,38 +313,201 @@ async fn get_pokemon_list(data: web::Data<RequestContext>) -> impl Responder {
     }
 }
 
+#[utoipa::path(
+    tag = "pokemon",
+    params(("number" = i32, Path, description = "図鑑 No")),
+    request_body = PokemonRequest,
+    responses(
+        (status = 200, description = "置き換えたポケモン", body = PokemonData, headers(("ETag" = String))),
+        (status = 201, description = "登録したポケモン", body = PokemonData,
+            headers(("Location" = String), ("ETag" = String))),
+        (status = 400, description = "不正な値、またはパスと本文の図鑑 No の不一致", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "論理削除されている。復元してから置き換える", body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "If-Match の版が一致しない", body = Problem, content_type = "application/problem+json"),
+        (status = 428, description = "If-Match が必要", body = Problem, content_type = "application/problem+json"),
+    ),
+    security(("bearer" = ["pokemon:write"]), ("api_key" = ["pokemon:write"]))
+)]
 #[put("/pokemon/{number}")]
 async fn update_pokemon(
+    user: AuthenticatedUser,
+    Accepted(format): Accepted,
+    version: ApiVersion,
     data: web::Data<RequestContext>,
     path_params: web::Path<(i32,)>,
-    request: Json<PokemonRequest>,
+    req: HttpRequest,
+    request: Body<PokemonRequest>,
 ) -> impl Responder {
-    let pokemon_application = PokemonUpdateService::new(data.pokemon_repository());
-    let no = path_params.into_inner().0.into();
-    let mut update_command = PokemonUpdateCommand::new(no);
-    update_command.set_name(Some(request.of().name.into()));
-    update_command.set_types(Some(request.of().types.into()));
-    match pokemon_application.handle(update_command) {
-        Ok(_) => HttpResponse::Ok().body(format!("SUCCESS Update Pokemon: no {}", no)),
+    let pokemon_application =
+        PokemonUpsertService::new(data.pokemon_repository()).with_event_bus(data.event_bus());
+    let no = path_params.into_inner().0;
+    log::info!("Update Pokemon requested by {}: no {}", user.subject, no);
+    if let Err(problem) = user.require(Permission::PokemonWrite) {
+        return problem.error_response();
+    }
+    if request.number != no {
+        return Problem::new(StatusCode::BAD_REQUEST, "number_mismatch", "Bad Request")
+            .with_detail(format!(
+                "本文の図鑑 No がパスと一致しません: path {}, body {}",
+                no, request.number
+            ))
+            .error_response();
+    }
+    // PUT は存在しなければ登録、存在すれば全項目の置き換えとして扱う
+    let mut upsert_command =
+        PokemonUpsertCommand::new(no, request.name.clone(), request.types.clone());
+    match expected_version(&req, &data, no) {
+        Ok(version) => upsert_command.set_expected_version(version),
+        Err(problem) => return problem.error_response(),
+    };
+    match pokemon_application.handle(upsert_command) {
+        Ok(PokemonUpserted::Created(pokemon)) => created(format, version, pokemon),
+        Ok(PokemonUpserted::Replaced(pokemon)) => updated(format, version, pokemon),
+        Err(e) => match e.downcast_ref::<PokemonError>() {
+            Some(PokemonError::InvalidValue(message)) => {
+                invalid_pokemon(message.clone()).error_response()
+            }
+            Some(PokemonError::VersionMismatch { .. }) => {
+                Problem::precondition_failed(e.to_string()).error_response()
+            }
+            Some(PokemonError::Deleted(_)) => deleted_pokemon(e.to_string()).error_response(),
+            _ => {
+                let response = ErrorResponse {
+                    message: format!("FAILURE Update Pokemon: no {}", no),
+                    r#type: "update_pokemon_error".to_string(),
+                };
+                HttpResponse::InternalServerError().json(response)
+            }
+        },
+    }
+}
+
+#[utoipa::path(
+    tag = "pokemon",
+    params(("number" = i32, Path, description = "図鑑 No")),
+    request_body(description = "JSON Merge Patch または JSON Patch", content(
+        ("application/merge-patch+json"),
+        ("application/json-patch+json"),
+    )),
+    responses(
+        (status = 200, description = "更新したポケモン", body = PokemonData, headers(("ETag" = String))),
+        (status = 400, description = "不正なパス", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "存在しない", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "パスを適用できない", body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "If-Match の版が一致しない", body = Problem, content_type = "application/problem+json"),
+        (status = 415, description = "対応していない Content-Type", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "適用結果が不正", body = Problem, content_type = "application/problem+json"),
+    ),
+    security(("bearer" = ["pokemon:write"]), ("api_key" = ["pokemon:write"]))
+)]
+#[patch("/pokemon/{number}")]
+async fn patch_pokemon(
+    user: AuthenticatedUser,
+    Accepted(accepted): Accepted,
+    version: ApiVersion,
+    data: web::Data<RequestContext>,
+    path_params: web::Path<(i32,)>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> impl Responder {
+    let no = path_params.into_inner().0;
+    log::info!("Patch Pokemon requested by {}: no {}", user.subject, no);
+    if let Err(problem) = user.require(Permission::PokemonWrite) {
+        return problem.error_response();
+    }
+    let format = match PatchFormat::from_request(&req) {
+        Ok(format) => format,
+        Err(problem) => return problem.error_response(),
+    };
+    let current = match PokemonGetService::new(data.pokemon_repository()).handle(no) {
+        Ok(current) => current,
+        Err(e) if is_not_found(&e) => return Problem::not_found(e.to_string()).error_response(),
         Err(_) => {
-            let response = ErrorResponse {
-                message: format!("FAILURE Update Pokemon: no {}", no),
-                r#type: "update_pokemon_error".to_string(),
-            };
-            HttpResponse::InternalServerError().json(response)
+            return Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "patch_pokemon_error",
+                "FAILURE Patch Pokemon",
+            )
+            .with_detail(format!("no {}", no))
+            .error_response()
+        }
+    };
+    match conditional::if_match(&req, CONFIG.require_if_match) {
+        Ok(Some(if_match)) => {
+            if let Err(problem) =
+                conditional::expected_version(&if_match, Some(*current.get_version()))
+            {
+                return problem.error_response();
+            }
         }
+        Ok(None) => {}
+        Err(problem) => return problem.error_response(),
+    }
+    let mut update_command = match patch::apply(&current, format, &body) {
+        Ok(command) => command,
+        Err(problem) => return problem.error_response(),
+    };
+    // パッチは取得した時点の値に対して適用しているため、その版から変わっていないことを前提に更新する
+    update_command.set_expected_version(Some(*current.get_version()));
+    let pokemon_application =
+        PokemonUpdateService::new(data.pokemon_repository()).with_event_bus(data.event_bus());
+    match pokemon_application.handle(update_command) {
+        Ok(pokemon) => updated(accepted, version, PokemonData::new(pokemon)),
+        Err(e) if is_version_mismatch(&e) => {
+            Problem::precondition_failed(e.to_string()).error_response()
+        }
+        Err(_) => Problem::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "patch_pokemon_error",
+            "FAILURE Patch Pokemon",
+        )
+        .with_detail(format!("no {}", no))
+        .error_response(),
     }
 }
 
+#[utoipa::path(
+    tag = "pokemon",
+    params(("number" = i32, Path, description = "図鑑 No")),
+    responses(
+        (status = 204, description = "削除した"),
+        (status = 404, description = "存在しない", body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "If-Match の版が一致しない", body = Problem, content_type = "application/problem+json"),
+    ),
+    security(("bearer" = ["pokemon:delete"]), ("api_key" = ["pokemon:delete"]))
+)]
 #[delete("/pokemon/{number}")]
 async fn delete_pokemon(
+    user: AuthenticatedUser,
     data: web::Data<RequestContext>,
     path_params: web::Path<(i32,)>,
+    req: HttpRequest,
 ) -> impl Responder {
-    let pokemon_application = PokemonDeleteService::new(data.pokemon_repository());
-    let no = path_params.into_inner().0.into();
-    match pokemon_application.handle(no) {
-        Ok(_) => HttpResponse::Ok().body(format!("SUCCESS Delete Pokemon: no {}", no)),
+    let pokemon_application = PokemonDeleteService::new(data.pokemon_repository())
+        .with_soft_delete(CONFIG.soft_delete)
+        .with_event_bus(data.event_bus());
+    let no = path_params.into_inner().0;
+    log::info!("Delete Pokemon requested by {}: no {}", user.subject, no);
+    if let Err(problem) = user.require(Permission::PokemonDelete) {
+        return problem.error_response();
+    }
+    let version = match expected_version(&req, &data, no) {
+        Ok(version) => version,
+        Err(problem) => return problem.error_response(),
+    };
+    match pokemon_application.handle(no, version) {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) if is_version_mismatch(&e) => {
+            Problem::precondition_failed(e.to_string()).error_response()
+        }
+        Err(e) if is_not_found(&e) => {
+            // 冪等な削除として扱う設定の場合は、存在しなくても削除済みとして成功にする
+            if CONFIG.delete_idempotent {
+                HttpResponse::NoContent().finish()
+            } else {
+                Problem::not_found(e.to_string()).error_response()
+            }
+        }
         Err(_) => {
             let response = ErrorResponse {
                 message: format!("FAILURE Delete Pokemon: no {}", no),
@@ -111,7 +518,549 @@ async fn delete_pokemon(
     }
 }
 
+#[utoipa::path(
+    tag = "pokemon",
+    params(("number" = i32, Path, description = "図鑑 No")),
+    responses(
+        (status = 200, description = "復元したポケモン", body = PokemonData, headers(("ETag" = String))),
+        (status = 404, description = "論理削除されたポケモンが存在しない", body = Problem, content_type = "application/problem+json"),
+    ),
+    security(("bearer" = ["pokemon:delete"]), ("api_key" = ["pokemon:delete"]))
+)]
+#[post("/pokemon/{number}/restore")]
+async fn restore_pokemon(
+    user: AuthenticatedUser,
+    Accepted(format): Accepted,
+    version: ApiVersion,
+    data: web::Data<RequestContext>,
+    path_params: web::Path<(i32,)>,
+) -> impl Responder {
+    let pokemon_application =
+        PokemonRestoreService::new(data.pokemon_repository()).with_event_bus(data.event_bus());
+    let no = path_params.into_inner().0;
+    log::info!("Restore Pokemon requested by {}: no {}", user.subject, no);
+    if let Err(problem) = user.require(Permission::PokemonDelete) {
+        return problem.error_response();
+    }
+    match pokemon_application.handle(no) {
+        Ok(pokemon) => updated(format, version, pokemon),
+        Err(e) if is_not_found(&e) => {
+            Problem::not_found(format!("削除済みのポケモンが存在しません: no {}", no))
+                .error_response()
+        }
+        Err(_) => Problem::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "restore_pokemon_error",
+            "FAILURE Restore Pokemon",
+        )
+        .with_detail(format!("no {}", no))
+        .error_response(),
+    }
+}
+
+/// 一括処理の 1 件分の結果
+#[derive(Serialize, ToSchema)]
+struct BatchItemResponse {
+    index: usize,
+    op: &'static str,
+    number: i32,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pokemon: Option<PokemonRepresentation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<Problem>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct BatchResponse {
+    results: Vec<BatchItemResponse>,
+}
+
+/// 一括処理で受け付ける操作の最大件数
+const BATCH_MAX_OPERATIONS: usize = 1000;
+
+/// 一括処理で失敗した操作のエラーを Problem に変換する
+fn batch_problem(e: &anyhow::Error) -> Problem {
+    match e.downcast_ref::<PokemonError>() {
+        Some(PokemonError::InvalidValue(message)) => invalid_pokemon(message.clone()),
+        Some(PokemonError::NotFound(_)) => Problem::not_found(e.to_string()),
+        Some(PokemonError::AlreadyExists(_)) => {
+            Problem::new(StatusCode::CONFLICT, "already_exists", "Conflict")
+                .with_detail(e.to_string())
+        }
+        Some(PokemonError::VersionMismatch { .. }) => Problem::precondition_failed(e.to_string()),
+        Some(PokemonError::Deleted(_)) => deleted_pokemon(e.to_string()),
+        None => {
+            log::error!("Batch operation failed: {:?}", e);
+            Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "batch_pokemon_error",
+                "FAILURE Batch Pokemon",
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    tag = "pokemon",
+    request_body = PokemonBatchRequest,
+    responses(
+        (status = 200, description = "全ての操作が成功した", body = BatchResponse),
+        (status = 207, description = "best_effort で一部の操作が失敗した", body = BatchResponse),
+        (status = "4XX", description = "atomic で失敗した操作のステータス", body = BatchResponse),
+        (status = 413, description = "操作の件数が多すぎる", body = Problem, content_type = "application/problem+json"),
+    ),
+    security(("bearer" = ["pokemon:write"]), ("api_key" = ["pokemon:write"]))
+)]
+#[post("/pokemon:batch")]
+async fn batch_pokemon(
+    user: AuthenticatedUser,
+    Accepted(format): Accepted,
+    version: ApiVersion,
+    data: web::Data<RequestContext>,
+    request: Body<PokemonBatchRequest>,
+) -> impl Responder {
+    let request = request.0;
+    log::info!(
+        "Batch Pokemon requested by {}: {} operations",
+        user.subject,
+        request.operations.len()
+    );
+    if let Err(problem) = user.require(Permission::PokemonWrite) {
+        return problem.error_response();
+    }
+    let has_delete = request
+        .operations
+        .iter()
+        .any(|operation| matches!(operation, PokemonOperationRequest::Delete { .. }));
+    if has_delete {
+        if let Err(problem) = user.require(Permission::PokemonDelete) {
+            return problem.error_response();
+        }
+    }
+    if request.operations.len() > BATCH_MAX_OPERATIONS {
+        return Problem::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "too_many_operations",
+            "Payload Too Large",
+        )
+        .with_detail(format!(
+            "操作は {} 件以下にしてください: {} 件",
+            BATCH_MAX_OPERATIONS,
+            request.operations.len()
+        ))
+        .error_response();
+    }
+
+    let atomic = request.mode == BatchMode::Atomic;
+    let pokemon_application = PokemonBatchService::new(data.pokemon_repository())
+        .with_soft_delete(CONFIG.soft_delete)
+        .with_event_bus(data.event_bus());
+    let commands = request.operations.iter().map(|o| o.of()).collect();
+    let outcomes = match pokemon_application.handle(commands, atomic) {
+        Ok(outcomes) => outcomes,
+        Err(e) => return batch_problem(&e).error_response(),
+    };
+
+    let results = request
+        .operations
+        .iter()
+        .zip(outcomes)
+        .enumerate()
+        .map(|(index, (operation, outcome))| {
+            let (status, pokemon, error) = match outcome {
+                PokemonBatchOutcome::Created(pokemon) => (StatusCode::CREATED, Some(pokemon), None),
+                PokemonBatchOutcome::Updated(pokemon) => (StatusCode::OK, Some(pokemon), None),
+                PokemonBatchOutcome::Deleted => (StatusCode::NO_CONTENT, None, None),
+                PokemonBatchOutcome::Failed(e) => {
+                    let problem = batch_problem(&e);
+                    (problem.status_code(), None, Some(problem))
+                }
+                // 他の操作の失敗により取り消された
+                PokemonBatchOutcome::Skipped => (
+                    StatusCode::FAILED_DEPENDENCY,
+                    None,
+                    Some(
+                        Problem::new(
+                            StatusCode::FAILED_DEPENDENCY,
+                            "batch_aborted",
+                            "Failed Dependency",
+                        )
+                        .with_detail("他の操作が失敗したため取り消されました"),
+                    ),
+                ),
+            };
+            BatchItemResponse {
+                index,
+                op: operation.op(),
+                number: operation.number(),
+                status: status.as_u16(),
+                pokemon: pokemon.map(|pokemon| version.represent(pokemon)),
+                error,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // 全て成功した場合は 200、all-or-nothing で失敗した場合は失敗した操作のステータス、
+    // 一部だけ失敗した場合は 207 を返す
+    let failed = results.iter().find(|result| {
+        result.error.is_some() && result.status != StatusCode::FAILED_DEPENDENCY.as_u16()
+    });
+    let status = match failed {
+        None => StatusCode::OK,
+        Some(result) if atomic => {
+            StatusCode::from_u16(result.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+        Some(_) => StatusCode::MULTI_STATUS,
+    };
+    format.respond(HttpResponse::build(status), &BatchResponse { results })
+}
+
+#[utoipa::path(
+    tag = "pokemon",
+    params(PokemonExportQuery),
+    responses(
+        (status = 200, description = "`number,name,types` の CSV", content_type = "text/csv", body = String),
+        (status = 400, description = "対応していない形式", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "取得に失敗した", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[get("/pokemon/export")]
+async fn export_pokemon(
+    _access: ReadAccess,
+    data: web::Data<RequestContext>,
+    query: web::Query<PokemonExportQuery>,
+) -> impl Responder {
+    let format = query.format.as_deref().unwrap_or("csv");
+    if !format.eq_ignore_ascii_case("csv") {
+        return Problem::new(StatusCode::BAD_REQUEST, "unsupported_format", "Bad Request")
+            .with_detail(format!("対応していない形式です: {:?}", format))
+            .error_response();
+    }
+    let repository = data.pokemon_repository();
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<std::io::Result<web::Bytes>>(NDJSON_BUFFER);
+    // Diesel の呼び出しはブロッキングするため、専用のスレッドでカーソルから読み込む。
+    // 見出し行は最初の行を読み込めてから送り、読み込む前の失敗はステータスで伝える
+    actix_web::rt::task::spawn_blocking(move || {
+        let mut started = false;
+        let send = |row: Vec<u8>| {
+            tx.blocking_send(Ok(web::Bytes::from(row)))
+                .map_err(|_| anyhow::anyhow!("client disconnected"))
+        };
+        let result = PokemonListService::new(repository).handle_each(|pokemon| {
+            if !started {
+                started = true;
+                send(csv_format::header())?;
+            }
+            send(csv_format::record(&pokemon))
+        });
+        // 1 件も登録されていない場合は見出し行だけを返す
+        let result = result.and_then(|_| {
+            if started {
+                Ok(())
+            } else {
+                send(csv_format::header())
+            }
+        });
+        match result {
+            Ok(_) => {}
+            Err(_) if tx.is_closed() => log::info!("Export Pokemon aborted by client"),
+            Err(e) => {
+                log::error!("Export Pokemon failed: {:?}", e);
+                // 送信済みの場合は応答を途中で打ち切って失敗を伝える
+                let _ = tx.blocking_send(Err(std::io::Error::other(e.to_string())));
+            }
+        }
+    });
+    let first = match rx.recv().await {
+        Some(Ok(first)) => first,
+        _ => {
+            return Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "export_pokemon_error",
+                "FAILURE Export Pokemon",
+            )
+            .error_response()
+        }
+    };
+    let rest = futures_util::stream::poll_fn(move |cx| rx.poll_recv(cx));
+    HttpResponse::Ok()
+        .content_type(csv_format::TEXT_CSV)
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"pokemon.csv\"",
+        ))
+        .streaming(futures_util::StreamExt::chain(
+            futures_util::stream::iter([Ok(first)]),
+            rest,
+        ))
+}
+
+#[utoipa::path(
+    tag = "pokemon",
+    params(PokemonImportQuery),
+    request_body(description = "`number,name,types` の CSV", content_type = "text/csv", content = String),
+    responses(
+        (status = 200, description = "検証結果", body = PokemonImportReport),
+        (status = 422, description = "不正な行があるため取り込まなかった", body = PokemonImportReport),
+        (status = 400, description = "不正な CSV", body = Problem, content_type = "application/problem+json"),
+        (status = 415, description = "対応していない Content-Type", body = Problem, content_type = "application/problem+json"),
+    ),
+    security(("bearer" = ["pokemon:write"]), ("api_key" = ["pokemon:write"]))
+)]
+#[post("/pokemon/import")]
+async fn import_pokemon(
+    user: AuthenticatedUser,
+    Accepted(format): Accepted,
+    data: web::Data<RequestContext>,
+    query: web::Query<PokemonImportQuery>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> impl Responder {
+    log::info!(
+        "Import Pokemon requested by {}: dry_run {}",
+        user.subject,
+        query.dry_run
+    );
+    if let Err(problem) = user.require(Permission::PokemonWrite) {
+        return problem.error_response();
+    }
+    let rows = match csv_format::require_csv(&req).and_then(|_| csv_format::read(&body)) {
+        Ok(rows) => rows,
+        Err(problem) => return problem.error_response(),
+    };
+    let pokemon_application =
+        PokemonImportService::new(data.pokemon_repository()).with_event_bus(data.event_bus());
+    match pokemon_application.handle(rows, query.dry_run) {
+        // 不正な行があれば何も取り込まず、検証結果を 422 で返す
+        Ok(report) if !report.get_dry_run() && *report.get_invalid() > 0 => {
+            format.respond(HttpResponse::UnprocessableEntity(), &report)
+        }
+        Ok(report) => format.respond(HttpResponse::Ok(), &report),
+        Err(e)
+            if matches!(
+                e.downcast_ref::<PokemonError>(),
+                Some(PokemonError::Deleted(_))
+            ) =>
+        {
+            deleted_pokemon(e.to_string()).error_response()
+        }
+        Err(e) => {
+            log::error!("Import Pokemon failed: {:?}", e);
+            Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "import_pokemon_error",
+                "FAILURE Import Pokemon",
+            )
+            .error_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    tag = "admin",
+    request_body = ApiKeyRequest,
+    responses(
+        (status = 201, description = "発行した API キー。平文のキーはこのレスポンスでしか返さない", body = IssuedApiKey),
+        (status = 400, description = "不正なスコープなど", body = Problem, content_type = "application/problem+json"),
+    ),
+    security(("bearer" = ["admin"]))
+)]
+#[post("/admin/api-keys")]
+async fn post_api_key(
+    user: AuthenticatedUser,
+    data: web::Data<RequestContext>,
+    request: Json<ApiKeyRequest>,
+) -> impl Responder {
+    if let Err(problem) = user.require(Permission::Admin) {
+        return problem.error_response();
+    }
+    let api_key_application = ApiKeyIssueService::new(data.api_key_repository());
+    match api_key_application.handle(request.of()) {
+        Ok(issued) => {
+            log::info!(
+                "Issue API Key requested by {}: id {}",
+                user.subject,
+                issued.api_key.get_id()
+            );
+            HttpResponse::Created().json(issued)
+        }
+        Err(e) => Problem::new(
+            StatusCode::BAD_REQUEST,
+            "issue_api_key_error",
+            "FAILURE Issue API Key",
+        )
+        .with_detail(e.to_string())
+        .error_response(),
+    }
+}
+
+#[utoipa::path(
+    tag = "admin",
+    params(("id" = i32, Path, description = "API キーの ID")),
+    responses(
+        (status = 204, description = "失効した"),
+        (status = 404, description = "存在しない", body = Problem, content_type = "application/problem+json"),
+    ),
+    security(("bearer" = ["admin"]))
+)]
+#[delete("/admin/api-keys/{id}")]
+async fn delete_api_key(
+    user: AuthenticatedUser,
+    data: web::Data<RequestContext>,
+    path_params: web::Path<(i32,)>,
+) -> impl Responder {
+    if let Err(problem) = user.require(Permission::Admin) {
+        return problem.error_response();
+    }
+    let api_key_application = ApiKeyRevokeService::new(data.api_key_repository());
+    let id = path_params.into_inner().0;
+    log::info!("Revoke API Key requested by {}: id {}", user.subject, id);
+    match api_key_application.handle(id) {
+        Ok(Some(_)) => HttpResponse::NoContent().finish(),
+        Ok(None) => {
+            Problem::not_found(format!("API キーが存在しません: id {}", id)).error_response()
+        }
+        Err(_) => Problem::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "revoke_api_key_error",
+            "FAILURE Revoke API Key",
+        )
+        .error_response(),
+    }
+}
+
+#[utoipa::path(
+    tag = "admin",
+    request_body = WebhookRequest,
+    responses(
+        (status = 201, description = "登録した Webhook。署名用のシークレットはこのレスポンスでしか返さない", body = RegisteredWebhook,
+            headers(("Location" = String))),
+        (status = 400, description = "不正な URL やイベント", body = Problem, content_type = "application/problem+json"),
+    ),
+    security(("bearer" = ["admin"]))
+)]
+#[post("/webhooks")]
+async fn post_webhook(
+    user: AuthenticatedUser,
+    data: web::Data<RequestContext>,
+    request: Json<WebhookRequest>,
+) -> impl Responder {
+    if let Err(problem) = user.require(Permission::Admin) {
+        return problem.error_response();
+    }
+    let webhook_application = WebhookRegisterService::new(data.webhook_repository());
+    match webhook_application.handle(request.of()) {
+        Ok(registered) => {
+            log::info!(
+                "Register Webhook requested by {}: id {}",
+                user.subject,
+                registered.webhook.get_id()
+            );
+            HttpResponse::Created()
+                .insert_header((
+                    header::LOCATION,
+                    format!("/webhooks/{}", registered.webhook.get_id()),
+                ))
+                .json(registered)
+        }
+        Err(e) => Problem::new(
+            StatusCode::BAD_REQUEST,
+            "register_webhook_error",
+            "FAILURE Register Webhook",
+        )
+        .with_detail(e.to_string())
+        .error_response(),
+    }
+}
+
+#[utoipa::path(
+    tag = "admin",
+    params(("id" = i32, Path, description = "Webhook の ID")),
+    responses(
+        (status = 204, description = "削除した"),
+        (status = 404, description = "存在しない", body = Problem, content_type = "application/problem+json"),
+    ),
+    security(("bearer" = ["admin"]))
+)]
+#[delete("/webhooks/{id}")]
+async fn delete_webhook(
+    user: AuthenticatedUser,
+    data: web::Data<RequestContext>,
+    path_params: web::Path<(i32,)>,
+) -> impl Responder {
+    if let Err(problem) = user.require(Permission::Admin) {
+        return problem.error_response();
+    }
+    let webhook_application = WebhookDeleteService::new(data.webhook_repository());
+    let id = path_params.into_inner().0;
+    log::info!("Delete Webhook requested by {}: id {}", user.subject, id);
+    match webhook_application.handle(id) {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => {
+            Problem::not_found(format!("Webhook が存在しません: id {}", id)).error_response()
+        }
+        Err(_) => Problem::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "delete_webhook_error",
+            "FAILURE Delete Webhook",
+        )
+        .error_response(),
+    }
+}
+
+#[utoipa::path(
+    tag = "admin",
+    params(("id" = i32, Path, description = "Webhook の ID")),
+    responses(
+        (status = 200, description = "直近 100 件の送信の記録（新しい順）。再送は 1 回ごとに記録する", body = Vec<WebhookDeliveryData>),
+        (status = 404, description = "存在しない", body = Problem, content_type = "application/problem+json"),
+    ),
+    security(("bearer" = ["admin"]))
+)]
+#[get("/webhooks/{id}/deliveries")]
+async fn get_webhook_deliveries(
+    user: AuthenticatedUser,
+    data: web::Data<RequestContext>,
+    path_params: web::Path<(i32,)>,
+) -> impl Responder {
+    if let Err(problem) = user.require(Permission::Admin) {
+        return problem.error_response();
+    }
+    let webhook_application = WebhookDeliveryListService::new(data.webhook_repository());
+    let id = path_params.into_inner().0;
+    match webhook_application.handle(id) {
+        Ok(Some(deliveries)) => HttpResponse::Ok().json(deliveries),
+        Ok(None) => {
+            Problem::not_found(format!("Webhook が存在しません: id {}", id)).error_response()
+        }
+        Err(_) => Problem::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "get_webhook_deliveries_error",
+            "FAILURE Get Webhook Deliveries",
+        )
+        .error_response(),
+    }
+}
+
+#[utoipa::path(tag = "system", responses((status = 200, description = "稼働している")))]
 #[get("/health")]
 async fn health() -> impl Responder {
     HttpResponse::Ok().body("Ok")
 }
+
+#[utoipa::path(
+    tag = "system",
+    responses(
+        (status = 200, description = "リクエストを受け付けられる"),
+        (status = 503, description = "停止処理中"),
+    )
+)]
+#[get("/ready")]
+async fn ready(readiness: web::Data<Readiness>) -> impl Responder {
+    if readiness.is_ready() {
+        HttpResponse::Ok().body("Ok")
+    } else {
+        HttpResponse::ServiceUnavailable().body("Shutting down")
+    }
+}
@@ -1,3 +1,17 @@
+pub mod auth;
+pub mod conditional;
+pub mod cors;
+pub mod csv_format;
+pub mod events;
+pub mod graphql;
 pub mod handlers;
+pub mod negotiation;
+pub mod openapi;
+pub mod patch;
+pub mod problem;
+pub mod rate_limit;
 pub mod request;
 pub mod router;
+pub mod shutdown;
+pub mod versioning;
+pub mod websocket;
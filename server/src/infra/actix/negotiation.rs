@@ -0,0 +1,326 @@
+//! Accept / Content-Type によるリクエスト・レスポンスの形式の切り替え。
+//! JSON のほか、MessagePack・CBOR・YAML に対応する。
+
+use crate::infra::actix::problem::Problem;
+use actix_web::dev::Payload;
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, HttpResponseBuilder, ResponseError};
+use futures_util::future::{ready, LocalBoxFuture, Ready};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// 本文の形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    MessagePack,
+    Cbor,
+    Yaml,
+}
+
+impl Format {
+    /// 対応する形式。Accept で優先度が同じ場合はこの順に選ぶ。
+    const ALL: [Format; 4] = [
+        Format::Json,
+        Format::MessagePack,
+        Format::Cbor,
+        Format::Yaml,
+    ];
+
+    /// レスポンスの Content-Type
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::MessagePack => "application/msgpack",
+            Format::Cbor => "application/cbor",
+            Format::Yaml => "application/yaml",
+        }
+    }
+
+    /// ETag で表現を区別するために付加する文字列。JSON は付加しない。
+    pub fn etag_suffix(&self) -> Option<&'static str> {
+        match self {
+            Format::Json => None,
+            Format::MessagePack => Some("msgpack"),
+            Format::Cbor => Some("cbor"),
+            Format::Yaml => Some("yaml"),
+        }
+    }
+
+    /// メディアタイプから形式を判定する。別名として広く使われているものも受け付ける。
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type.to_ascii_lowercase().as_str() {
+            "application/json" => Some(Format::Json),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(Format::MessagePack)
+            }
+            "application/cbor" => Some(Format::Cbor),
+            "application/yaml" | "application/x-yaml" | "text/yaml" => Some(Format::Yaml),
+            _ => None,
+        }
+    }
+
+    fn supported() -> String {
+        Self::ALL
+            .iter()
+            .map(|f| f.media_type())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Accept ヘッダーから応答の形式を決める。
+    /// ヘッダーがない場合は JSON とし、対応する形式がない場合は 406 とする。
+    pub fn from_accept(req: &HttpRequest) -> Result<Self, Problem> {
+        let ranges = accept_ranges(req);
+        if ranges.is_empty() {
+            return Ok(Format::Json);
+        }
+        let rejected = |format: &Format| {
+            ranges
+                .iter()
+                .any(|(m, q)| *q <= 0.0 && Self::from_media_type(m) == Some(*format))
+        };
+        for (media_type, quality) in ranges.iter() {
+            if *quality <= 0.0 {
+                continue;
+            }
+            let format = match media_type.as_str() {
+                "*/*" | "application/*" => Self::ALL.iter().copied().find(|f| !rejected(f)),
+                _ => Self::from_media_type(media_type),
+            };
+            if let Some(format) = format {
+                return Ok(format);
+            }
+        }
+        Err(Problem::new(
+            StatusCode::NOT_ACCEPTABLE,
+            "not_acceptable",
+            "Not Acceptable",
+        )
+        .with_detail(format!("対応している形式は {} です", Self::supported())))
+    }
+
+    /// Content-Type ヘッダーからリクエストの本文の形式を判定する。
+    /// 対応していない場合やヘッダーがない場合は 415 とする。
+    pub fn from_content_type(req: &HttpRequest) -> Result<Self, Problem> {
+        let content_type = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(';').next())
+            .map(|v| v.trim().to_string())
+            .unwrap_or_default();
+        Self::from_media_type(&content_type).ok_or_else(|| {
+            Problem::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "unsupported_media_type",
+                "Unsupported Media Type",
+            )
+            .with_detail(format!(
+                "対応していない Content-Type です: {:?}（対応している形式は {} です）",
+                content_type,
+                Self::supported()
+            ))
+        })
+    }
+
+    /// 値をこの形式で符号化する
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Problem> {
+        let result = match self {
+            Format::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            // フィールド名を含めて符号化し、クライアントが項目の順序に依存しないようにする
+            Format::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+            Format::Cbor => {
+                let mut buf = vec![];
+                ciborium::ser::into_writer(value, &mut buf)
+                    .map(|_| buf)
+                    .map_err(|e| e.to_string())
+            }
+            Format::Yaml => serde_yaml::to_string(value)
+                .map(String::into_bytes)
+                .map_err(|e| e.to_string()),
+        };
+        result.map_err(|e| {
+            log::error!("Failed to encode {}: {}", self.media_type(), e);
+            Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "encode_error",
+                "Internal Server Error",
+            )
+        })
+    }
+
+    /// この形式の本文を復号する
+    pub fn decode<T: DeserializeOwned>(&self, body: &[u8]) -> Result<T, Problem> {
+        let result = match self {
+            Format::Json => serde_json::from_slice(body).map_err(|e| e.to_string()),
+            Format::MessagePack => rmp_serde::from_slice(body).map_err(|e| e.to_string()),
+            Format::Cbor => ciborium::de::from_reader(body).map_err(|e| e.to_string()),
+            Format::Yaml => serde_yaml::from_slice(body).map_err(|e| e.to_string()),
+        };
+        result.map_err(|e| {
+            Problem::new(StatusCode::BAD_REQUEST, "invalid_body", "Bad Request").with_detail(e)
+        })
+    }
+
+    /// 値をこの形式で符号化した本文を持つレスポンスを作成する
+    pub fn respond<T: Serialize>(
+        &self,
+        mut builder: HttpResponseBuilder,
+        value: &T,
+    ) -> HttpResponse {
+        match self.encode(value) {
+            Ok(body) => builder
+                .content_type(self.media_type())
+                .append_header((header::VARY, "Accept"))
+                .body(body),
+            Err(problem) => problem.error_response(),
+        }
+    }
+}
+
+/// Accept ヘッダーのメディアレンジと優先度（q 値）を、優先度の高い順に返す。
+/// 同じ優先度のものはヘッダーでの順序を保つ。
+fn accept_ranges(req: &HttpRequest) -> Vec<(String, f32)> {
+    let mut ranges = req
+        .headers()
+        .get_all(header::ACCEPT)
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|range| {
+            let mut params = range.split(';').map(|p| p.trim());
+            let media_type = params.next().filter(|m| !m.is_empty())?;
+            let quality = params
+                .find_map(|p| p.strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            Some((media_type.to_ascii_lowercase(), quality))
+        })
+        .collect::<Vec<_>>();
+    ranges.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    ranges
+}
+
+/// Accept ヘッダーで `media_type` が明示的に指定されている場合の優先度。指定されていなければ 0。
+pub fn quality(req: &HttpRequest, media_type: &str) -> f32 {
+    accept_ranges(req)
+        .into_iter()
+        .find(|(m, _)| m.eq_ignore_ascii_case(media_type))
+        .map(|(_, q)| q)
+        .unwrap_or(0.0)
+}
+
+/// Accept ヘッダーで決めた応答の形式を取り出す extractor。
+/// 対応する形式がない場合は、ハンドラーの処理を行わずに 406 を返す。
+pub struct Accepted(pub Format);
+
+impl FromRequest for Accepted {
+    type Error = Problem;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Format::from_accept(req).map(Accepted))
+    }
+}
+
+/// Content-Type に応じて本文を復号する extractor
+pub struct Body<T>(pub T);
+
+impl<T> std::ops::Deref for Body<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: DeserializeOwned + 'static> FromRequest for Body<T> {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let format = Format::from_content_type(req);
+        let bytes = web::Bytes::from_request(req, payload);
+        Box::pin(async move {
+            let format = format?;
+            let body = bytes.await?;
+            Ok(Body(format.decode(&body)?))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::actix::request::PokemonRequest;
+    use actix_web::test as actix_test;
+
+    fn accept(value: &str) -> Result<Format, Problem> {
+        let req = actix_test::TestRequest::default()
+            .insert_header((header::ACCEPT, value))
+            .to_http_request();
+        Format::from_accept(&req)
+    }
+
+    #[test]
+    fn from_accept_ok() {
+        assert_eq!(accept("application/msgpack").unwrap(), Format::MessagePack);
+        assert_eq!(
+            accept("application/json;q=0.5, application/cbor").unwrap(),
+            Format::Cbor
+        );
+        assert_eq!(
+            accept("application/json;q=0, */*").unwrap(),
+            Format::MessagePack
+        );
+        assert_eq!(
+            Format::from_accept(&actix_test::TestRequest::default().to_http_request()).unwrap(),
+            Format::Json
+        );
+    }
+
+    #[test]
+    fn quality_ok() {
+        let req = actix_test::TestRequest::default()
+            .insert_header((
+                header::ACCEPT,
+                "application/json, application/x-ndjson;q=0.5",
+            ))
+            .to_http_request();
+        assert_eq!(quality(&req, "application/x-ndjson"), 0.5);
+        assert_eq!(quality(&req, "application/yaml"), 0.0);
+    }
+
+    #[test]
+    fn from_accept_ng_not_acceptable() {
+        let result = accept("text/html");
+        assert_eq!(
+            result.err().unwrap().status_code(),
+            StatusCode::NOT_ACCEPTABLE
+        );
+    }
+
+    #[test]
+    fn from_content_type_ng_unsupported() {
+        let req = actix_test::TestRequest::default()
+            .insert_header((header::CONTENT_TYPE, "text/plain"))
+            .to_http_request();
+        assert_eq!(
+            Format::from_content_type(&req).err().unwrap().status_code(),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+    }
+
+    #[test]
+    fn encode_decode_ok() {
+        let request = PokemonRequest {
+            number: 1,
+            name: "TestPokemon".to_string(),
+            types: vec!["Fire".to_string()],
+        };
+        for format in Format::ALL.iter() {
+            let body = format.encode(&request).unwrap();
+            let decoded: PokemonRequest = format.decode(&body).unwrap();
+            assert_eq!(decoded, request);
+        }
+    }
+}
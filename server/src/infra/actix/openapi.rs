@@ -0,0 +1,106 @@
+//! ハンドラーの定義から作成する OpenAPI 3 の仕様書と、それを表示する Swagger UI。
+
+use super::events;
+use super::handlers;
+use super::websocket;
+use actix_web::{get, HttpResponse, Responder};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use utoipa_swagger_ui::SwaggerUi;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Pokemon API",
+        description = "ポケモン図鑑の Web API。\n\n\
+            `/pokemon` の各エンドポイントは `Content-Type` と `Accept` に応じて、\
+            JSON のほか MessagePack（`application/msgpack`）・CBOR（`application/cbor`）・\
+            YAML（`application/yaml`）でも本文をやり取りできる。\n\n\
+            記載のパスは `/v1` の別名で、`/v1` と同じく廃止予定である。\
+            `/v2` ではポケモンを `data`・`meta`・`links` のエンベロープで返す。"
+    ),
+    paths(
+        handlers::post_pokemon,
+        handlers::get_pokemon,
+        handlers::get_pokemon_list,
+        events::pokemon_events,
+        websocket::websocket,
+        handlers::update_pokemon,
+        handlers::patch_pokemon,
+        handlers::delete_pokemon,
+        handlers::restore_pokemon,
+        handlers::batch_pokemon,
+        handlers::export_pokemon,
+        handlers::import_pokemon,
+        handlers::post_api_key,
+        handlers::delete_api_key,
+        handlers::post_webhook,
+        handlers::delete_webhook,
+        handlers::get_webhook_deliveries,
+        handlers::health,
+        handlers::ready,
+    ),
+    modifiers(&SecuritySchemes),
+    tags(
+        (name = "pokemon", description = "ポケモンの参照・登録・更新・削除"),
+        (name = "admin", description = "API キーの管理"),
+        (name = "system", description = "死活監視"),
+    )
+)]
+pub struct ApiDoc;
+
+/// 認証方式（Auth0 のアクセストークンと API キー）を仕様書に追加する
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                "Authorization",
+                "`ApiKey <key>` の形式で指定する",
+            ))),
+        );
+    }
+}
+
+/// `/docs` で Swagger UI を、`/openapi.json` で仕様書を返すサービス
+pub fn swagger_ui() -> SwaggerUi {
+    SwaggerUi::new("/docs/{_:.*}").url("/openapi.json", ApiDoc::openapi())
+}
+
+/// 末尾のスラッシュがない `/docs` を Swagger UI に転送する
+#[get("/docs")]
+async fn docs() -> impl Responder {
+    HttpResponse::MovedPermanently()
+        .insert_header(("Location", "/docs/"))
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn openapi_ok() {
+        let doc = ApiDoc::openapi();
+        let paths = &doc.paths.paths;
+        assert!(paths.contains_key("/pokemon"));
+        assert!(paths.contains_key("/pokemon/{number}"));
+        assert!(paths.contains_key("/pokemon:batch"));
+        let schemas = &doc.components.as_ref().unwrap().schemas;
+        for name in ["PokemonRequest", "PokemonData", "Problem", "ErrorResponse"] {
+            assert!(schemas.contains_key(name), "{} is missing", name);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! PATCH リクエストの本文を現在のポケモン情報に適用する処理。
+//! JSON Merge Patch (RFC 7396) と JSON Patch (RFC 6902) に対応する。
+
+use crate::application::pokemon_data::PokemonData;
+use crate::application::pokemon_update_service::PokemonUpdateCommand;
+use crate::domain::models::pokemon::{pokemon_name::PokemonName, pokemon_types::PokemonTypes};
+use crate::infra::actix::problem::Problem;
+use crate::infra::actix::request::PokemonRequest;
+use actix_web::http::{
+    header::{self, HeaderName, HeaderValue},
+    StatusCode,
+};
+use actix_web::HttpRequest;
+use serde_json::Value;
+use std::convert::TryFrom;
+
+pub const MERGE_PATCH: &str = "application/merge-patch+json";
+pub const JSON_PATCH: &str = "application/json-patch+json";
+
+/// PATCH の本文の形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchFormat {
+    MergePatch,
+    JsonPatch,
+}
+
+impl PatchFormat {
+    /// Content-Type から形式を判定する。
+    /// `application/json` は JSON Merge Patch として扱う。
+    pub fn from_request(req: &HttpRequest) -> Result<Self, Problem> {
+        let content_type = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(';').next())
+            .map(|v| v.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+        match content_type.as_str() {
+            MERGE_PATCH | "application/json" => Ok(PatchFormat::MergePatch),
+            JSON_PATCH => Ok(PatchFormat::JsonPatch),
+            _ => Err(Problem::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "unsupported_patch_format",
+                "Unsupported Media Type",
+            )
+            .with_detail(format!(
+                "対応していない Content-Type です: {:?}",
+                content_type
+            ))
+            .with_header(
+                HeaderName::from_static("accept-patch"),
+                HeaderValue::from_static(
+                    "application/merge-patch+json, application/json-patch+json",
+                ),
+            )),
+        }
+    }
+}
+
+fn invalid_patch(detail: impl Into<String>) -> Problem {
+    Problem::new(StatusCode::BAD_REQUEST, "invalid_patch", "Bad Request").with_detail(detail)
+}
+
+fn unprocessable(detail: impl Into<String>) -> Problem {
+    Problem::new(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "invalid_pokemon",
+        "Unprocessable Entity",
+    )
+    .with_detail(detail)
+}
+
+/// 現在のポケモン情報にパッチを適用し、変更された項目だけを持つ更新コマンドを作成する。
+pub fn apply(
+    current: &PokemonData,
+    format: PatchFormat,
+    body: &[u8],
+) -> Result<PokemonUpdateCommand, Problem> {
+    let original = serde_json::to_value(current).map_err(|e| invalid_patch(e.to_string()))?;
+    let mut patched = original.clone();
+    match format {
+        PatchFormat::MergePatch => {
+            let patch: Value =
+                serde_json::from_slice(body).map_err(|e| invalid_patch(e.to_string()))?;
+            if !patch.is_object() {
+                return Err(invalid_patch(
+                    "JSON Merge Patch はオブジェクトである必要があります",
+                ));
+            }
+            json_patch::merge(&mut patched, &patch);
+        }
+        PatchFormat::JsonPatch => {
+            let patch: json_patch::Patch =
+                serde_json::from_slice(body).map_err(|e| invalid_patch(e.to_string()))?;
+            // test 操作の失敗など、現在の状態に適用できないパッチは 409 とする
+            json_patch::patch(&mut patched, &patch).map_err(|e| {
+                Problem::new(StatusCode::CONFLICT, "patch_conflict", "Conflict")
+                    .with_detail(e.to_string())
+            })?;
+        }
+    }
+
+    if patched.get("number") != original.get("number") {
+        return Err(unprocessable("図鑑 No は変更できません"));
+    }
+    let PokemonRequest { name, types, .. } =
+        serde_json::from_value(patched).map_err(|e| unprocessable(e.to_string()))?;
+
+    let mut command = PokemonUpdateCommand::new(*current.get_number());
+    if &name != current.get_name() {
+        PokemonName::try_from(name.clone())
+            .map_err(|_| unprocessable(format!("不正な名前です: {:?}", name)))?;
+        command.set_name(Some(name));
+    }
+    if &types != current.get_types() {
+        PokemonTypes::try_from(types.clone())
+            .map_err(|_| unprocessable(format!("不正なタイプです: {:?}", types)))?;
+        command.set_types(Some(types));
+    }
+    Ok(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::pokemon::{
+        pokemon::Pokemon, pokemon_name::PokemonName, pokemon_number::PokemonNumber,
+        pokemon_types::PokemonTypes,
+    };
+    use actix_web::{test as actix_test, ResponseError};
+
+    fn current() -> PokemonData {
+        PokemonData::new(Pokemon::new(
+            PokemonNumber::try_from(1).unwrap(),
+            PokemonName::try_from("TestPokemon".to_string()).unwrap(),
+            PokemonTypes::try_from(vec!["Fire".to_string()]).unwrap(),
+        ))
+    }
+
+    #[test]
+    fn apply_ok_merge_patch() {
+        let command = apply(
+            &current(),
+            PatchFormat::MergePatch,
+            br#"{"name":"TestName"}"#,
+        )
+        .unwrap();
+        assert_eq!(command.get_name(), &Some("TestName".to_string()));
+        assert_eq!(command.get_types(), &None);
+    }
+
+    #[test]
+    fn apply_ok_json_patch() {
+        let command = apply(
+            &current(),
+            PatchFormat::JsonPatch,
+            br#"[{"op":"test","path":"/name","value":"TestPokemon"},{"op":"add","path":"/types/-","value":"Flying"}]"#,
+        )
+        .unwrap();
+        assert_eq!(command.get_name(), &None);
+        assert_eq!(
+            command.get_types(),
+            &Some(vec!["Fire".to_string(), "Flying".to_string()])
+        );
+    }
+
+    #[test]
+    fn apply_ng_json_patch_test_failed() {
+        let result = apply(
+            &current(),
+            PatchFormat::JsonPatch,
+            br#"[{"op":"test","path":"/name","value":"Other"}]"#,
+        );
+        assert_eq!(result.err().unwrap().status_code(), StatusCode::CONFLICT);
+    }
+
+    #[test]
+    fn apply_ng_remove_required_field() {
+        let result = apply(&current(), PatchFormat::MergePatch, br#"{"name":null}"#);
+        assert_eq!(
+            result.err().unwrap().status_code(),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+
+    #[test]
+    fn apply_ng_change_number() {
+        let result = apply(&current(), PatchFormat::MergePatch, br#"{"number":2}"#);
+        assert_eq!(
+            result.err().unwrap().status_code(),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+
+    #[test]
+    fn from_request_ng_unsupported_media_type() {
+        let req = actix_test::TestRequest::default()
+            .insert_header((header::CONTENT_TYPE, "text/plain"))
+            .to_http_request();
+        let result = PatchFormat::from_request(&req);
+        assert_eq!(
+            result.err().unwrap().status_code(),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! RFC 7807 (Problem Details for HTTP APIs) 形式のエラーレスポンス。
+
+use actix_web::{
+    http::{
+        header::{HeaderName, HeaderValue},
+        StatusCode,
+    },
+    HttpResponse, ResponseError,
+};
+use serde::Serialize;
+use std::fmt;
+use utoipa::ToSchema;
+
+/// `application/problem+json` で返却するエラー。
+/// extractor やミドルウェアのエラーとしてそのまま返せるよう `ResponseError` を実装する。
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct Problem {
+    r#type: String,
+    title: String,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+    #[serde(skip)]
+    headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl Problem {
+    /// コンストラクタ。type には問題の種類を表す識別子を渡す。
+    pub fn new(status: StatusCode, r#type: &str, title: &str) -> Self {
+        Self {
+            r#type: r#type.to_string(),
+            title: title.to_string(),
+            status: status.as_u16(),
+            detail: None,
+            headers: vec![],
+        }
+    }
+
+    /// 問題の詳細説明を設定する
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// レスポンスに付与するヘッダーを追加する
+    pub fn with_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
+    /// 401 Unauthorized
+    pub fn unauthorized(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, "unauthorized", "Unauthorized").with_detail(detail)
+    }
+
+    /// 403 Forbidden
+    pub fn forbidden(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, "forbidden", "Forbidden").with_detail(detail)
+    }
+
+    /// 404 Not Found
+    pub fn not_found(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not_found", "Not Found").with_detail(detail)
+    }
+
+    /// 412 Precondition Failed
+    pub fn precondition_failed(detail: impl Into<String>) -> Self {
+        Self::new(
+            StatusCode::PRECONDITION_FAILED,
+            "precondition_failed",
+            "Precondition Failed",
+        )
+        .with_detail(detail)
+    }
+
+    /// 428 Precondition Required
+    pub fn precondition_required(detail: impl Into<String>) -> Self {
+        Self::new(
+            StatusCode::PRECONDITION_REQUIRED,
+            "precondition_required",
+            "Precondition Required",
+        )
+        .with_detail(detail)
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.detail {
+            Some(detail) => write!(f, "{} {}: {}", self.status, self.title, detail),
+            None => write!(f, "{} {}", self.status, self.title),
+        }
+    }
+}
+
+impl ResponseError for Problem {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut builder = HttpResponse::build(self.status_code());
+        for (name, value) in self.headers.iter() {
+            builder.insert_header((name.clone(), value.clone()));
+        }
+        builder.content_type("application/problem+json").json(self)
+    }
+}
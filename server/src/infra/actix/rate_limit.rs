@@ -0,0 +1,510 @@
+//! トークンバケットによるクライアントごとのレート制限。
+//! クライアントは認証済みのユーザー（API キーまたは JWT の subject）、IP アドレスの順で識別する。
+
+use super::auth::AuthenticatedUser;
+use super::problem::Problem;
+use super::versioning;
+use crate::config::Config;
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::{
+        header::{self, HeaderName, HeaderValue},
+        Method, StatusCode,
+    },
+    middleware::Next,
+    web, Error, ResponseError,
+};
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// レート制限の対象外とするパス（死活監視・readiness probe）
+const EXEMPT_PATHS: [&str; 2] = ["/health", "/ready"];
+
+/// インメモリのストアが満タンに戻ったバケットを捨てる間隔
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 制限値。period の間に capacity 回までリクエストでき、トークンは一定の速度で補充される。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub capacity: u32,
+    pub period: Duration,
+}
+
+impl RateLimit {
+    /// `<capacity>/<period 秒>` 形式の文字列から作成する。
+    pub fn parse(s: &str) -> Result<Self> {
+        let (capacity, period) = s
+            .trim()
+            .split_once('/')
+            .with_context(|| format!("invalid rate limit {:?}", s))?;
+        let capacity: u32 = capacity
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid rate limit capacity {:?}", s))?;
+        let period: u64 = period
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid rate limit period {:?}", s))?;
+        if capacity == 0 || period == 0 {
+            return Err(anyhow::anyhow!("rate limit must be positive: {:?}", s));
+        }
+        Ok(Self {
+            capacity,
+            period: Duration::from_secs(period),
+        })
+    }
+
+    /// 1 秒あたりに補充されるトークン数
+    fn refill_per_sec(&self) -> f64 {
+        self.capacity as f64 / self.period.as_secs_f64()
+    }
+}
+
+/// レート制限の判定結果
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// バケットが満タンに戻るまでの時間
+    pub reset_after: Duration,
+    /// 拒否した場合、次のリクエストが可能になるまでの時間
+    pub retry_after: Option<Duration>,
+}
+
+/// バケットを保持するストア。
+/// 複数インスタンスで制限を共有する場合は、共有ストアを実装して差し替える。
+pub trait RateLimitStore: Send + Sync {
+    /// key のバケットからトークンを 1 つ取得する
+    fn acquire(&self, key: &str, limit: &RateLimit) -> RateLimitDecision;
+}
+
+/// バケット。ルートごとに制限値が異なるため、作成時の制限値を自身で保持する。
+#[derive(Debug, Clone)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    capacity: f64,
+    refill_per_sec: f64,
+    /// 最後に使われた順序。最も長く使われていないバケットを探すために使う。
+    tick: u64,
+}
+
+impl Bucket {
+    /// 指定した時刻までに補充されるトークンを加えた数
+    fn tokens_at(&self, now: Instant) -> f64 {
+        let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+        (self.tokens + elapsed * self.refill_per_sec).min(self.capacity)
+    }
+}
+
+/// ストアの状態。バケットと、最後に使われた順序からキーへの索引を保持する。
+#[derive(Default)]
+struct Buckets {
+    buckets: HashMap<String, Bucket>,
+    order: BTreeMap<u64, String>,
+    tick: u64,
+    swept_at: Option<Instant>,
+}
+
+impl Buckets {
+    /// 満タンに戻ったバケットを捨てる。捨てても新しく作るバケットと区別できないため、制限には影響しない。
+    fn sweep(&mut self, now: Instant) {
+        self.buckets.retain(|_, b| b.tokens_at(now) < b.capacity);
+        let buckets = &self.buckets;
+        self.order.retain(|_, key| buckets.contains_key(key));
+        self.swept_at = Some(now);
+    }
+
+    /// 最も長く使われていないバケットを捨てる
+    fn evict_least_recently_used(&mut self) {
+        if let Some((_, key)) = self.order.pop_first() {
+            self.buckets.remove(&key);
+        }
+    }
+}
+
+/// プロセス内にバケットを保持するストア。
+/// 満タンに戻ったバケットは一定の間隔でまとめて捨て、上限を超える場合は最も長く使われていないものから捨てる。
+pub struct InMemoryRateLimitStore {
+    state: Mutex<Buckets>,
+    max_buckets: usize,
+}
+
+impl InMemoryRateLimitStore {
+    /// コンストラクタ。max_buckets は保持するバケット数の上限で、超えた場合は最も長く使われていないバケットを捨てる。
+    pub fn new(max_buckets: usize) -> Self {
+        Self {
+            state: Mutex::new(Buckets::default()),
+            max_buckets: max_buckets.max(1),
+        }
+    }
+
+    /// 指定した時刻でトークンを取得する
+    fn acquire_at(&self, key: &str, limit: &RateLimit, now: Instant) -> RateLimitDecision {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        if state
+            .swept_at
+            .is_none_or(|swept_at| now.duration_since(swept_at) >= SWEEP_INTERVAL)
+        {
+            state.sweep(now);
+        }
+        if !state.buckets.contains_key(key) {
+            while state.buckets.len() >= self.max_buckets {
+                state.evict_least_recently_used();
+            }
+        }
+
+        state.tick += 1;
+        let tick = state.tick;
+        let bucket = state.buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: limit.capacity as f64,
+            updated_at: now,
+            capacity: limit.capacity as f64,
+            refill_per_sec: limit.refill_per_sec(),
+            tick,
+        });
+        state.order.remove(&bucket.tick);
+        state.order.insert(tick, key.to_string());
+        bucket.tick = tick;
+        bucket.tokens = bucket.tokens_at(now);
+        bucket.updated_at = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        let retry_after = if allowed {
+            None
+        } else {
+            Some(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / bucket.refill_per_sec,
+            ))
+        };
+        RateLimitDecision {
+            allowed,
+            limit: bucket.capacity as u32,
+            remaining: bucket.tokens.floor() as u32,
+            reset_after: Duration::from_secs_f64(
+                (bucket.capacity - bucket.tokens) / bucket.refill_per_sec,
+            ),
+            retry_after,
+        }
+    }
+}
+
+impl RateLimitStore for InMemoryRateLimitStore {
+    fn acquire(&self, key: &str, limit: &RateLimit) -> RateLimitDecision {
+        self.acquire_at(key, limit, Instant::now())
+    }
+}
+
+/// ルートごとの制限値
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteRateLimit {
+    /// None の場合は全てのメソッドが対象
+    pub method: Option<Method>,
+    /// actix のルートパターン（例: `/pokemon/{number}`）
+    pub pattern: String,
+    pub limit: RateLimit,
+}
+
+impl RouteRateLimit {
+    /// `<METHOD|*> <pattern>=<capacity>/<period 秒>` 形式の文字列から作成する。
+    pub fn parse(s: &str) -> Result<Self> {
+        let (route, limit) = s
+            .trim()
+            .rsplit_once('=')
+            .with_context(|| format!("invalid route rate limit {:?}", s))?;
+        let (method, pattern) = route
+            .trim()
+            .split_once(' ')
+            .with_context(|| format!("invalid route rate limit {:?}", s))?;
+        let method = match method {
+            "*" => None,
+            m => Some(
+                Method::from_bytes(m.to_uppercase().as_bytes())
+                    .with_context(|| format!("invalid method in route rate limit {:?}", s))?,
+            ),
+        };
+        Ok(Self {
+            method,
+            pattern: pattern.trim().to_string(),
+            limit: RateLimit::parse(limit)?,
+        })
+    }
+
+    fn matches(&self, method: &Method, pattern: &str) -> bool {
+        self.method.as_ref().is_none_or(|m| m == method) && self.pattern == pattern
+    }
+}
+
+/// レート制限の設定とストアを保持する。
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    default_limit: RateLimit,
+    routes: Vec<RouteRateLimit>,
+    trust_proxy: bool,
+}
+
+impl RateLimiter {
+    /// コンストラクタ
+    pub fn new(
+        store: Arc<dyn RateLimitStore>,
+        default_limit: RateLimit,
+        routes: Vec<RouteRateLimit>,
+        trust_proxy: bool,
+    ) -> Self {
+        Self {
+            store,
+            default_limit,
+            routes,
+            trust_proxy,
+        }
+    }
+
+    /// 設定から作成する。ストアはプロセス内のものを使う。
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let default_limit = RateLimit::parse(&config.rate_limit_default)?;
+        let routes = config
+            .rate_limit_routes
+            .split(',')
+            .filter(|r| !r.trim().is_empty())
+            .map(RouteRateLimit::parse)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self::new(
+            Arc::new(InMemoryRateLimitStore::new(config.rate_limit_max_buckets)),
+            default_limit,
+            routes,
+            config.rate_limit_trust_proxy,
+        ))
+    }
+
+    /// リクエストに適用する制限値と、バケットを区別するための名前を返す。
+    fn limit_for(&self, method: &Method, pattern: Option<&str>) -> (String, RateLimit) {
+        pattern
+            .and_then(|p| {
+                self.routes
+                    .iter()
+                    .enumerate()
+                    .find(|(_, r)| r.matches(method, p))
+            })
+            .map(|(i, r)| (format!("route{}", i), r.limit))
+            .unwrap_or_else(|| ("default".to_string(), self.default_limit))
+    }
+}
+
+/// リクエストの送り主を識別するキーを求める。
+/// 認証できたリクエストはユーザー（API キーの ID または JWT の subject）、できなければ IP アドレスを使う。
+/// 無効な API キーやトークンごとにバケットを作らないよう、認証できない場合は IP アドレスで識別する。
+/// 認証の結果はリクエストに保持され、ハンドラの `AuthenticatedUser` でそのまま使う。
+async fn client_key(req: &ServiceRequest, trust_proxy: bool) -> String {
+    if req.headers().contains_key(header::AUTHORIZATION) {
+        if let Ok(user) = AuthenticatedUser::resolve(req.request()).await {
+            return format!("user:{}", user.subject);
+        }
+    }
+
+    let ip = if trust_proxy {
+        req.connection_info().realip_remote_addr().map(String::from)
+    } else {
+        req.peer_addr().map(|addr| addr.ip().to_string())
+    };
+    format!("ip:{}", ip.unwrap_or_else(|| "unknown".to_string()))
+}
+
+/// ヘッダーに設定する秒数。端数は切り上げる。
+fn ceil_secs(d: Duration) -> u64 {
+    d.as_secs() + u64::from(d.subsec_nanos() > 0)
+}
+
+fn rate_limit_headers(decision: &RateLimitDecision) -> Vec<(HeaderName, HeaderValue)> {
+    let mut headers = vec![
+        (
+            HeaderName::from_static("ratelimit-limit"),
+            HeaderValue::from(decision.limit),
+        ),
+        (
+            HeaderName::from_static("ratelimit-remaining"),
+            HeaderValue::from(decision.remaining),
+        ),
+        (
+            HeaderName::from_static("ratelimit-reset"),
+            HeaderValue::from(ceil_secs(decision.reset_after)),
+        ),
+    ];
+    if let Some(retry_after) = decision.retry_after {
+        headers.push((
+            header::RETRY_AFTER,
+            HeaderValue::from(ceil_secs(retry_after)),
+        ));
+    }
+    headers
+}
+
+/// レート制限のミドルウェア。`middleware::from_fn` で登録する。
+/// 制限を超えたリクエストは 429 を返し、全てのレスポンスに `RateLimit-*` ヘッダーを付与する。
+pub async fn rate_limit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let limiter = match req.app_data::<web::Data<RateLimiter>>() {
+        Some(limiter) if !EXEMPT_PATHS.contains(&req.path()) => limiter.clone(),
+        _ => return next.call(req).await.map(|res| res.map_into_boxed_body()),
+    };
+
+    // バージョンのスコープに関わらず、同じルートには同じ制限値とバケットを使う
+    let pattern = req.match_pattern();
+    let (bucket, limit) = limiter.limit_for(
+        req.method(),
+        pattern.as_deref().map(versioning::unversioned),
+    );
+    let key = format!("{}|{}", client_key(&req, limiter.trust_proxy).await, bucket);
+    let decision = limiter.store.acquire(&key, &limit);
+    let headers = rate_limit_headers(&decision);
+
+    if !decision.allowed {
+        log::warn!("レート制限を超えました: {}", key);
+        let problem = headers.into_iter().fold(
+            Problem::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "too_many_requests",
+                "Too Many Requests",
+            )
+            .with_detail("リクエスト数が上限を超えました。時間をおいて再度お試しください。"),
+            |problem, (name, value)| problem.with_header(name, value),
+        );
+        return Ok(req.into_response(problem.error_response()));
+    }
+
+    let mut res = next.call(req).await?.map_into_boxed_body();
+    for (name, value) in headers {
+        res.headers_mut().insert(name, value);
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limit_parse_ok() {
+        let result = RateLimit::parse("10/60").unwrap();
+        assert_eq!(result.capacity, 10);
+        assert_eq!(result.period, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn rate_limit_parse_ng() {
+        assert!(RateLimit::parse("10").is_err());
+        assert!(RateLimit::parse("0/60").is_err());
+        assert!(RateLimit::parse("a/60").is_err());
+    }
+
+    #[test]
+    fn route_rate_limit_parse_ok() {
+        let result = RouteRateLimit::parse("post /pokemon/{number}=5/10").unwrap();
+        assert_eq!(result.method, Some(Method::POST));
+        assert_eq!(result.pattern, "/pokemon/{number}");
+        assert_eq!(result.limit.capacity, 5);
+
+        let result = RouteRateLimit::parse("* /pokemon=5/10").unwrap();
+        assert_eq!(result.method, None);
+    }
+
+    #[test]
+    fn limit_for_route() {
+        let default_limit = RateLimit::parse("100/60").unwrap();
+        let limiter = RateLimiter::new(
+            Arc::new(InMemoryRateLimitStore::new(100)),
+            default_limit,
+            vec![RouteRateLimit::parse("POST /pokemon=5/60").unwrap()],
+            false,
+        );
+        let (bucket, limit) = limiter.limit_for(&Method::POST, Some("/pokemon"));
+        assert_eq!(bucket, "route0");
+        assert_eq!(limit.capacity, 5);
+
+        let (bucket, limit) = limiter.limit_for(&Method::GET, Some("/pokemon"));
+        assert_eq!(bucket, "default");
+        assert_eq!(limit, default_limit);
+    }
+
+    #[test]
+    fn in_memory_store_rejects_when_empty() {
+        let store = InMemoryRateLimitStore::new(100);
+        let limit = RateLimit::parse("2/10").unwrap();
+        let now = Instant::now();
+
+        let first = store.acquire_at("client", &limit, now);
+        assert!(first.allowed);
+        assert_eq!(first.remaining, 1);
+
+        let second = store.acquire_at("client", &limit, now);
+        assert!(second.allowed);
+        assert_eq!(second.remaining, 0);
+
+        let third = store.acquire_at("client", &limit, now);
+        assert!(!third.allowed);
+        assert_eq!(third.retry_after, Some(Duration::from_secs(5)));
+
+        let other = store.acquire_at("other", &limit, now);
+        assert!(other.allowed);
+    }
+
+    #[test]
+    fn in_memory_store_refills() {
+        let store = InMemoryRateLimitStore::new(100);
+        let limit = RateLimit::parse("2/10").unwrap();
+        let now = Instant::now();
+
+        store.acquire_at("client", &limit, now);
+        store.acquire_at("client", &limit, now);
+        let later = store.acquire_at("client", &limit, now + Duration::from_secs(5));
+        assert!(later.allowed);
+        assert_eq!(later.remaining, 0);
+    }
+
+    #[test]
+    fn in_memory_store_evicts_least_recently_used() {
+        let store = InMemoryRateLimitStore::new(2);
+        let limit = RateLimit::parse("1/60").unwrap();
+        let now = Instant::now();
+
+        store.acquire_at("a", &limit, now);
+        store.acquire_at("b", &limit, now);
+        // a を使ったため、上限を超えたときは b を捨てる
+        assert!(!store.acquire_at("a", &limit, now).allowed);
+        store.acquire_at("c", &limit, now);
+
+        let state = store.state.lock().unwrap();
+        assert_eq!(state.buckets.len(), 2);
+        assert!(state.buckets.contains_key("a"));
+        assert!(!state.buckets.contains_key("b"));
+        assert_eq!(state.order.len(), 2);
+    }
+
+    #[test]
+    fn in_memory_store_sweeps_with_own_limit() {
+        let store = InMemoryRateLimitStore::new(100);
+        let short = RateLimit::parse("1/10").unwrap();
+        let long = RateLimit::parse("1/3600").unwrap();
+        let now = Instant::now();
+
+        store.acquire_at("short", &short, now);
+        store.acquire_at("long", &long, now);
+        // 別のルートの制限値ではなく、それぞれのバケットの制限値で満タンかどうかを判定する
+        store.acquire_at("other", &short, now + SWEEP_INTERVAL);
+
+        let state = store.state.lock().unwrap();
+        assert!(!state.buckets.contains_key("short"));
+        assert!(state.buckets.contains_key("long"));
+        assert_eq!(state.order.len(), 2);
+    }
+}
@@ -1,21 +1,159 @@
-use std::convert::TryInto;
-
-use crate::domain::models::pokemon::pokemon::Pokemon;
+use crate::application::api_key_issue_service::ApiKeyIssueCommand;
+use crate::application::pokemon_batch_service::PokemonBatchCommand;
+use crate::application::pokemon_update_service::PokemonUpdateCommand;
+use crate::application::webhook_register_service::WebhookRegisterCommand;
+use crate::domain::models::pokemon::{pokemon::Pokemon, pokemon_error::PokemonError};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash, Default, Deserialize, Serialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Default, Deserialize, Serialize, ToSchema)]
 pub struct PokemonRequest {
+    /// 図鑑 No（1〜898）
+    #[schema(example = 25)]
     pub number: i32,
+    #[schema(example = "Pikachu")]
     pub name: String,
+    /// タイプ（Fire, Water, Grass, Electric, Flying, Unknown）
+    #[schema(example = json!(["Electric"]))]
     pub types: Vec<String>,
 }
 
 impl PokemonRequest {
-    pub fn of(&self) -> Pokemon {
-        Pokemon::new(
-            self.number.try_into().unwrap(),
-            self.name.clone().try_into().unwrap(),
-            self.types.clone().try_into().unwrap(),
-        )
+    pub fn of(&self) -> Result<Pokemon, PokemonError> {
+        Pokemon::try_new(self.number, self.name.clone(), self.types.clone())
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Default, Deserialize, Serialize, ToSchema)]
+pub struct ApiKeyRequest {
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKeyRequest {
+    pub fn of(&self) -> ApiKeyIssueCommand {
+        let mut command = ApiKeyIssueCommand::new(self.name.clone(), self.scopes.clone());
+        command.set_expires_at(self.expires_at);
+        command
     }
 }
+
+#[derive(Debug, Clone, Eq, PartialEq, Default, Deserialize, Serialize, ToSchema)]
+pub struct WebhookRequest {
+    /// 通知先の URL（http または https）
+    #[schema(example = "https://example.com/hooks/pokemon")]
+    pub url: String,
+    /// 通知するイベント（pokemon.created, pokemon.updated, pokemon.deleted）。省略した場合は全て
+    #[serde(default)]
+    #[schema(example = json!(["pokemon.created", "pokemon.deleted"]))]
+    pub events: Vec<String>,
+}
+
+impl WebhookRequest {
+    pub fn of(&self) -> WebhookRegisterCommand {
+        WebhookRegisterCommand::new(self.url.clone(), self.events.clone())
+    }
+}
+
+/// 一括処理の実行方法
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchMode {
+    /// 1 つのトランザクションで実行し、1 件でも失敗すれば全て取り消す
+    #[default]
+    Atomic,
+    /// 操作ごとに確定し、失敗した操作があっても残りを続ける
+    BestEffort,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct PokemonBatchRequest {
+    #[serde(default)]
+    pub mode: BatchMode,
+    pub operations: Vec<PokemonOperationRequest>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum PokemonOperationRequest {
+    Create {
+        number: i32,
+        name: String,
+        types: Vec<String>,
+    },
+    Update {
+        number: i32,
+        name: Option<String>,
+        types: Option<Vec<String>>,
+        version: Option<i32>,
+    },
+    Delete {
+        number: i32,
+        version: Option<i32>,
+    },
+}
+
+impl PokemonOperationRequest {
+    /// 操作の種類の名前
+    pub fn op(&self) -> &'static str {
+        match self {
+            PokemonOperationRequest::Create { .. } => "create",
+            PokemonOperationRequest::Update { .. } => "update",
+            PokemonOperationRequest::Delete { .. } => "delete",
+        }
+    }
+
+    /// 対象の図鑑 No
+    pub fn number(&self) -> i32 {
+        match self {
+            PokemonOperationRequest::Create { number, .. }
+            | PokemonOperationRequest::Update { number, .. }
+            | PokemonOperationRequest::Delete { number, .. } => *number,
+        }
+    }
+
+    pub fn of(&self) -> PokemonBatchCommand {
+        match self.clone() {
+            PokemonOperationRequest::Create {
+                number,
+                name,
+                types,
+            } => PokemonBatchCommand::Create {
+                number,
+                name,
+                types,
+            },
+            PokemonOperationRequest::Update {
+                number,
+                name,
+                types,
+                version,
+            } => {
+                let mut command = PokemonUpdateCommand::new(number);
+                command.set_name(name);
+                command.set_types(types);
+                command.set_expected_version(version);
+                PokemonBatchCommand::Update(command)
+            }
+            PokemonOperationRequest::Delete { number, version } => PokemonBatchCommand::Delete {
+                number,
+                expected_version: version,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Default, Deserialize, Serialize, IntoParams)]
+pub struct PokemonExportQuery {
+    /// 出力形式（`csv` のみ対応）
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Default, Deserialize, Serialize, IntoParams)]
+pub struct PokemonImportQuery {
+    /// true の場合は検証結果だけを返し、データベースに反映しない
+    #[serde(default)]
+    pub dry_run: bool,
+}
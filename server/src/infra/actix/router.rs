@@ -1,10 +1,34 @@
+use super::auth::Authenticator;
+use super::cors;
+use super::events::{self, ChangeFeed, ChangeFeedSubscriber};
+use super::graphql;
 use super::handlers;
-use crate::{config::CONFIG, domain::models::pokemon::pokemon_repository::PokemonRepository};
-use actix_web::{middleware::Logger, App, HttpServer, web};
+use super::openapi;
+use super::rate_limit::{self, RateLimiter};
+use super::shutdown::{self, Readiness};
+use super::versioning::{self, ApiVersion, DeprecationPolicy};
+use super::websocket;
+use crate::config::CONFIG;
+use crate::domain::models::{
+    api_key::api_key_repository::ApiKeyRepository,
+    outbox::outbox_repository::OutboxRepository,
+    pokemon::{pokemon_event_bus::PokemonEventBus, pokemon_repository::PokemonRepository},
+    role::role_repository::RoleRepository,
+    webhook::webhook_repository::WebhookRepository,
+};
+use crate::infra::audit::AuditLogSubscriber;
+use crate::infra::grpc::{self, pokemon_service::PokemonGrpcService};
+use crate::infra::outbox;
+use crate::infra::webhook::{self, RetryPolicy};
+use actix_web::{
+    middleware::{from_fn, Logger},
+    web, App, HttpServer,
+};
 use diesel::{
     r2d2::{ConnectionManager, Pool},
     PgConnection,
 };
+use std::time::Duration;
 
 #[actix_web::main]
 pub async fn run() -> std::io::Result<()> {
@@ -14,25 +38,160 @@ pub async fn run() -> std::io::Result<()> {
         .and_then(|val| val.parse::<u16>().ok())
         .unwrap_or(CONFIG.server_port);
 
-    HttpServer::new(|| {
-        App::new()
-            .app_data(web::Data::new(RequestContext::new()).clone())
+    // DB コネクションプールは全ワーカーで共有し、停止時にまとめてクローズする。
+    let context = RequestContext::new();
+    let readiness = Readiness::new();
+    let authenticator = web::Data::new(
+        Authenticator::from_config(&CONFIG)
+            .await
+            .map_err(|e| std::io::Error::other(format!("{:?}", e)))?,
+    );
+    // 設定の誤りは起動時に検出する。ワーカーごとの CORS ミドルウェアはこの設定から作り直す。
+    if let Err(e) = cors::cors(&CONFIG) {
+        return Err(std::io::Error::other(format!("{:?}", e)));
+    }
+    let deprecation = web::Data::new(
+        DeprecationPolicy::from_config(&CONFIG)
+            .map_err(|e| std::io::Error::other(format!("{:?}", e)))?,
+    );
+    let rate_limiter = if CONFIG.rate_limit_enabled {
+        Some(web::Data::new(
+            RateLimiter::from_config(&CONFIG)
+                .map_err(|e| std::io::Error::other(format!("{:?}", e)))?,
+        ))
+    } else {
+        None
+    };
+
+    // gRPC は別のポートで待ち受け、HTTP サーバーの停止後に停止する
+    let grpc_incoming = grpc::bind(
+        format!("{}:{}", CONFIG.server_address, CONFIG.grpc_port)
+            .parse()
+            .map_err(std::io::Error::other)?,
+    )?;
+    let (grpc_shutdown, grpc_shutdown_rx) = tokio::sync::watch::channel(false);
+    let grpc_server = actix_web::rt::spawn(grpc::serve(
+        grpc_incoming,
+        PokemonGrpcService::new(
+            context.clone(),
+            authenticator.clone(),
+            grpc_shutdown_rx.clone(),
+        ),
+        grpc_shutdown_rx,
+    ));
+
+    // アウトボックスの配信先は起動時に検証し、未配信のドメインイベントを配信し続ける
+    let outbox_sink =
+        outbox::sink_from_config(&CONFIG).map_err(|e| std::io::Error::other(format!("{:?}", e)))?;
+    actix_web::rt::spawn(outbox::run(
+        context.clone(),
+        outbox_sink,
+        Duration::from_secs(CONFIG.outbox_poll_interval.max(1)),
+        CONFIG.outbox_batch_size.max(1),
+    ));
+
+    // Webhook はアウトボックスのイベントから通知を作り、データベースのキューを通して送信する
+    actix_web::rt::spawn(webhook::run(
+        context.clone(),
+        RetryPolicy::from_config(&CONFIG),
+        Duration::from_secs(CONFIG.webhook_poll_interval.max(1)),
+    ));
+
+    // GraphQL のスキーマは内部で共有されるため、全ワーカーで 1 つを使う
+    let schema = web::Data::new(graphql::schema(context.clone()));
+
+    let app_context = context.clone();
+    let app_readiness = readiness.clone();
+    let server = HttpServer::new(move || {
+        let mut app = App::new()
+            .app_data(web::Data::new(app_context.clone()))
+            .app_data(web::Data::new(app_readiness.clone()))
+            .app_data(authenticator.clone())
+            .app_data(schema.clone())
+            .app_data(deprecation.clone());
+        if let Some(rate_limiter) = &rate_limiter {
+            app = app.app_data(rate_limiter.clone());
+        }
+        app.wrap(from_fn(rate_limit::rate_limit))
+            .wrap(cors::cors(&CONFIG).unwrap())
             .wrap(Logger::default())
             .service(handlers::health)
-            .service(handlers::post_pokemon)
-            .service(handlers::get_pokemon)
-            .service(handlers::update_pokemon)
-            .service(handlers::delete_pokemon)
-            .service(handlers::get_pokemon_list)
+            .service(handlers::ready)
+            .service(graphql::graphql)
+            .service(graphql::graphiql)
+            .service(websocket::websocket)
+            .service(openapi::docs)
+            .service(openapi::swagger_ui())
+            .configure(admin)
+            .service(
+                web::scope("/v1")
+                    .app_data(ApiVersion::V1)
+                    .wrap(from_fn(versioning::deprecated))
+                    .configure(api),
+            )
+            .service(web::scope("/v2").app_data(ApiVersion::V2).configure(api))
+            // バージョンのないパスは移行期間中の `/v1` の別名。全パスに一致するため最後に登録する。
+            .service(
+                web::scope("")
+                    .wrap(from_fn(versioning::deprecated))
+                    .configure(api),
+            )
     })
+    // シグナルは shutdown モジュールで扱い、readiness の切り替えを先に行う。
+    .disable_signals()
+    .shutdown_timeout(CONFIG.shutdown_timeout)
     .bind(format!("{}:{}", CONFIG.server_address, port))?
-    .run()
-    .await
+    .run();
+
+    actix_web::rt::spawn(shutdown::graceful_shutdown(
+        server.handle(),
+        readiness,
+        Duration::from_secs(CONFIG.shutdown_drain_delay),
+    ));
+    server.await?;
+
+    let _ = grpc_shutdown.send(true);
+    match grpc_server.await {
+        Ok(Err(e)) => log::error!("gRPC サーバーが異常終了しました: {:?}", e),
+        Err(e) => log::error!("gRPC サーバーが異常終了しました: {:?}", e),
+        Ok(Ok(_)) => {}
+    }
+    drop(context);
+    log::info!("DB コネクションプールをクローズしました。サーバーを停止します。");
+    log::logger().flush();
+    Ok(())
+}
+
+/// バージョンごとのスコープに登録する API のルート
+fn api(cfg: &mut web::ServiceConfig) {
+    cfg.service(handlers::post_pokemon)
+        // `/pokemon/{number}` より先に登録する
+        .service(handlers::export_pokemon)
+        .service(handlers::import_pokemon)
+        .service(events::pokemon_events)
+        .service(handlers::get_pokemon)
+        .service(handlers::update_pokemon)
+        .service(handlers::patch_pokemon)
+        .service(handlers::delete_pokemon)
+        .service(handlers::restore_pokemon)
+        .service(handlers::batch_pokemon)
+        .service(handlers::get_pokemon_list);
+}
+
+/// バージョンに依存しない管理用のルート。バージョンのスコープより先に 1 度だけ登録する。
+fn admin(cfg: &mut web::ServiceConfig) {
+    cfg.service(handlers::post_api_key)
+        .service(handlers::delete_api_key)
+        .service(handlers::post_webhook)
+        .service(handlers::delete_webhook)
+        .service(handlers::get_webhook_deliveries);
 }
 
 #[derive(Clone)]
 pub struct RequestContext {
     pool: Pool<ConnectionManager<PgConnection>>,
+    change_feed: ChangeFeed,
+    event_bus: PokemonEventBus,
 }
 
 impl RequestContext {
@@ -42,7 +201,27 @@ impl RequestContext {
             .build(manager)
             .expect("Failed to create DB connection pool.");
 
-        RequestContext { pool }
+        // ドメインイベントの購読者はここで登録する
+        let change_feed = ChangeFeed::new(CONFIG.sse_event_log_size);
+        let mut event_bus = PokemonEventBus::default();
+        event_bus.subscribe(AuditLogSubscriber);
+        event_bus.subscribe(ChangeFeedSubscriber::new(change_feed.clone()));
+
+        RequestContext {
+            pool,
+            change_feed,
+            event_bus,
+        }
+    }
+
+    /// 登録・更新・削除の成功を記録するイベントログ
+    pub fn change_feed(&self) -> &ChangeFeed {
+        &self.change_feed
+    }
+
+    /// 登録・更新・削除のドメインイベントを配信するイベントバス
+    pub fn event_bus(&self) -> PokemonEventBus {
+        self.event_bus.clone()
     }
 
     pub fn pokemon_repository(&self) -> impl PokemonRepository {
@@ -52,4 +231,36 @@ impl RequestContext {
             pool: Box::new(self.pool.to_owned()),
         }
     }
+
+    pub fn role_repository(&self) -> impl RoleRepository {
+        use crate::infra::diesel::role_repository::RoleRepositoryImpl;
+
+        RoleRepositoryImpl {
+            pool: Box::new(self.pool.to_owned()),
+        }
+    }
+
+    pub fn api_key_repository(&self) -> impl ApiKeyRepository {
+        use crate::infra::diesel::api_key_repository::ApiKeyRepositoryImpl;
+
+        ApiKeyRepositoryImpl {
+            pool: Box::new(self.pool.to_owned()),
+        }
+    }
+
+    pub fn outbox_repository(&self) -> impl OutboxRepository {
+        use crate::infra::diesel::outbox_repository::OutboxRepositoryImpl;
+
+        OutboxRepositoryImpl {
+            pool: Box::new(self.pool.to_owned()),
+        }
+    }
+
+    pub fn webhook_repository(&self) -> impl WebhookRepository {
+        use crate::infra::diesel::webhook_repository::WebhookRepositoryImpl;
+
+        WebhookRepositoryImpl {
+            pool: Box::new(self.pool.to_owned()),
+        }
+    }
 }
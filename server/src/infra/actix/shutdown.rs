@@ -0,0 +1,92 @@
+//! シグナルを受けてサーバーを安全に停止するための処理。
+
+use actix_web::dev::ServerHandle;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::time::Duration;
+
+/// リクエストを受け付けられる状態かどうかを表す。
+/// シャットダウンが始まると失敗に切り替わり、`/ready` が 503 を返すようになる。
+#[derive(Clone, Debug)]
+pub struct Readiness(Arc<AtomicBool>);
+
+impl Readiness {
+    /// コンストラクタ。受付可能な状態で作成する。
+    pub fn new() -> Self {
+        Self(Arc::new(AtomicBool::new(true)))
+    }
+
+    /// 受付可能かどうか
+    pub fn is_ready(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// 受付不可の状態に切り替える
+    pub fn set_not_ready(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// SIGTERM / SIGINT を待ち、受信したらサーバーのグレースフルシャットダウンを開始する。
+/// readiness を失敗にしたあと drain_delay だけ待ってから新規接続の受付を止め、
+/// 処理中のリクエストは HttpServer の shutdown_timeout の範囲で完了を待つ。
+pub async fn graceful_shutdown(handle: ServerHandle, readiness: Readiness, drain_delay: Duration) {
+    wait_for_signal().await;
+    log::info!("シャットダウンシグナルを受信しました。readiness を失敗に切り替えます。");
+    readiness.set_not_ready();
+
+    if !drain_delay.is_zero() {
+        actix_web::rt::time::sleep(drain_delay).await;
+    }
+
+    log::info!("新規接続の受付を停止し、処理中のリクエストの完了を待ちます。");
+    handle.stop(true).await;
+}
+
+/// SIGTERM または SIGINT を受信するまで待つ。
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use actix_web::rt::signal::unix::{signal, SignalKind};
+
+    let mut sigterm = signal(SignalKind::terminate()).expect("Failed to install SIGTERM handler.");
+    let mut sigint = signal(SignalKind::interrupt()).expect("Failed to install SIGINT handler.");
+    tokio::select! {
+        _ = sigterm.recv() => {},
+        _ = sigint.recv() => {},
+    }
+}
+
+/// Ctrl-C を受信するまで待つ。
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    actix_web::rt::signal::ctrl_c()
+        .await
+        .expect("Failed to install Ctrl-C handler.");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readiness_new_is_ready() {
+        let readiness = Readiness::new();
+        assert!(readiness.is_ready());
+    }
+
+    #[test]
+    fn readiness_set_not_ready_is_shared() {
+        let readiness = Readiness::new();
+        let cloned = readiness.clone();
+        cloned.set_not_ready();
+        assert!(!readiness.is_ready());
+    }
+}
@@ -0,0 +1,296 @@
+//! API のバージョン管理。
+//! `/v1` は従来の形式、`/v2` はデータとリンクをまとめたエンベロープ形式で本文を返す。
+//! バージョンのないパスは移行期間中の `/v1` の別名として残し、`/v1` とともに
+//! Deprecation (RFC 9745) / Sunset (RFC 8594) ヘッダーで廃止予定であることを伝える。
+
+use crate::application::pokemon_data::PokemonData;
+use crate::config::Config;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue, HttpDate, LINK};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, FromRequest, HttpRequest};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use futures_util::future::{ready, Ready};
+use serde::Serialize;
+use std::time::SystemTime;
+use utoipa::ToSchema;
+
+/// API のバージョン。スコープの app_data で指定し、指定がなければ V1 とする。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiVersion {
+    V1,
+    V2,
+}
+
+impl FromRequest for ApiVersion {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(req
+            .app_data::<ApiVersion>()
+            .copied()
+            .unwrap_or(ApiVersion::V1)))
+    }
+}
+
+impl ApiVersion {
+    /// ポケモンの URL。V1 は従来どおりバージョンのないパスを返す。
+    pub fn pokemon_location(&self, number: i32) -> String {
+        match self {
+            ApiVersion::V1 => format!("/pokemon/{}", number),
+            ApiVersion::V2 => format!("/v2/pokemon/{}", number),
+        }
+    }
+
+    /// 一覧や一括処理の要素など、1 件分のポケモンの表現
+    pub fn represent(&self, pokemon: PokemonData) -> PokemonRepresentation {
+        match self {
+            ApiVersion::V1 => PokemonRepresentation::V1(pokemon),
+            ApiVersion::V2 => PokemonRepresentation::V2(PokemonResource::from(pokemon)),
+        }
+    }
+
+    /// ポケモン 1 件を返すレスポンスの本文
+    pub fn pokemon_body(&self, pokemon: &PokemonData) -> PokemonBody {
+        match self {
+            ApiVersion::V1 => PokemonBody::V1(pokemon.clone()),
+            ApiVersion::V2 => PokemonBody::V2(Envelope {
+                data: PokemonResource::from(pokemon.clone()),
+                meta: None,
+                links: Links {
+                    self_link: self.pokemon_location(*pokemon.get_number()),
+                },
+            }),
+        }
+    }
+
+    /// ポケモンの一覧を返すレスポンスの本文
+    pub fn pokemon_list_body(&self, pokemon: &[PokemonData]) -> PokemonListBody {
+        match self {
+            ApiVersion::V1 => PokemonListBody::V1(pokemon.to_vec()),
+            ApiVersion::V2 => PokemonListBody::V2(Envelope {
+                data: pokemon.iter().cloned().map(PokemonResource::from).collect(),
+                meta: Some(Meta {
+                    count: pokemon.len(),
+                }),
+                links: Links {
+                    self_link: String::from("/v2/pokemon"),
+                },
+            }),
+        }
+    }
+}
+
+/// v2 のポケモンの表現。v1 ではヘッダーでのみ返していた版と最終更新日時を本文にも含める。
+#[derive(Serialize, Clone, PartialEq, Eq, Debug, ToSchema)]
+pub struct PokemonResource {
+    number: i32,
+    name: String,
+    types: Vec<String>,
+    version: i32,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+impl From<PokemonData> for PokemonResource {
+    fn from(pokemon: PokemonData) -> Self {
+        Self {
+            number: *pokemon.get_number(),
+            name: pokemon.get_name().clone(),
+            types: pokemon.get_types().clone(),
+            version: *pokemon.get_version(),
+            updated_at: *pokemon.get_updated_at(),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, PartialEq, Eq, Debug, ToSchema)]
+#[serde(untagged)]
+pub enum PokemonRepresentation {
+    V1(PokemonData),
+    V2(PokemonResource),
+}
+
+/// v2 のレスポンスのエンベロープ
+#[derive(Serialize, Debug)]
+pub struct Envelope<T> {
+    data: T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    meta: Option<Meta>,
+    links: Links,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Meta {
+    count: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Links {
+    #[serde(rename = "self")]
+    self_link: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum PokemonBody {
+    V1(PokemonData),
+    V2(Envelope<PokemonResource>),
+}
+
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum PokemonListBody {
+    V1(Vec<PokemonData>),
+    V2(Envelope<Vec<PokemonResource>>),
+}
+
+/// ルートのパターンからバージョンのスコープを取り除く。
+/// ルートごとの設定（レート制限など）をバージョンに関わらず適用するために使う。
+pub fn unversioned(pattern: &str) -> &str {
+    ["/v1", "/v2"]
+        .iter()
+        .find_map(|prefix| pattern.strip_prefix(prefix).filter(|p| p.starts_with('/')))
+        .unwrap_or(pattern)
+}
+
+/// 旧バージョンの廃止予定。どちらの日時も指定がなければヘッダーを付与しない。
+#[derive(Debug, Clone)]
+pub struct DeprecationPolicy {
+    deprecated_at: Option<DateTime<Utc>>,
+    sunset: Option<DateTime<Utc>>,
+}
+
+impl DeprecationPolicy {
+    /// 設定から作成する。日時は RFC 3339 形式で指定し、空の場合はそのヘッダーを付与しない。
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let parse = |name: &str, value: &str| match value.trim() {
+            "" => Ok(None),
+            value => DateTime::parse_from_rfc3339(value)
+                .map(|d| Some(d.with_timezone(&Utc)))
+                .with_context(|| format!("invalid {}: {:?}", name, value)),
+        };
+        let deprecated_at = parse("API_V1_DEPRECATED_AT", &config.api_v1_deprecated_at)?;
+        let sunset = parse("API_V1_SUNSET", &config.api_v1_sunset)?;
+        Ok(Self {
+            deprecated_at,
+            sunset,
+        })
+    }
+
+    /// 旧バージョンのレスポンスに付与するヘッダー。
+    /// 後継のバージョンとして、同じリソースの `/v2` の URL を Link ヘッダーで示す。
+    fn headers(&self, path: &str) -> Vec<(HeaderName, HeaderValue)> {
+        let mut headers = vec![];
+        if self.deprecated_at.is_none() && self.sunset.is_none() {
+            return headers;
+        }
+        if let Some(deprecated_at) = self.deprecated_at {
+            headers.push((
+                HeaderName::from_static("deprecation"),
+                HeaderValue::from_str(&format!("@{}", deprecated_at.timestamp())).unwrap(),
+            ));
+        }
+        if let Some(sunset) = self.sunset {
+            let date = HttpDate::from(SystemTime::from(sunset));
+            headers.push((
+                HeaderName::from_static("sunset"),
+                HeaderValue::from_str(&date.to_string()).unwrap(),
+            ));
+        }
+        let successor = format!("/v2{}", path.strip_prefix("/v1").unwrap_or(path));
+        if let Ok(link) =
+            HeaderValue::from_str(&format!("<{}>; rel=\"successor-version\"", successor))
+        {
+            headers.push((LINK, link));
+        }
+        headers
+    }
+}
+
+/// 旧バージョンのスコープに登録するミドルウェア。`middleware::from_fn` で登録する。
+/// ルートに一致したリクエストのレスポンスにのみ、廃止予定を示すヘッダーを付与する。
+pub async fn deprecated(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let policy = req.app_data::<web::Data<DeprecationPolicy>>().cloned();
+    let path = req.path().to_string();
+    let mut res = next.call(req).await?;
+    if let Some(policy) = policy {
+        if res.request().match_pattern().is_some() {
+            for (name, value) in policy.headers(&path) {
+                res.headers_mut().insert(name, value);
+            }
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::pokemon::pokemon::Pokemon;
+
+    fn pokemon() -> PokemonData {
+        PokemonData::new(
+            Pokemon::try_new(1, "TestPokemon".to_string(), vec!["Fire".to_string()]).unwrap(),
+        )
+    }
+
+    #[test]
+    fn pokemon_body_v1_is_unchanged() {
+        let body = serde_json::to_value(ApiVersion::V1.pokemon_body(&pokemon())).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"number": 1, "name": "TestPokemon", "types": ["Fire"]})
+        );
+    }
+
+    #[test]
+    fn pokemon_list_body_v2_envelope() {
+        let body = serde_json::to_value(ApiVersion::V2.pokemon_list_body(&[pokemon()])).unwrap();
+        assert_eq!(body["data"][0]["version"], 1);
+        assert_eq!(body["meta"]["count"], 1);
+        assert_eq!(body["links"]["self"], "/v2/pokemon");
+    }
+
+    #[test]
+    fn unversioned_ok() {
+        assert_eq!(unversioned("/v1/pokemon/{number}"), "/pokemon/{number}");
+        assert_eq!(unversioned("/v2/pokemon"), "/pokemon");
+        assert_eq!(unversioned("/pokemon"), "/pokemon");
+        assert_eq!(unversioned("/v10/pokemon"), "/v10/pokemon");
+    }
+
+    #[test]
+    fn deprecation_headers() {
+        let policy = DeprecationPolicy {
+            deprecated_at: Some(
+                DateTime::parse_from_rfc3339("2026-10-19T00:00:00Z")
+                    .unwrap()
+                    .with_timezone(&Utc),
+            ),
+            sunset: Some(
+                DateTime::parse_from_rfc3339("2027-04-19T00:00:00Z")
+                    .unwrap()
+                    .with_timezone(&Utc),
+            ),
+        };
+        let headers = policy.headers("/v1/pokemon/1");
+        assert_eq!(headers[0].1, "@1792368000");
+        assert_eq!(headers[1].1, "Mon, 19 Apr 2027 00:00:00 GMT");
+        assert_eq!(headers[2].1, "</v2/pokemon/1>; rel=\"successor-version\"");
+    }
+
+    #[test]
+    fn deprecation_headers_unset() {
+        let policy = DeprecationPolicy {
+            deprecated_at: None,
+            sunset: None,
+        };
+        assert!(policy.headers("/v1/pokemon/1").is_empty());
+    }
+}
@@ -0,0 +1,470 @@
+//! WebSocket によるポケモンの変更の通知。
+//! クライアントは図鑑 No やタイプを指定して購読し、該当するポケモンの登録・更新・削除の通知を受け取る。
+//! 通知は SSE と同じイベントログ（`events::ChangeFeed`）から受け取る。
+//! ブラウザの WebSocket は Authorization ヘッダーを付けられないため、認証情報はサブプロトコルでも受け付ける。
+
+use super::auth::ReadAccess;
+use super::events::{ChangeEvent, Subscription};
+use super::router::RequestContext;
+use super::shutdown::Readiness;
+use super::versioning::ApiVersion;
+use crate::application::pokemon_list_service::PokemonListService;
+use crate::application::pokemon_watch_service::PokemonChange;
+use crate::config::CONFIG;
+use crate::domain::models::pokemon::{pokemon_query::PokemonQuery, pokemon_type::PokemonType};
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use actix_ws::{AggregatedMessage, CloseCode, CloseReason, Session};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+/// 受け付けるメッセージの最大サイズ
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// 接続の終了を伝える Close フレームの送信を待つ時間
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// 1 つの接続で購読できる図鑑 No の最大数
+const MAX_SUBSCRIBED_NUMBERS: usize = 1000;
+
+/// ハンドシェイクで選択するサブプロトコル。
+/// 認証情報をサブプロトコル（`bearer.<token>`・`apikey.<key>`）で渡す場合、ブラウザは選択されたサブプロトコルを
+/// 応答に求めるため、クライアントはこれも合わせて指定する。
+const PROTOCOL: &str = "pokedex";
+
+/// クライアントから受け取るメッセージ
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe {
+        #[serde(default)]
+        numbers: Vec<i32>,
+        #[serde(default)]
+        types: Vec<String>,
+    },
+    Unsubscribe {
+        #[serde(default)]
+        numbers: Vec<i32>,
+        #[serde(default)]
+        types: Vec<String>,
+    },
+}
+
+/// 購読の状態を返すメッセージ
+#[derive(Serialize)]
+struct Subscribed<'a> {
+    r#type: &'static str,
+    numbers: &'a BTreeSet<i32>,
+    types: &'a BTreeSet<String>,
+}
+
+/// 接続ごとの購読の内容
+#[derive(Default, Debug)]
+struct Subscriptions {
+    numbers: BTreeSet<i32>,
+    types: BTreeSet<String>,
+    /// 購読中のタイプを持つポケモンの図鑑 No と、最後に確認したタイプ。
+    /// 削除の通知はタイプを持たないため、このポケモンの削除であれば通知する。
+    typed: BTreeMap<i32, Vec<String>>,
+}
+
+impl Subscriptions {
+    fn has_subscribed_type(&self, types: &[String]) -> bool {
+        types.iter().any(|t| self.types.contains(t))
+    }
+
+    /// 購読に図鑑 No を追加する。合わせて上限を超える場合は何も追加せずに false を返す
+    fn add_numbers(&mut self, numbers: Vec<i32>) -> bool {
+        let added = numbers
+            .into_iter()
+            .filter(|number| !self.numbers.contains(number))
+            .collect::<BTreeSet<_>>();
+        if self.numbers.len() + added.len() > MAX_SUBSCRIBED_NUMBERS {
+            return false;
+        }
+        self.numbers.extend(added);
+        true
+    }
+
+    /// 購読にタイプを追加し、現在そのタイプを持つポケモンを記録する
+    fn add_types(&mut self, types: Vec<String>, current: Vec<(i32, Vec<String>)>) {
+        self.types.extend(types);
+        for (number, types) in current {
+            if self.has_subscribed_type(&types) {
+                self.typed.insert(number, types);
+            }
+        }
+    }
+
+    /// 購読からタイプを取り除き、購読中のタイプを持たなくなったポケモンの記録を消す
+    fn remove_types(&mut self, types: &[String]) {
+        for t in types {
+            self.types.remove(t);
+        }
+        let subscribed = std::mem::take(&mut self.typed);
+        self.typed = subscribed
+            .into_iter()
+            .filter(|(_, types)| self.has_subscribed_type(types))
+            .collect();
+    }
+
+    /// 変更を通知するかどうかを判定し、記録を更新する。
+    /// 購読中のタイプから外れた更新も、クライアントが一覧から取り除けるように通知する。
+    fn accept(&mut self, change: &PokemonChange) -> bool {
+        match change {
+            PokemonChange::Created(pokemon) | PokemonChange::Updated(pokemon) => {
+                let number = *pokemon.get_number();
+                let matched = self.has_subscribed_type(pokemon.get_types());
+                let was_matched = if matched {
+                    self.typed
+                        .insert(number, pokemon.get_types().clone())
+                        .is_some()
+                } else {
+                    self.typed.remove(&number).is_some()
+                };
+                self.numbers.contains(&number) || matched || was_matched
+            }
+            PokemonChange::Deleted(number) => {
+                let was_matched = self.typed.remove(number).is_some();
+                self.numbers.contains(number) || was_matched
+            }
+        }
+    }
+}
+
+/// 変更の通知。ポケモンは v2 の表現で返す。
+fn notification(event: &ChangeEvent) -> String {
+    match &event.change {
+        PokemonChange::Created(pokemon) => json!({
+            "type": "created",
+            "id": event.id.to_string(),
+            "pokemon": ApiVersion::V2.represent(pokemon.clone()),
+        }),
+        PokemonChange::Updated(pokemon) => json!({
+            "type": "updated",
+            "id": event.id.to_string(),
+            "pokemon": ApiVersion::V2.represent(pokemon.clone()),
+        }),
+        PokemonChange::Deleted(number) => json!({
+            "type": "deleted",
+            "id": event.id.to_string(),
+            "number": number,
+        }),
+    }
+    .to_string()
+}
+
+fn error_message(message: impl Into<String>) -> String {
+    json!({ "type": "error", "message": message.into() }).to_string()
+}
+
+/// クライアントへ送るメッセージ
+enum Outgoing {
+    Text(String),
+    Ping,
+    Pong(web::Bytes),
+}
+
+/// 送信キューからクライアントへ送る
+async fn write(mut session: Session, mut rx: mpsc::Receiver<Outgoing>) {
+    while let Some(message) = rx.recv().await {
+        let result = match message {
+            Outgoing::Text(text) => session.text(text).await,
+            Outgoing::Ping => session.ping(b"").await,
+            Outgoing::Pong(bytes) => session.pong(&bytes).await,
+        };
+        if result.is_err() {
+            break;
+        }
+    }
+}
+
+/// 接続を閉じる理由
+fn close_reason(code: CloseCode, description: &str) -> Option<CloseReason> {
+    Some(CloseReason {
+        code,
+        description: Some(description.to_string()),
+    })
+}
+
+/// 指定したタイプのいずれかを現在持つポケモンについて、図鑑 No とタイプの組を求める。
+/// 該当するものがない場合は空とする。
+async fn current_types(
+    context: &RequestContext,
+    types: &[String],
+) -> anyhow::Result<Vec<(i32, Vec<String>)>> {
+    let repository = context.pokemon_repository();
+    let query = PokemonQuery {
+        any_types: types
+            .iter()
+            .filter_map(|t| PokemonType::try_from(t.clone()).ok())
+            .collect(),
+        ..Default::default()
+    };
+    let pokemon = actix_web::rt::task::spawn_blocking(move || {
+        PokemonListService::new(repository).search(&query)
+    })
+    .await??;
+    Ok(pokemon
+        .into_iter()
+        .map(|p| (*p.get_number(), p.get_types().clone()))
+        .collect())
+}
+
+/// クライアントからのメッセージを処理し、返信を返す
+async fn handle_text(
+    text: &str,
+    subscriptions: &mut Subscriptions,
+    context: &RequestContext,
+) -> String {
+    let message = match serde_json::from_str::<ClientMessage>(text) {
+        Ok(message) => message,
+        Err(e) => return error_message(format!("不正なメッセージです: {}", e)),
+    };
+    let (ClientMessage::Subscribe { types, .. } | ClientMessage::Unsubscribe { types, .. }) =
+        &message;
+    if let Some(invalid) = types
+        .iter()
+        .find(|t| PokemonType::try_from(t.to_string()).is_err())
+    {
+        return error_message(format!("不正なタイプです: {}", invalid));
+    }
+    match message {
+        ClientMessage::Subscribe { numbers, types } => {
+            if !subscriptions.add_numbers(numbers) {
+                return error_message(format!(
+                    "購読できる図鑑 No は {} 件までです",
+                    MAX_SUBSCRIBED_NUMBERS
+                ));
+            }
+            let types = types
+                .into_iter()
+                .filter(|t| !subscriptions.types.contains(t))
+                .collect::<Vec<_>>();
+            if !types.is_empty() {
+                match current_types(context, &types).await {
+                    Ok(current) => subscriptions.add_types(types, current),
+                    Err(e) => {
+                        log::error!("WebSocket subscribe failed: {:?}", e);
+                        return error_message("購読に失敗しました");
+                    }
+                }
+            }
+        }
+        ClientMessage::Unsubscribe { numbers, types } => {
+            for number in numbers {
+                subscriptions.numbers.remove(&number);
+            }
+            subscriptions.remove_types(&types);
+        }
+    }
+    serde_json::to_string(&Subscribed {
+        r#type: "subscribed",
+        numbers: &subscriptions.numbers,
+        types: &subscriptions.types,
+    })
+    .unwrap_or_default()
+}
+
+/// 1 つの接続を処理する。
+/// 送信キューがあふれた（クライアントの受信が追いつかない）場合や、イベントを取りこぼした場合は接続を閉じる。
+async fn serve(
+    session: Session,
+    stream: actix_ws::MessageStream,
+    mut feed: Subscription,
+    context: RequestContext,
+    readiness: Readiness,
+) {
+    let (tx, rx) = mpsc::channel(CONFIG.ws_send_buffer.max(1));
+    let writer = actix_web::rt::spawn(write(session.clone(), rx));
+    let mut stream = stream
+        .max_frame_size(MAX_MESSAGE_SIZE)
+        .aggregate_continuations()
+        .max_continuation_size(MAX_MESSAGE_SIZE);
+    let interval = Duration::from_secs(CONFIG.ws_heartbeat_interval.max(1));
+    let mut heartbeat = actix_web::rt::time::interval(interval);
+    // 最初の tick はすぐに完了するため読み捨てる
+    heartbeat.tick().await;
+    let mut last_seen = Instant::now();
+    let mut subscriptions = Subscriptions::default();
+
+    let reason = loop {
+        let outgoing = tokio::select! {
+            message = stream.recv() => match message {
+                Some(Ok(message)) => {
+                    last_seen = Instant::now();
+                    match message {
+                        AggregatedMessage::Text(text) => {
+                            Outgoing::Text(handle_text(&text, &mut subscriptions, &context).await)
+                        }
+                        AggregatedMessage::Binary(_) => {
+                            Outgoing::Text(error_message("バイナリのメッセージには対応していません"))
+                        }
+                        AggregatedMessage::Ping(bytes) => Outgoing::Pong(bytes),
+                        AggregatedMessage::Pong(_) => continue,
+                        AggregatedMessage::Close(reason) => break reason,
+                    }
+                }
+                Some(Err(e)) => {
+                    log::info!("WebSocket protocol error: {:?}", e);
+                    break close_reason(CloseCode::Protocol, "protocol error");
+                }
+                None => break None,
+            },
+            event = feed.receiver.recv() => match event {
+                Ok(event) if subscriptions.accept(&event.change) => Outgoing::Text(notification(&event)),
+                Ok(_) => continue,
+                Err(_) => break close_reason(CloseCode::Again, "missed events"),
+            },
+            _ = heartbeat.tick() => {
+                if !readiness.is_ready() {
+                    break close_reason(CloseCode::Away, "server shutting down");
+                }
+                // Pong を含め、2 回分の間隔の間にクライアントから何も届かなければ切断とみなす
+                if last_seen.elapsed() > interval * 2 {
+                    break close_reason(CloseCode::Away, "heartbeat timeout");
+                }
+                Outgoing::Ping
+            }
+        };
+        match tx.try_send(outgoing) {
+            Ok(_) => {}
+            Err(TrySendError::Full(_)) => break close_reason(CloseCode::Again, "too slow"),
+            Err(TrySendError::Closed(_)) => break None,
+        }
+    };
+
+    // 送信待ちのメッセージは破棄して Close フレームを送る
+    writer.abort();
+    let _ = actix_web::rt::time::timeout(CLOSE_TIMEOUT, session.close(reason)).await;
+}
+
+#[utoipa::path(
+    tag = "pokemon",
+    params(("Sec-WebSocket-Protocol" = Option<String>, Header, description = "`pokedex` と、Authorization ヘッダーの代わりの `bearer.<token>` または `apikey.<key>`")),
+    responses(
+        (status = 101, description = "WebSocket に切り替えた。`subscribe`・`unsubscribe` のメッセージで購読する"),
+        (status = 400, description = "WebSocket のハンドシェイクではない"),
+    )
+)]
+#[get("/ws")]
+async fn websocket(
+    _access: ReadAccess,
+    data: web::Data<RequestContext>,
+    readiness: web::Data<Readiness>,
+    req: HttpRequest,
+    body: web::Payload,
+) -> actix_web::Result<HttpResponse> {
+    let (response, session, stream) = actix_ws::handle_with_protocols(&req, body, &[PROTOCOL])?;
+    // 購読を受け付ける前の変更を通知しないよう、接続した時点から受け取る
+    let feed = data.change_feed().subscribe(None);
+    actix_web::rt::spawn(serve(
+        session,
+        stream,
+        feed,
+        data.get_ref().clone(),
+        readiness.get_ref().clone(),
+    ));
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::pokemon_data::PokemonData;
+    use crate::domain::models::pokemon::pokemon::Pokemon;
+    use crate::infra::actix::events::EventId;
+
+    fn pokemon(number: i32, types: &[&str]) -> PokemonData {
+        PokemonData::new(
+            Pokemon::try_new(
+                number,
+                "TestPokemon".to_string(),
+                types.iter().map(|t| t.to_string()).collect(),
+            )
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn client_message_ok() {
+        let message =
+            serde_json::from_str::<ClientMessage>(r#"{"type":"subscribe","types":["Fire"]}"#)
+                .unwrap();
+        assert_eq!(
+            message,
+            ClientMessage::Subscribe {
+                numbers: vec![],
+                types: vec!["Fire".to_string()]
+            }
+        );
+        assert!(serde_json::from_str::<ClientMessage>(r#"{"type":"publish"}"#).is_err());
+    }
+
+    #[test]
+    fn accept_by_number() {
+        let mut subscriptions = Subscriptions::default();
+        subscriptions.numbers.insert(1);
+        assert!(subscriptions.accept(&PokemonChange::Updated(pokemon(1, &["Fire"]))));
+        assert!(subscriptions.accept(&PokemonChange::Deleted(1)));
+        assert!(!subscriptions.accept(&PokemonChange::Created(pokemon(2, &["Fire"]))));
+    }
+
+    #[test]
+    fn add_numbers_up_to_limit() {
+        let mut subscriptions = Subscriptions::default();
+        assert!(subscriptions.add_numbers((1..=MAX_SUBSCRIBED_NUMBERS as i32).collect()));
+        // 購読済みのものは数えない
+        assert!(subscriptions.add_numbers(vec![1, 2]));
+        assert!(!subscriptions.add_numbers(vec![0]));
+        assert_eq!(subscriptions.numbers.len(), MAX_SUBSCRIBED_NUMBERS);
+    }
+
+    #[test]
+    fn accept_by_type() {
+        let mut subscriptions = Subscriptions::default();
+        subscriptions.add_types(
+            vec!["Fire".to_string()],
+            vec![
+                (1, vec!["Fire".to_string()]),
+                (2, vec!["Water".to_string()]),
+            ],
+        );
+        // 購読時点で該当していたポケモンの削除
+        assert!(subscriptions.accept(&PokemonChange::Deleted(1)));
+        assert!(!subscriptions.accept(&PokemonChange::Deleted(2)));
+
+        assert!(subscriptions.accept(&PokemonChange::Created(pokemon(3, &["Fire", "Flying"]))));
+        // 購読中のタイプから外れる更新は通知し、以降は通知しない
+        assert!(subscriptions.accept(&PokemonChange::Updated(pokemon(3, &["Water"]))));
+        assert!(!subscriptions.accept(&PokemonChange::Deleted(3)));
+
+        subscriptions.accept(&PokemonChange::Created(pokemon(4, &["Fire"])));
+        subscriptions.remove_types(&["Fire".to_string()]);
+        assert!(!subscriptions.accept(&PokemonChange::Deleted(4)));
+    }
+
+    #[test]
+    fn accept_by_type_from_empty() {
+        // 1 件も登録されていない時点で購読しても、以降の登録・削除を通知する
+        let mut subscriptions = Subscriptions::default();
+        subscriptions.add_types(vec!["Fire".to_string()], vec![]);
+        assert!(subscriptions.accept(&PokemonChange::Created(pokemon(1, &["Fire"]))));
+        assert!(subscriptions.accept(&PokemonChange::Deleted(1)));
+    }
+
+    #[test]
+    fn notification_ok() {
+        let event = ChangeEvent {
+            id: EventId { epoch: 100, seq: 6 },
+            change: PokemonChange::Deleted(1),
+        };
+        assert_eq!(
+            notification(&event),
+            "{\"id\":\"100-6\",\"number\":1,\"type\":\"deleted\"}"
+        );
+    }
+}
@@ -0,0 +1,45 @@
+//! 監査ログ。ポケモンのドメインイベントを購読し、何が起きたかをログに出力する。
+
+use crate::domain::models::pokemon::{
+    pokemon_event::PokemonEvent, pokemon_event_bus::PokemonEventSubscriber,
+};
+use anyhow::Result;
+
+/// ドメインイベントを監査ログとして出力する購読者
+pub struct AuditLogSubscriber;
+
+impl PokemonEventSubscriber for AuditLogSubscriber {
+    fn handle(&self, event: &PokemonEvent) -> Result<()> {
+        let number: i32 = event.number().clone().into();
+        match event {
+            PokemonEvent::Registered(e) => log::info!(
+                target: "audit",
+                "Pokemon registered: no {}, name {}, types {:?}",
+                number,
+                String::from(e.pokemon.name.clone()),
+                Vec::<String>::from(e.pokemon.types.clone())
+            ),
+            PokemonEvent::Renamed(e) => log::info!(
+                target: "audit",
+                "Pokemon renamed: no {}, {} -> {}",
+                number,
+                String::from(e.from.clone()),
+                String::from(e.to.clone())
+            ),
+            PokemonEvent::Retyped(e) => log::info!(
+                target: "audit",
+                "Pokemon retyped: no {}, {:?} -> {:?}",
+                number,
+                Vec::<String>::from(e.from.clone()),
+                Vec::<String>::from(e.to.clone())
+            ),
+            PokemonEvent::Deleted(e) => log::info!(
+                target: "audit",
+                "Pokemon deleted: no {}, soft {}",
+                number,
+                e.soft
+            ),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+//! Diesel を用いて API キーのデータをやり取りするためのリポジトリ。
+
+use super::schema::api_keys;
+use crate::domain::models::api_key::{
+    api_key::{ApiKey, NewApiKey},
+    api_key_repository::ApiKeyRepository,
+};
+use crate::domain::models::role::permission::Permission;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool};
+use std::convert::TryFrom;
+
+/// Diesel が直接利用するデータモデル。
+#[derive(Debug, Queryable, Clone)]
+pub struct ApiKeyEntity {
+    pub id: i32,
+    pub name: String,
+    pub key_hash: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "api_keys"]
+pub struct NewApiKeyEntity {
+    pub name: String,
+    pub key_hash: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// ApiKey の振る舞い： ApiKeyEntity から ApiKey への変換処理。
+/// 未知のスコープは無視する。
+impl From<ApiKeyEntity> for ApiKey {
+    fn from(entity: ApiKeyEntity) -> ApiKey {
+        ApiKey {
+            id: entity.id,
+            name: entity.name,
+            key_hash: entity.key_hash,
+            scopes: entity
+                .scopes
+                .into_iter()
+                .filter_map(|s| Permission::try_from(s).ok())
+                .collect(),
+            expires_at: entity.expires_at,
+            last_used_at: entity.last_used_at,
+            created_at: entity.created_at,
+            revoked_at: entity.revoked_at,
+        }
+    }
+}
+
+pub struct ApiKeyRepositoryImpl {
+    pub pool: Box<Pool<ConnectionManager<PgConnection>>>,
+}
+
+impl ApiKeyRepository for ApiKeyRepositoryImpl {
+    /// ハッシュ値に一致する API キーを返却する
+    fn find_by_hash(&self, target_hash: &str) -> Result<Option<ApiKey>> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        let result = api_keys::table
+            .filter(api_keys::key_hash.eq(target_hash))
+            .first::<ApiKeyEntity>(&conn)
+            .optional()?;
+        Ok(result.map(ApiKey::from))
+    }
+
+    /// ID に一致する API キーを返却する
+    fn find_by_id(&self, target_id: i32) -> Result<Option<ApiKey>> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        let result = api_keys::table
+            .find(target_id)
+            .first::<ApiKeyEntity>(&conn)
+            .optional()?;
+        Ok(result.map(ApiKey::from))
+    }
+
+    /// API キーを挿入し、採番された API キーを返却する
+    fn insert(&self, data: &NewApiKey) -> Result<ApiKey> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        let new_api_key = NewApiKeyEntity {
+            name: data.name.clone(),
+            key_hash: data.key_hash.clone(),
+            scopes: data.scopes.iter().map(|s| String::from(*s)).collect(),
+            expires_at: data.expires_at,
+        };
+        let entity = diesel::insert_into(api_keys::table)
+            .values(&new_api_key)
+            .get_result::<ApiKeyEntity>(&conn)?;
+        Ok(ApiKey::from(entity))
+    }
+
+    /// API キーを失効させる
+    fn revoke(&self, target_id: i32, at: DateTime<Utc>) -> Result<()> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        diesel::update(api_keys::table.find(target_id))
+            .set(api_keys::revoked_at.eq(Some(at)))
+            .execute(&conn)?;
+        Ok(())
+    }
+
+    /// 最終利用日時を記録する
+    fn touch(&self, target_id: i32, at: DateTime<Utc>) -> Result<()> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        diesel::update(api_keys::table.find(target_id))
+            .set(api_keys::last_used_at.eq(Some(at)))
+            .execute(&conn)?;
+        Ok(())
+    }
+}
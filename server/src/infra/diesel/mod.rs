@@ -1,2 +1,9 @@
+// Diesel 1.x の derive / table! マクロが生成する impl は非ローカル定義として警告されるため抑制する。
+#![allow(non_local_definitions)]
+
+pub mod api_key_repository;
+pub mod outbox_repository;
 pub mod pokemon_repository;
+pub mod role_repository;
 pub mod schema;
+pub mod webhook_repository;
@@ -0,0 +1,152 @@
+//! Diesel を用いてアウトボックスのデータをやり取りするためのリポジトリ。
+
+use super::schema::outbox;
+use crate::domain::models::outbox::{
+    outbox::{NewOutboxMessage, OutboxMessage},
+    outbox_repository::OutboxRepository,
+};
+use crate::domain::models::pokemon::pokemon_event::PokemonEvent;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool};
+
+/// Diesel が直接利用するデータモデル。
+#[derive(Debug, Queryable, Clone)]
+pub struct OutboxEntity {
+    pub id: i64,
+    pub event_type: String,
+    pub aggregate_id: i32,
+    pub payload: String,
+    pub created_at: DateTime<Utc>,
+    pub attempts: i32,
+    /// 調査のために記録するもので、配信には使わない
+    #[allow(dead_code)]
+    pub last_error: Option<String>,
+    /// 未配信の判定は SQL の条件で行うため、読み出した値は使わない
+    #[allow(dead_code)]
+    pub sent_at: Option<DateTime<Utc>>,
+    /// Webhook の通知を作ったかどうかの判定も SQL の条件で行う
+    #[allow(dead_code)]
+    pub webhooks_queued_at: Option<DateTime<Utc>>,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "outbox"]
+pub struct NewOutboxEntity {
+    pub event_type: String,
+    pub aggregate_id: i32,
+    pub payload: String,
+}
+
+/// OutboxMessage の振る舞い： OutboxEntity から OutboxMessage への変換処理。
+impl From<OutboxEntity> for OutboxMessage {
+    fn from(entity: OutboxEntity) -> OutboxMessage {
+        OutboxMessage {
+            id: entity.id,
+            event_type: entity.event_type,
+            aggregate_id: entity.aggregate_id,
+            payload: entity.payload,
+            created_at: entity.created_at,
+            attempts: entity.attempts,
+        }
+    }
+}
+
+/// 1 つの接続でドメインイベントをアウトボックスに記録する。
+/// 呼び出し側のトランザクションの中で呼び出し、変更と記録をまとめて確定させる。
+pub fn enqueue_with(conn: &PgConnection, events: &[PokemonEvent]) -> Result<()> {
+    if events.is_empty() {
+        return Ok(());
+    }
+    let messages = events
+        .iter()
+        .map(|event| {
+            let message = NewOutboxMessage::from(event);
+            NewOutboxEntity {
+                event_type: message.event_type,
+                aggregate_id: message.aggregate_id,
+                payload: message.payload,
+            }
+        })
+        .collect::<Vec<_>>();
+    diesel::insert_into(outbox::table)
+        .values(&messages)
+        .execute(conn)
+        .context("Error saving outbox messages")?;
+    Ok(())
+}
+
+pub struct OutboxRepositoryImpl {
+    pub pool: Box<Pool<ConnectionManager<PgConnection>>>,
+}
+
+impl OutboxRepository for OutboxRepositoryImpl {
+    /// 未配信のメッセージを ID 順に行ロックして読み出し、先頭から続く取り出されていないものを期限まで取り出し中にする。
+    /// 行ロックで待つため、同時に取り出そうとした他のインスタンスは確定後の取り出し中の状態を見る。
+    fn claim(&self, limit: i64, locked_until: DateTime<Utc>) -> Result<Vec<OutboxMessage>> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        conn.transaction::<_, anyhow::Error, _>(|| {
+            let now = Utc::now();
+            let results = outbox::table
+                .filter(outbox::sent_at.is_null())
+                .order(outbox::id.asc())
+                .limit(limit)
+                .for_update()
+                .load::<OutboxEntity>(&conn)?
+                .into_iter()
+                .take_while(|entity| entity.locked_until.is_none_or(|until| until < now))
+                .collect::<Vec<_>>();
+            if results.is_empty() {
+                return Ok(vec![]);
+            }
+            let ids = results.iter().map(|entity| entity.id).collect::<Vec<_>>();
+            diesel::update(outbox::table.filter(outbox::id.eq_any(&ids)))
+                .set(outbox::locked_until.eq(Some(locked_until)))
+                .execute(&conn)?;
+            Ok(results.into_iter().map(OutboxMessage::from).collect())
+        })
+    }
+
+    /// 配信日時を記録し、取り出し中の期限を消す
+    fn mark_sent(&self, target_id: i64) -> Result<()> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        diesel::update(outbox::table.find(target_id))
+            .set((
+                outbox::sent_at.eq(Some(Utc::now())),
+                outbox::locked_until.eq(None::<DateTime<Utc>>),
+            ))
+            .execute(&conn)
+            .with_context(|| format!("Error marking outbox message {} as sent", target_id))?;
+        Ok(())
+    }
+
+    /// 失敗した回数とエラーの内容を記録し、取り出し中の期限を消す
+    fn mark_failed(&self, target_id: i64, error: &str) -> Result<()> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        diesel::update(outbox::table.find(target_id))
+            .set((
+                outbox::attempts.eq(outbox::attempts + 1),
+                outbox::last_error.eq(Some(error)),
+                outbox::locked_until.eq(None::<DateTime<Utc>>),
+            ))
+            .execute(&conn)
+            .with_context(|| format!("Error marking outbox message {} as failed", target_id))?;
+        Ok(())
+    }
+
+    /// 取り出し中の期限を消す
+    fn release(&self, ids: &[i64]) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let conn = self.pool.get().context("failed to get connection")?;
+        diesel::update(outbox::table.filter(outbox::id.eq_any(ids)))
+            .set(outbox::locked_until.eq(None::<DateTime<Utc>>))
+            .execute(&conn)
+            .context("Error releasing outbox messages")?;
+        Ok(())
+    }
+}
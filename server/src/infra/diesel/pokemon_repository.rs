@@ -1,22 +1,41 @@
 //! Diesel を用いてポケモンのデータをやり取りするためのリポジトリ。
 
+use super::outbox_repository::enqueue_with;
 use super::schema::pokemon;
 use super::schema::pokemon::dsl::*;
 use crate::domain::models::pokemon::{
-    pokemon::Pokemon, pokemon_number::PokemonNumber, pokemon_repository::PokemonRepository,
+    pokemon::Pokemon,
+    pokemon_error::PokemonError,
+    pokemon_event::PokemonEvent,
+    pokemon_number::PokemonNumber,
+    pokemon_query::PokemonQuery,
+    pokemon_repository::{PokemonOperation, PokemonRepository},
+    pokemon_type::PokemonType,
 };
 use anyhow::{Context, Result};
-use diesel::pg::PgConnection;
+use chrono::{DateTime, Utc};
+use diesel::dsl::sql;
+use diesel::expression::SqlLiteral;
+use diesel::pg::upsert::excluded;
+use diesel::pg::{Pg, PgConnection};
 use diesel::prelude::*;
+use diesel::query_builder::QueryFragment;
 use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::sql_types::{Array, Bool, Text};
 use std::convert::TryInto;
 
 /// Diesel が直接利用するデータモデル。
-#[derive(Debug, Queryable, Clone)]
+#[derive(Debug, Queryable, QueryableByName, Clone)]
+#[table_name = "pokemon"]
 pub struct PokemonEntity {
     pub no: i32,
     pub name: String,
     pub type_: Vec<String>,
+    pub version: i32,
+    pub updated_at: DateTime<Utc>,
+    /// 論理削除の判定は SQL の条件で行うため、読み出した値は使わない
+    #[allow(dead_code)]
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Insertable)]
@@ -34,82 +53,593 @@ impl From<PokemonEntity> for Pokemon {
             number: entity.no.try_into().unwrap(),
             name: entity.name.try_into().unwrap(),
             types: entity.type_.try_into().unwrap(),
+            version: entity.version,
+            updated_at: Some(entity.updated_at),
         }
     }
 }
 
+/// カーソルから一度に読み込む行数
+const SCAN_FETCH_SIZE: usize = 100;
+
 pub struct PokemonRepositoryImpl {
     pub pool: Box<Pool<ConnectionManager<PgConnection>>>,
 }
 
+/// 論理削除されたデータがあれば `PokemonError::Deleted` を返す。
+/// 登録し直しで論理削除したデータを暗黙に上書きしないよう、登録の前に呼び出す。
+/// 行をロックするため、登録までの間に他の接続で論理削除されることはない。
+fn ensure_not_deleted(conn: &PgConnection, target_number: i32) -> Result<()> {
+    let deleted = pokemon
+        .find(target_number)
+        .select(deleted_at)
+        .for_update()
+        .first::<Option<DateTime<Utc>>>(conn)
+        .optional()
+        .with_context(|| format!("Error locking pokemon {}", target_number))?;
+    match deleted {
+        Some(Some(_)) => Err(PokemonError::Deleted(target_number).into()),
+        _ => Ok(()),
+    }
+}
+
+/// LIKE のパターンで特別な意味を持つ文字をエスケープする
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// attacker のタイプが弱点であるという条件。
+/// 倍率は 2 倍・0.5 倍・等倍のいずれかのため、倍率の積が等倍を超えるのは、
+/// 2 倍となるタイプを 0.5 倍となるタイプより多く持つ場合となる。
+fn weak_to_condition(attacker: &PokemonType) -> SqlLiteral<Bool, impl QueryFragment<Pg>> {
+    let defenders = |matches: fn(f32) -> bool| {
+        PokemonType::ALL
+            .iter()
+            .filter(|defender| matches(attacker.effectiveness(defender)))
+            .cloned()
+            .map(String::from)
+            .collect::<Vec<_>>()
+    };
+    sql::<Bool>("(SELECT count(*) FROM unnest(pokemon.type) AS t WHERE t = ANY(")
+        .bind::<Array<Text>, _>(defenders(|e| e > 1.0))
+        .sql(")) > (SELECT count(*) FROM unnest(pokemon.type) AS t WHERE t = ANY(")
+        .bind::<Array<Text>, _>(defenders(|e| e < 1.0))
+        .sql("))")
+}
+
+/// 条件に一致するポケモンデータを読み込むクエリ
+fn filtered(query: &PokemonQuery) -> pokemon::BoxedQuery<'static, Pg> {
+    let mut statement = pokemon.filter(deleted_at.is_null()).into_boxed();
+    if !query.any_types.is_empty() {
+        let any_types = query
+            .any_types
+            .iter()
+            .cloned()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        statement = statement.filter(type_.overlaps_with(any_types));
+    }
+    if let Some(attacker) = &query.weak_to {
+        statement = statement.filter(weak_to_condition(attacker));
+    }
+    if let Some(part) = &query.name_contains {
+        statement = statement.filter(name.ilike(format!("%{}%", escape_like(part))));
+    }
+    if let Some(from) = query.number_from {
+        statement = statement.filter(no.ge(from));
+    }
+    if let Some(to) = query.number_to {
+        statement = statement.filter(no.le(to));
+    }
+    statement
+}
+
+/// 1 つの接続で条件に一致するポケモンデータを図鑑 No 順に読み込む。
+fn search_with(conn: &PgConnection, query: &PokemonQuery) -> Result<Vec<Pokemon>> {
+    let results = filtered(query)
+        .order(no.asc())
+        .load::<PokemonEntity>(conn)
+        .context("Error searching pokemon")?;
+    Ok(results.into_iter().map(Pokemon::from).collect())
+}
+
+/// 1 つの接続で条件に一致する件数を数え、図鑑 No 順に offset 件飛ばして最大 limit 件を読み込む。
+fn search_page_with(
+    conn: &PgConnection,
+    query: &PokemonQuery,
+    offset: i64,
+    limit: i64,
+) -> Result<(Vec<Pokemon>, i64)> {
+    conn.transaction::<_, anyhow::Error, _>(|| {
+        let total = filtered(query)
+            .count()
+            .get_result::<i64>(conn)
+            .context("Error counting pokemon")?;
+        let results = filtered(query)
+            .order(no.asc())
+            .offset(offset)
+            .limit(limit)
+            .load::<PokemonEntity>(conn)
+            .context("Error searching pokemon")?;
+        Ok((results.into_iter().map(Pokemon::from).collect(), total))
+    })
+}
+
+/// 1 つの接続でポケモンデータを挿入する。
+/// 論理削除されている場合は `PokemonError::Deleted` を返す。
+fn insert_with(conn: &PgConnection, data: &Pokemon) -> Result<()> {
+    let new_pokemon = NewPokemon {
+        no: data.number.clone().into(),
+        name: data.name.clone().into(),
+        type_: data.types.clone().into(),
+    };
+    conn.transaction::<_, anyhow::Error, _>(|| {
+        ensure_not_deleted(conn, new_pokemon.no)?;
+        diesel::insert_into(pokemon::table)
+            .values(&new_pokemon)
+            .execute(conn)
+            .with_context(|| format!("Error saving new pokemon {}", new_pokemon.no))?;
+        Ok(())
+    })
+}
+
+/// 1 つの接続でポケモンデータを更新する。
+/// 保存されている版が `data.version` と一致しない場合は `PokemonError::VersionMismatch` を返す。
+fn update_with(conn: &PgConnection, data: &Pokemon) -> Result<()> {
+    let target_number: i32 = data.number.clone().into();
+    let target_name: String = data.name.clone().into();
+    let target_types: Vec<String> = data.types.clone().into();
+    let updated = diesel::update(
+        pokemon
+            .filter(no.eq(target_number))
+            .filter(version.eq(data.version))
+            .filter(deleted_at.is_null()),
+    )
+    .set((
+        name.eq(target_name),
+        type_.eq(target_types),
+        version.eq(version + 1),
+    ))
+    .execute(conn)
+    .with_context(|| format!("Unable to update pokemon {}", target_number))?;
+    if updated == 0 {
+        return Err(PokemonError::VersionMismatch {
+            number: target_number,
+            expected: data.version,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// 1 つの接続でポケモンデータを登録し、既に存在する場合は置き換える。
+/// 論理削除されている場合は `PokemonError::Deleted` を返す。
+fn upsert_with(conn: &PgConnection, data: &Pokemon) -> Result<Pokemon> {
+    let new_pokemon = NewPokemon {
+        no: data.number.clone().into(),
+        name: data.name.clone().into(),
+        type_: data.types.clone().into(),
+    };
+    let entity = conn.transaction::<_, anyhow::Error, _>(|| {
+        ensure_not_deleted(conn, new_pokemon.no)?;
+        diesel::insert_into(pokemon::table)
+            .values(&new_pokemon)
+            .on_conflict(no)
+            .do_update()
+            .set((
+                name.eq(excluded(name)),
+                type_.eq(excluded(type_)),
+                version.eq(version + 1),
+            ))
+            .get_result::<PokemonEntity>(conn)
+            .with_context(|| format!("Unable to upsert pokemon {}", new_pokemon.no))
+    })?;
+    Ok(Pokemon::from(entity))
+}
+
+/// 1 つの接続でポケモンデータを削除し、削除した件数を返す。
+/// 論理削除されたものは対象にしない。版を指定した場合は、保存されている版と一致するときだけ削除する。
+fn delete_with(
+    conn: &PgConnection,
+    number: &PokemonNumber,
+    expected_version: Option<i32>,
+) -> Result<usize> {
+    let target_number: i32 = number.clone().into();
+    let target = pokemon
+        .filter(no.eq(target_number))
+        .filter(deleted_at.is_null());
+    match expected_version {
+        Some(expected) => diesel::delete(target.filter(version.eq(expected))).execute(conn),
+        None => diesel::delete(target).execute(conn),
+    }
+    .with_context(|| format!("Error deleting pokemon {}", target_number))
+}
+
+/// 1 つの接続でポケモンデータに削除日時を記録し、記録した件数を返す。
+/// 版を指定した場合は、保存されている版と一致するときだけ記録する。
+fn soft_delete_with(
+    conn: &PgConnection,
+    number: &PokemonNumber,
+    expected_version: Option<i32>,
+) -> Result<usize> {
+    let target_number: i32 = number.clone().into();
+    let target = pokemon
+        .filter(no.eq(target_number))
+        .filter(deleted_at.is_null());
+    let now = deleted_at.eq(Some(Utc::now()));
+    match expected_version {
+        Some(expected) => diesel::update(target.filter(version.eq(expected)))
+            .set(now)
+            .execute(conn),
+        None => diesel::update(target).set(now).execute(conn),
+    }
+    .with_context(|| format!("Error deleting pokemon {}", target_number))
+}
+
+/// 削除の対象がなかった理由を返す。
+/// 対象が存在すれば版の不一致、存在しなければ `PokemonError::NotFound` とする。
+fn not_deleted(
+    conn: &PgConnection,
+    number: &PokemonNumber,
+    expected_version: Option<i32>,
+) -> Result<PokemonError> {
+    let target_number: i32 = number.clone().into();
+    let exists = diesel::select(diesel::dsl::exists(
+        pokemon
+            .filter(no.eq(target_number))
+            .filter(deleted_at.is_null()),
+    ))
+    .get_result::<bool>(conn)
+    .with_context(|| format!("Error finding pokemon {}", target_number))?;
+    Ok(match expected_version {
+        Some(expected) if exists => PokemonError::VersionMismatch {
+            number: target_number,
+            expected,
+        },
+        _ => PokemonError::NotFound(target_number),
+    })
+}
+
+/// 1 つの接続で操作を実行する。
+/// 削除対象が存在しない場合は `PokemonError::NotFound` を、版が一致しない場合は `PokemonError::VersionMismatch` を返す。
+fn execute(conn: &PgConnection, operation: &PokemonOperation) -> Result<()> {
+    let (number, expected_version, deleted) = match operation {
+        PokemonOperation::Insert(data) => return insert_with(conn, data),
+        PokemonOperation::Update(data) => return update_with(conn, data),
+        PokemonOperation::Upsert(data) => return upsert_with(conn, data).map(|_| ()),
+        PokemonOperation::Delete(number, expected) => {
+            (number, *expected, delete_with(conn, number, *expected)?)
+        }
+        PokemonOperation::SoftDelete(number, expected) => (
+            number,
+            *expected,
+            soft_delete_with(conn, number, *expected)?,
+        ),
+    };
+    if deleted == 0 {
+        return Err(not_deleted(conn, number, expected_version)?.into());
+    }
+    Ok(())
+}
+
+/// 1 つの接続で操作とアウトボックスへの記録を 1 つのトランザクション（入れ子の場合はセーブポイント）で実行する
+fn save_with(
+    conn: &PgConnection,
+    operation: &PokemonOperation,
+    events: &[PokemonEvent],
+) -> Result<()> {
+    conn.transaction::<_, anyhow::Error, _>(|| {
+        execute(conn, operation)?;
+        enqueue_with(conn, events)
+    })
+}
+
 impl PokemonRepository for PokemonRepositoryImpl {
     /// ポケモンの一覧を出力する
     fn list(&self) -> Result<Vec<Pokemon>> {
         let conn = self.pool.get().context("failed to get connection")?;
-        match pokemon.load::<PokemonEntity>(&conn) {
-            Ok(result) => match result.len() {
-                0 => Err(anyhow::anyhow!("Not Found Pokemon List")),
-                _ => Ok(result
-                    .iter()
-                    .map(|c| Pokemon::from(c.clone()))
-                    .collect::<Vec<Pokemon>>()),
-            },
+        match pokemon
+            .filter(deleted_at.is_null())
+            .load::<PokemonEntity>(&conn)
+        {
+            Ok(result) => Ok(result
+                .iter()
+                .map(|c| Pokemon::from(c.clone()))
+                .collect::<Vec<Pokemon>>()),
             Err(e) => Err(anyhow::anyhow!(e)),
         }
     }
 
+    /// 条件をクエリに変換して読み込む
+    fn search(&self, query: &PokemonQuery) -> Result<Vec<Pokemon>> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        search_with(&conn, query)
+    }
+
+    /// 条件をクエリに変換し、件数とページを読み込む
+    fn search_page(
+        &self,
+        query: &PokemonQuery,
+        offset: i64,
+        limit: i64,
+    ) -> Result<(Vec<Pokemon>, i64)> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        search_page_with(&conn, query, offset, limit)
+    }
+
     /// 引数で渡した図鑑 No のポケモンを返却する
     fn find_by_number(&self, number: &PokemonNumber) -> Result<Pokemon> {
         let conn = self.pool.get().context("failed to get connection")?;
-        let target_num: i32 = number.clone().try_into().unwrap();
+        let target_num: i32 = number.clone().into();
         match pokemon
             .filter(pokemon::no.eq(target_num))
+            .filter(deleted_at.is_null())
             .load::<PokemonEntity>(&conn)
         {
-            Ok(result) => match result.get(0) {
+            Ok(result) => match result.first() {
                 Some(value) => Ok(Pokemon::from(value.clone())),
-                None => Err(anyhow::anyhow!("Not Found Pokemon number:{}", target_num)),
+                None => Err(PokemonError::NotFound(target_num).into()),
             },
             Err(e) => Err(anyhow::anyhow!(e)),
         }
     }
 
-    /// ポケモンデータを挿入する
-    fn insert(&self, data: &Pokemon) -> Result<()> {
+    /// ポケモンデータを登録し、既に存在する場合は置き換える。
+    /// 置き換え前の行をロックして読み込むため、イベントは実際に置き換えた内容と一致する。
+    fn upsert(
+        &self,
+        data: &Pokemon,
+        events: &dyn Fn(Option<&Pokemon>, &Pokemon) -> Vec<PokemonEvent>,
+    ) -> Result<(Pokemon, Vec<PokemonEvent>)> {
         let conn = self.pool.get().context("failed to get connection")?;
-        let new_pokemon = NewPokemon {
-            no: data.number.clone().try_into().unwrap(),
-            name: data.name.clone().try_into().unwrap(),
-            type_: data.types.clone().try_into().unwrap(),
-        };
+        let target_number: i32 = data.number.clone().into();
+        conn.transaction::<_, anyhow::Error, _>(|| {
+            let before = pokemon
+                .filter(no.eq(target_number))
+                .filter(deleted_at.is_null())
+                .for_update()
+                .first::<PokemonEntity>(&conn)
+                .optional()
+                .with_context(|| format!("Error locking pokemon {}", target_number))?
+                .map(Pokemon::from);
+            let stored = upsert_with(&conn, data)?;
+            let events = events(before.as_ref(), &stored);
+            enqueue_with(&conn, &events)?;
+            Ok((stored, events))
+        })
+    }
 
-        diesel::insert_into(pokemon::table)
-            .values(&new_pokemon)
-            .execute(&conn)
-            .expect("Error saving new pokemon");
-        Ok(())
+    /// 論理削除されたポケモンデータを元に戻す
+    fn restore(
+        &self,
+        number: &PokemonNumber,
+        events: &dyn Fn(&Pokemon) -> Vec<PokemonEvent>,
+    ) -> Result<Option<(Pokemon, Vec<PokemonEvent>)>> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        let target_number: i32 = number.clone().into();
+        conn.transaction::<_, anyhow::Error, _>(|| {
+            let entity = diesel::update(
+                pokemon
+                    .filter(no.eq(target_number))
+                    .filter(deleted_at.is_not_null()),
+            )
+            .set(deleted_at.eq(None::<DateTime<Utc>>))
+            .get_result::<PokemonEntity>(&conn)
+            .optional()
+            .with_context(|| format!("Error restoring pokemon {}", target_number))?;
+            match entity.map(Pokemon::from) {
+                Some(restored) => {
+                    let events = events(&restored);
+                    enqueue_with(&conn, &events)?;
+                    Ok(Some((restored, events)))
+                }
+                None => Ok(None),
+            }
+        })
     }
 
-    /// ポケモンデータを更新する
-    fn update(&self, data: &Pokemon) -> Result<()> {
+    /// 複数の操作をまとめて実行する
+    fn batch(
+        &self,
+        operations: &[(PokemonOperation, Vec<PokemonEvent>)],
+        atomic: bool,
+    ) -> Result<Vec<Result<()>>> {
         let conn = self.pool.get().context("failed to get connection")?;
-        let target_number: i32 = data.number.clone().try_into().unwrap();
-        let target_name: String = data.name.clone().try_into().unwrap();
-        let target_types: Vec<String> = data.types.clone().try_into().unwrap();
-        diesel::update(pokemon.find(target_number))
-            .set((name.eq(target_name), type_.eq(target_types)))
-            .execute(&conn)
-            .expect(&format!("Unable to find pokemon {}", target_number));
-        Ok(())
+        if !atomic {
+            return Ok(operations
+                .iter()
+                .map(|(operation, events)| save_with(&conn, operation, events))
+                .collect());
+        }
+
+        let mut results = Vec::with_capacity(operations.len());
+        let committed = conn.transaction::<_, anyhow::Error, _>(|| {
+            for (operation, events) in operations {
+                let result = save_with(&conn, operation, events);
+                let failed = result.is_err();
+                results.push(result);
+                if failed {
+                    return Err(anyhow::anyhow!("rollback"));
+                }
+            }
+            Ok(())
+        });
+        // 全ての操作が成功したのに確定できなかった場合は、バッチ全体の失敗とする
+        if let Err(e) = committed {
+            if results.iter().all(|result| result.is_ok()) {
+                return Err(e);
+            }
+        }
+        Ok(results)
     }
 
-    /// ポケモンデータを削除する
-    fn delete(&self, number: &PokemonNumber) -> Result<()> {
+    /// 操作とアウトボックスへの記録を 1 つのトランザクションで実行する
+    fn save(&self, operation: &PokemonOperation, events: &[PokemonEvent]) -> Result<()> {
         let conn = self.pool.get().context("failed to get connection")?;
-        let target_number: i32 = number.clone().try_into().unwrap();
-        diesel::delete(pokemon.find(target_number))
+        save_with(&conn, operation, events)
+    }
+
+    /// サーバー側のカーソルで一定の行数ずつ読み込み、1 件ずつ渡す
+    fn scan(&self, f: &mut dyn FnMut(Pokemon) -> Result<()>) -> Result<()> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        // カーソルはトランザクションの中でのみ有効
+        conn.transaction::<_, anyhow::Error, _>(|| {
+            diesel::sql_query(
+                "DECLARE pokemon_scan NO SCROLL CURSOR FOR \
+                 SELECT no, name, type AS type_, version, updated_at, deleted_at \
+                 FROM pokemon WHERE deleted_at IS NULL ORDER BY no",
+            )
             .execute(&conn)
-            .expect("Error deleting pokemon");
-        Ok(())
+            .context("Error declaring pokemon cursor")?;
+            loop {
+                let rows =
+                    diesel::sql_query(format!("FETCH {} FROM pokemon_scan", SCAN_FETCH_SIZE))
+                        .load::<PokemonEntity>(&conn)
+                        .context("Error fetching pokemon cursor")?;
+                let last = rows.len() < SCAN_FETCH_SIZE;
+                for row in rows {
+                    f(Pokemon::from(row))?;
+                }
+                if last {
+                    return Ok(());
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::pokemon::pokemon_type::PokemonType;
+    use std::convert::TryFrom;
+
+    /// DATABASE_URL の PostgreSQL に接続し、テスト用のトランザクションで実行する（変更はロールバックされる）。
+    fn with_test_connection(f: impl FnOnce(&PgConnection) -> Result<()>) {
+        dotenv::dotenv().ok();
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let conn = PgConnection::establish(&url).unwrap();
+        conn.test_transaction::<_, anyhow::Error, _>(|| f(&conn));
+    }
+
+    #[test]
+    #[ignore = "DATABASE_URL の PostgreSQL が必要"]
+    fn delete_ng_after_soft_delete() {
+        with_test_connection(|conn| {
+            let number = PokemonNumber::try_from(898).unwrap();
+            diesel::delete(pokemon.filter(no.eq(898))).execute(conn)?;
+            insert_with(
+                conn,
+                &Pokemon::try_new(898, "TestPokemon".to_string(), vec!["Fire".to_string()])
+                    .unwrap(),
+            )?;
+            execute(conn, &PokemonOperation::SoftDelete(number.clone(), None))?;
+
+            // 論理削除したものは物理削除の対象にせず、存在しないものとして扱う
+            let result = execute(conn, &PokemonOperation::Delete(number, None));
+            assert_eq!(
+                result.unwrap_err().downcast_ref::<PokemonError>(),
+                Some(&PokemonError::NotFound(898))
+            );
+            let remaining = pokemon
+                .filter(no.eq(898))
+                .filter(deleted_at.is_not_null())
+                .count()
+                .get_result::<i64>(conn)?;
+            assert_eq!(remaining, 1);
+            Ok(())
+        });
+    }
+
+    #[test]
+    #[ignore = "DATABASE_URL の PostgreSQL が必要"]
+    fn search_by_types() {
+        with_test_connection(|conn| {
+            diesel::delete(pokemon).execute(conn)?;
+            for (number, types) in [
+                (1, vec!["Fire"]),
+                (2, vec!["Water", "Flying"]),
+                (3, vec!["Grass"]),
+            ] {
+                let types = types.into_iter().map(String::from).collect();
+                insert_with(
+                    conn,
+                    &Pokemon::try_new(number, "TestPokemon".to_string(), types).unwrap(),
+                )?;
+            }
+            execute(
+                conn,
+                &PokemonOperation::SoftDelete(PokemonNumber::try_from(1).unwrap(), None),
+            )?;
+
+            let query = PokemonQuery {
+                any_types: vec![PokemonType::Fire, PokemonType::Flying],
+                ..Default::default()
+            };
+            let numbers = search_with(conn, &query)?
+                .into_iter()
+                .map(|p| p.number.into())
+                .collect::<Vec<i32>>();
+            // 論理削除したものは含めない
+            assert_eq!(numbers, vec![2]);
+            assert_eq!(search_with(conn, &PokemonQuery::default())?.len(), 2);
+            Ok(())
+        });
+    }
+
+    #[test]
+    #[ignore = "DATABASE_URL の PostgreSQL が必要"]
+    fn search_page_by_query() {
+        with_test_connection(|conn| {
+            diesel::delete(pokemon).execute(conn)?;
+            for (number, pokemon_name, types) in [
+                (1, "Charmander", vec!["Fire"]),
+                (2, "Squirtle", vec!["Water"]),
+                (3, "Bulbasaur", vec!["Grass"]),
+                (4, "Charizard", vec!["Fire", "Flying"]),
+                (5, "Char_Test", vec!["Grass", "Water"]),
+            ] {
+                let types = types.into_iter().map(String::from).collect();
+                insert_with(
+                    conn,
+                    &Pokemon::try_new(number, pokemon_name.to_string(), types).unwrap(),
+                )?;
+            }
+            let numbers = |query: &PokemonQuery, offset, limit| -> Result<(Vec<i32>, i64)> {
+                let (result, total) = search_page_with(conn, query, offset, limit)?;
+                Ok((result.into_iter().map(|p| p.number.into()).collect(), total))
+            };
+
+            // 件数は offset と limit に関わらず条件に一致した全件を返す
+            assert_eq!(numbers(&PokemonQuery::default(), 1, 2)?, (vec![2, 3], 5));
+            // 名前は大文字・小文字を区別せず、% と _ は文字として扱う
+            let query = PokemonQuery {
+                name_contains: Some("CHAR".to_string()),
+                ..Default::default()
+            };
+            assert_eq!(numbers(&query, 0, 10)?, (vec![1, 4, 5], 3));
+            let query = PokemonQuery {
+                name_contains: Some("r_".to_string()),
+                ..Default::default()
+            };
+            assert_eq!(numbers(&query, 0, 10)?, (vec![5], 1));
+            // Grass/Water は Fire が等倍のため弱点ではない
+            let query = PokemonQuery {
+                weak_to: Some(PokemonType::Fire),
+                ..Default::default()
+            };
+            assert_eq!(numbers(&query, 0, 10)?, (vec![3], 1));
+            let query = PokemonQuery {
+                any_types: vec![PokemonType::Fire, PokemonType::Water],
+                number_from: Some(2),
+                number_to: Some(4),
+                ..Default::default()
+            };
+            assert_eq!(numbers(&query, 0, 10)?, (vec![2, 4], 2));
+            Ok(())
+        });
     }
 }
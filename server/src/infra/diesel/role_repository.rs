@@ -0,0 +1,28 @@
+//! Diesel を用いて利用者の役割のデータをやり取りするためのリポジトリ。
+
+use super::schema::user_roles;
+use crate::domain::models::role::{role::Role, role_repository::RoleRepository};
+use anyhow::{Context, Result};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool};
+use std::convert::TryFrom;
+
+pub struct RoleRepositoryImpl {
+    pub pool: Box<Pool<ConnectionManager<PgConnection>>>,
+}
+
+impl RoleRepository for RoleRepositoryImpl {
+    /// 利用者に割り当てられた役割を返却する。未知の役割は無視する。
+    fn find_by_subject(&self, target_subject: &str) -> Result<Vec<Role>> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        let roles = user_roles::table
+            .filter(user_roles::subject.eq(target_subject))
+            .select(user_roles::role)
+            .load::<String>(&conn)?;
+        Ok(roles
+            .into_iter()
+            .filter_map(|r| Role::try_from(r).ok())
+            .collect())
+    }
+}
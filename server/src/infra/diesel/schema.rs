@@ -1,8 +1,97 @@
+table! {
+    api_keys (id) {
+        id -> Int4,
+        name -> Text,
+        key_hash -> Text,
+        scopes -> Array<Text>,
+        expires_at -> Nullable<Timestamptz>,
+        last_used_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+        revoked_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    outbox (id) {
+        id -> Int8,
+        event_type -> Text,
+        aggregate_id -> Int4,
+        payload -> Text,
+        created_at -> Timestamptz,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        sent_at -> Nullable<Timestamptz>,
+        webhooks_queued_at -> Nullable<Timestamptz>,
+        locked_until -> Nullable<Timestamptz>,
+    }
+}
+
 table! {
     pokemon (no) {
         no -> Int4,
         name -> Text,
         #[sql_name = "type"]
         type_ -> Array<Text>,
+        version -> Int4,
+        updated_at -> Timestamptz,
+        deleted_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    user_roles (subject, role) {
+        subject -> Text,
+        role -> Text,
+    }
+}
+
+table! {
+    webhook_deliveries (id) {
+        id -> Int4,
+        webhook_id -> Int4,
+        delivery_id -> Text,
+        event -> Text,
+        attempt -> Int4,
+        status_code -> Nullable<Int4>,
+        error -> Nullable<Text>,
+        succeeded -> Bool,
+        attempted_at -> Timestamptz,
+    }
+}
+
+table! {
+    webhook_queue (id) {
+        id -> Int8,
+        webhook_id -> Int4,
+        delivery_id -> Text,
+        event -> Text,
+        body -> Text,
+        attempt -> Int4,
+        next_attempt_at -> Timestamptz,
+        locked_until -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    webhooks (id) {
+        id -> Int4,
+        url -> Text,
+        secret -> Text,
+        events -> Array<Text>,
+        created_at -> Timestamptz,
     }
 }
+
+joinable!(webhook_deliveries -> webhooks (webhook_id));
+joinable!(webhook_queue -> webhooks (webhook_id));
+
+allow_tables_to_appear_in_same_query!(
+    api_keys,
+    outbox,
+    pokemon,
+    user_roles,
+    webhook_deliveries,
+    webhook_queue,
+    webhooks,
+);
@@ -0,0 +1,325 @@
+//! Diesel を用いて Webhook のデータをやり取りするためのリポジトリ。
+
+use super::outbox_repository::OutboxEntity;
+use super::schema::{outbox, webhook_deliveries, webhook_queue, webhooks};
+use crate::domain::models::outbox::outbox::OutboxMessage;
+use crate::domain::models::webhook::{
+    webhook::{
+        NewWebhook, NewWebhookDelivery, NewWebhookJob, Webhook, WebhookDelivery, WebhookEvent,
+        WebhookJob,
+    },
+    webhook_repository::WebhookRepository,
+};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool};
+use std::convert::TryFrom;
+
+/// Diesel が直接利用するデータモデル。
+#[derive(Debug, Queryable, Clone)]
+pub struct WebhookEntity {
+    pub id: i32,
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "webhooks"]
+pub struct NewWebhookEntity {
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<String>,
+}
+
+#[derive(Debug, Queryable, Clone)]
+pub struct WebhookDeliveryEntity {
+    pub id: i32,
+    pub webhook_id: i32,
+    pub delivery_id: String,
+    pub event: String,
+    pub attempt: i32,
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub succeeded: bool,
+    pub attempted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "webhook_deliveries"]
+pub struct NewWebhookDeliveryEntity {
+    pub webhook_id: i32,
+    pub delivery_id: String,
+    pub event: String,
+    pub attempt: i32,
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub succeeded: bool,
+}
+
+/// 送信待ちの通知と、送信先の Webhook の URL・シークレットを結合したもの
+#[derive(Debug, Queryable, Clone)]
+pub struct WebhookJobEntity {
+    pub id: i64,
+    pub webhook_id: i32,
+    pub url: String,
+    pub secret: String,
+    pub delivery_id: String,
+    pub event: String,
+    pub body: String,
+    pub attempt: i32,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "webhook_queue"]
+pub struct NewWebhookJobEntity {
+    pub webhook_id: i32,
+    pub delivery_id: String,
+    pub event: String,
+    pub body: String,
+}
+
+/// Webhook の振る舞い： WebhookEntity から Webhook への変換処理。
+/// 未知のイベントの種類は無視する。
+impl From<WebhookEntity> for Webhook {
+    fn from(entity: WebhookEntity) -> Webhook {
+        Webhook {
+            id: entity.id,
+            url: entity.url,
+            secret: entity.secret,
+            events: entity
+                .events
+                .into_iter()
+                .filter_map(|e| WebhookEvent::try_from(e).ok())
+                .collect(),
+            created_at: entity.created_at,
+        }
+    }
+}
+
+/// 送信の記録の振る舞い： WebhookDeliveryEntity から WebhookDelivery への変換処理。
+impl TryFrom<WebhookDeliveryEntity> for WebhookDelivery {
+    type Error = anyhow::Error;
+
+    fn try_from(entity: WebhookDeliveryEntity) -> Result<WebhookDelivery> {
+        let event = WebhookEvent::try_from(entity.event.clone())
+            .map_err(|_| anyhow::anyhow!("unknown webhook event: {}", entity.event))?;
+        Ok(WebhookDelivery {
+            id: entity.id,
+            webhook_id: entity.webhook_id,
+            delivery_id: entity.delivery_id,
+            event,
+            attempt: entity.attempt,
+            status_code: entity.status_code,
+            error: entity.error,
+            succeeded: entity.succeeded,
+            attempted_at: entity.attempted_at,
+        })
+    }
+}
+
+/// 送信待ちの通知の振る舞い： WebhookJobEntity から WebhookJob への変換処理。
+impl TryFrom<WebhookJobEntity> for WebhookJob {
+    type Error = anyhow::Error;
+
+    fn try_from(entity: WebhookJobEntity) -> Result<WebhookJob> {
+        let event = WebhookEvent::try_from(entity.event.clone())
+            .map_err(|_| anyhow::anyhow!("unknown webhook event: {}", entity.event))?;
+        Ok(WebhookJob {
+            id: entity.id,
+            webhook_id: entity.webhook_id,
+            url: entity.url,
+            secret: entity.secret,
+            delivery_id: entity.delivery_id,
+            event,
+            body: entity.body,
+            attempt: entity.attempt,
+        })
+    }
+}
+
+pub struct WebhookRepositoryImpl {
+    pub pool: Box<Pool<ConnectionManager<PgConnection>>>,
+}
+
+impl WebhookRepository for WebhookRepositoryImpl {
+    /// ID に一致する Webhook を返却する
+    fn find_by_id(&self, target_id: i32) -> Result<Option<Webhook>> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        let result = webhooks::table
+            .find(target_id)
+            .first::<WebhookEntity>(&conn)
+            .optional()?;
+        Ok(result.map(Webhook::from))
+    }
+
+    /// 全ての Webhook を ID 順に返却する
+    fn list(&self) -> Result<Vec<Webhook>> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        let results = webhooks::table
+            .order(webhooks::id.asc())
+            .load::<WebhookEntity>(&conn)?;
+        Ok(results.into_iter().map(Webhook::from).collect())
+    }
+
+    /// Webhook を挿入し、採番された Webhook を返却する
+    fn insert(&self, data: &NewWebhook) -> Result<Webhook> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        let new_webhook = NewWebhookEntity {
+            url: data.url.clone(),
+            secret: data.secret.clone(),
+            events: data.events.iter().map(|e| String::from(*e)).collect(),
+        };
+        let entity = diesel::insert_into(webhooks::table)
+            .values(&new_webhook)
+            .get_result::<WebhookEntity>(&conn)?;
+        Ok(Webhook::from(entity))
+    }
+
+    /// Webhook を削除する。送信の記録は外部キーの ON DELETE CASCADE で削除される。
+    fn delete(&self, target_id: i32) -> Result<bool> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        let count = diesel::delete(webhooks::table.find(target_id)).execute(&conn)?;
+        Ok(count > 0)
+    }
+
+    /// 送信の記録を挿入する
+    fn insert_delivery(&self, data: &NewWebhookDelivery) -> Result<WebhookDelivery> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        let new_delivery = NewWebhookDeliveryEntity {
+            webhook_id: data.webhook_id,
+            delivery_id: data.delivery_id.clone(),
+            event: String::from(data.event),
+            attempt: data.attempt,
+            status_code: data.status_code,
+            error: data.error.clone(),
+            succeeded: data.succeeded,
+        };
+        let entity = diesel::insert_into(webhook_deliveries::table)
+            .values(&new_delivery)
+            .get_result::<WebhookDeliveryEntity>(&conn)?;
+        WebhookDelivery::try_from(entity)
+    }
+
+    /// 送信の記録を新しい順に返却する
+    fn list_deliveries(&self, target_id: i32, limit: i64) -> Result<Vec<WebhookDelivery>> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        let results = webhook_deliveries::table
+            .filter(webhook_deliveries::webhook_id.eq(target_id))
+            .order(webhook_deliveries::id.desc())
+            .limit(limit)
+            .load::<WebhookDeliveryEntity>(&conn)?;
+        results.into_iter().map(WebhookDelivery::try_from).collect()
+    }
+
+    /// 通知を作っていないイベントを行ロックして取り出し、通知の挿入と通知済みの記録を同じトランザクションで行う。
+    /// 他のインスタンスが取り出し中のイベントは飛ばす。
+    fn enqueue(
+        &self,
+        limit: i64,
+        jobs: &dyn Fn(&OutboxMessage) -> Result<Vec<NewWebhookJob>>,
+    ) -> Result<usize> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        conn.transaction::<_, anyhow::Error, _>(|| {
+            let messages = outbox::table
+                .filter(outbox::webhooks_queued_at.is_null())
+                .order(outbox::id.asc())
+                .limit(limit)
+                .for_update()
+                .skip_locked()
+                .load::<OutboxEntity>(&conn)?;
+            if messages.is_empty() {
+                return Ok(0);
+            }
+            let ids = messages.iter().map(|m| m.id).collect::<Vec<_>>();
+            let mut new_jobs = vec![];
+            for message in messages {
+                for job in jobs(&OutboxMessage::from(message))? {
+                    new_jobs.push(NewWebhookJobEntity {
+                        webhook_id: job.webhook_id,
+                        delivery_id: job.delivery_id,
+                        event: String::from(job.event),
+                        body: job.body,
+                    });
+                }
+            }
+            diesel::insert_into(webhook_queue::table)
+                .values(&new_jobs)
+                .execute(&conn)
+                .context("Error saving webhook jobs")?;
+            diesel::update(outbox::table.filter(outbox::id.eq_any(&ids)))
+                .set(outbox::webhooks_queued_at.eq(Some(Utc::now())))
+                .execute(&conn)?;
+            Ok(ids.len())
+        })
+    }
+
+    /// 送信の時刻になり、他から取り出されていない通知を行ロックして取り出し、期限まで取り出し中にする
+    fn claim(&self, limit: i64, locked_until: DateTime<Utc>) -> Result<Vec<WebhookJob>> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        conn.transaction::<_, anyhow::Error, _>(|| {
+            let now = Utc::now();
+            let ids = webhook_queue::table
+                .select(webhook_queue::id)
+                .filter(webhook_queue::next_attempt_at.le(now))
+                .filter(
+                    webhook_queue::locked_until
+                        .is_null()
+                        .or(webhook_queue::locked_until.lt(now)),
+                )
+                .order(webhook_queue::next_attempt_at.asc())
+                .limit(limit)
+                .for_update()
+                .skip_locked()
+                .load::<i64>(&conn)?;
+            if ids.is_empty() {
+                return Ok(vec![]);
+            }
+            diesel::update(webhook_queue::table.filter(webhook_queue::id.eq_any(&ids)))
+                .set(webhook_queue::locked_until.eq(Some(locked_until)))
+                .execute(&conn)?;
+            let results = webhook_queue::table
+                .inner_join(webhooks::table)
+                .filter(webhook_queue::id.eq_any(&ids))
+                .order(webhook_queue::next_attempt_at.asc())
+                .select((
+                    webhook_queue::id,
+                    webhook_queue::webhook_id,
+                    webhooks::url,
+                    webhooks::secret,
+                    webhook_queue::delivery_id,
+                    webhook_queue::event,
+                    webhook_queue::body,
+                    webhook_queue::attempt,
+                ))
+                .load::<WebhookJobEntity>(&conn)?;
+            results.into_iter().map(WebhookJob::try_from).collect()
+        })
+    }
+
+    /// 通知を削除する
+    fn complete(&self, target_id: i64) -> Result<()> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        diesel::delete(webhook_queue::table.find(target_id))
+            .execute(&conn)
+            .with_context(|| format!("Error completing webhook job {}", target_id))?;
+        Ok(())
+    }
+
+    /// 送信した回数を進めて再送の時刻を記録し、取り出し中を解除する
+    fn retry(&self, target_id: i64, next_attempt_at: DateTime<Utc>) -> Result<()> {
+        let conn = self.pool.get().context("failed to get connection")?;
+        diesel::update(webhook_queue::table.find(target_id))
+            .set((
+                webhook_queue::attempt.eq(webhook_queue::attempt + 1),
+                webhook_queue::next_attempt_at.eq(next_attempt_at),
+                webhook_queue::locked_until.eq(None::<DateTime<Utc>>),
+            ))
+            .execute(&conn)
+            .with_context(|| format!("Error scheduling webhook job {}", target_id))?;
+        Ok(())
+    }
+}
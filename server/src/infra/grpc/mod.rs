@@ -0,0 +1,35 @@
+//! tonic による gRPC サーバー。
+//! REST API とは別のポートで待ち受け、同じアプリケーションサービスを公開する。
+
+pub mod pokemon_service;
+
+/// `proto/pokedex/v1/pokedex.proto` から生成したメッセージとサービスの定義
+pub mod pb {
+    tonic::include_proto!("pokedex.v1");
+}
+
+use pb::pokemon_service_server::PokemonServiceServer;
+use pokemon_service::PokemonGrpcService;
+use std::net::SocketAddr;
+use tokio::sync::watch;
+use tonic::transport::{server::TcpIncoming, Server};
+
+/// 待ち受けるポートを開く。起動時にポートの誤りを検出できるよう、サーバーの開始とは分けている。
+pub fn bind(addr: SocketAddr) -> std::io::Result<TcpIncoming> {
+    TcpIncoming::new(addr, true, None)
+        .map_err(|e| std::io::Error::other(format!("failed to bind gRPC server {}: {}", addr, e)))
+}
+
+/// gRPC サーバーを開始する。`shutdown` が変更されると新規の呼び出しの受付を止め、処理中の呼び出しの完了を待つ。
+pub async fn serve(
+    incoming: TcpIncoming,
+    service: PokemonGrpcService,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<(), tonic::transport::Error> {
+    Server::builder()
+        .add_service(PokemonServiceServer::new(service))
+        .serve_with_incoming_shutdown(incoming, async move {
+            let _ = shutdown.changed().await;
+        })
+        .await
+}
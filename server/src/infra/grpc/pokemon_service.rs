@@ -0,0 +1,371 @@
+//! gRPC の PokemonService の実装。
+//! REST のハンドラーと同じアプリケーションサービスを呼び出し、結果を gRPC のメッセージとステータスに変換する。
+
+use super::pb::{self, pokemon_event::Type as EventType, pokemon_service_server::PokemonService};
+use crate::application::pokemon_data::PokemonData;
+use crate::application::pokemon_delete_service::PokemonDeleteService;
+use crate::application::pokemon_get_service::PokemonGetService;
+use crate::application::pokemon_list_service::PokemonListService;
+use crate::application::pokemon_register_service::PokemonRegisterService;
+use crate::application::pokemon_update_service::{PokemonUpdateCommand, PokemonUpdateService};
+use crate::application::pokemon_watch_service::{
+    PokemonChange, PokemonSnapshot, PokemonWatchService,
+};
+use crate::config::CONFIG;
+use crate::domain::models::pokemon::{pokemon::Pokemon, pokemon_error::PokemonError};
+use crate::domain::models::role::permission::Permission;
+use crate::infra::actix::auth::{self, AuthenticatedUser, Authenticator};
+use crate::infra::actix::problem::Problem;
+use crate::infra::actix::router::RequestContext;
+use actix_web::{http::StatusCode, web, ResponseError};
+use futures_util::Stream;
+use std::pin::Pin;
+use tokio::sync::{broadcast::error::RecvError, mpsc, watch};
+use tonic::{Request, Response, Status};
+
+/// Watch の送信待ちとして保持する最大イベント数
+const WATCH_BUFFER: usize = 64;
+
+pub struct PokemonGrpcService {
+    context: RequestContext,
+    authenticator: web::Data<Authenticator>,
+    /// サーバーの停止を Watch のストリームに伝える
+    shutdown: watch::Receiver<bool>,
+}
+
+impl PokemonGrpcService {
+    /// コンストラクタ
+    pub fn new(
+        context: RequestContext,
+        authenticator: web::Data<Authenticator>,
+        shutdown: watch::Receiver<bool>,
+    ) -> Self {
+        Self {
+            context,
+            authenticator,
+            shutdown,
+        }
+    }
+
+    /// メタデータの authorization を検証し、指定した操作の権限を持つユーザーを返す
+    async fn authorize<T>(
+        &self,
+        request: &Request<T>,
+        required: Permission,
+    ) -> Result<AuthenticatedUser, Status> {
+        let authorization = request
+            .metadata()
+            .get("authorization")
+            .and_then(|v| v.to_str().ok());
+        let user = auth::authenticate(
+            Some(self.authenticator.get_ref()),
+            Some(&self.context),
+            authorization,
+        )
+        .await
+        .map_err(problem_status)?;
+        user.require(required).map_err(problem_status)?;
+        Ok(user)
+    }
+
+    /// 参照が公開されていなければ pokemon:read の権限を要求する
+    async fn authorize_read<T>(&self, request: &Request<T>) -> Result<(), Status> {
+        if self.authenticator.public_read() {
+            Ok(())
+        } else {
+            self.authorize(request, Permission::PokemonRead)
+                .await
+                .map(|_| ())
+        }
+    }
+}
+
+/// 認証・認可の Problem を gRPC のステータスに変換する
+fn problem_status(problem: Problem) -> Status {
+    match problem.status_code() {
+        StatusCode::UNAUTHORIZED => Status::unauthenticated(problem.to_string()),
+        StatusCode::FORBIDDEN => Status::permission_denied(problem.to_string()),
+        _ => Status::internal(problem.to_string()),
+    }
+}
+
+/// アプリケーションサービスのエラーを gRPC のステータスに変換する
+fn error_status(e: anyhow::Error) -> Status {
+    match e.downcast_ref::<PokemonError>() {
+        Some(PokemonError::InvalidValue(_)) => Status::invalid_argument(e.to_string()),
+        Some(PokemonError::NotFound(_)) => Status::not_found(e.to_string()),
+        Some(PokemonError::AlreadyExists(_)) => Status::already_exists(e.to_string()),
+        Some(PokemonError::VersionMismatch { .. }) => Status::aborted(e.to_string()),
+        Some(PokemonError::Deleted(_)) => Status::failed_precondition(e.to_string()),
+        None => {
+            log::error!("gRPC call failed: {:?}", e);
+            Status::internal("Internal Server Error")
+        }
+    }
+}
+
+/// Diesel の呼び出しはブロッキングするため、専用のスレッドで実行する
+async fn blocking<F, R>(f: F) -> Result<R, Status>
+where
+    F: FnOnce() -> anyhow::Result<R> + Send + 'static,
+    R: Send + 'static,
+{
+    actix_web::rt::task::spawn_blocking(f)
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(error_status)
+}
+
+impl From<PokemonData> for pb::Pokemon {
+    fn from(pokemon: PokemonData) -> Self {
+        Self {
+            number: *pokemon.get_number(),
+            name: pokemon.get_name().clone(),
+            types: pokemon.get_types().clone(),
+            version: *pokemon.get_version(),
+            updated_at: pokemon
+                .get_updated_at()
+                .map(|updated_at| prost_types::Timestamp {
+                    seconds: updated_at.timestamp(),
+                    nanos: updated_at.timestamp_subsec_nanos() as i32,
+                }),
+        }
+    }
+}
+
+impl From<PokemonChange> for pb::PokemonEvent {
+    fn from(change: PokemonChange) -> Self {
+        let (r#type, number, pokemon) = match change {
+            PokemonChange::Created(pokemon) => {
+                (EventType::Created, *pokemon.get_number(), Some(pokemon))
+            }
+            PokemonChange::Updated(pokemon) => {
+                (EventType::Updated, *pokemon.get_number(), Some(pokemon))
+            }
+            PokemonChange::Deleted(number) => (EventType::Deleted, number, None),
+        };
+        Self {
+            r#type: r#type as i32,
+            number,
+            pokemon: pokemon.map(pb::Pokemon::from),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl PokemonService for PokemonGrpcService {
+    async fn get(
+        &self,
+        request: Request<pb::GetPokemonRequest>,
+    ) -> Result<Response<pb::Pokemon>, Status> {
+        self.authorize_read(&request).await?;
+        let number = request.into_inner().number;
+        let repository = self.context.pokemon_repository();
+        // 取得サービスは存在しない場合とそれ以外のエラーを区別しないため、全て NOT_FOUND とする
+        let pokemon = actix_web::rt::task::spawn_blocking(move || {
+            PokemonGetService::new(repository).handle(number)
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(|e| Status::not_found(e.to_string()))?;
+        Ok(Response::new(pokemon.into()))
+    }
+
+    async fn list(
+        &self,
+        request: Request<pb::ListPokemonRequest>,
+    ) -> Result<Response<pb::ListPokemonResponse>, Status> {
+        self.authorize_read(&request).await?;
+        let repository = self.context.pokemon_repository();
+        let pokemon = blocking(move || PokemonListService::new(repository).handle()).await?;
+        Ok(Response::new(pb::ListPokemonResponse {
+            pokemon: pokemon.into_iter().map(pb::Pokemon::from).collect(),
+        }))
+    }
+
+    async fn register(
+        &self,
+        request: Request<pb::RegisterPokemonRequest>,
+    ) -> Result<Response<pb::Pokemon>, Status> {
+        let user = self.authorize(&request, Permission::PokemonWrite).await?;
+        let request = request.into_inner();
+        log::info!(
+            "Register Pokemon requested by {} via gRPC: no {}",
+            user.subject,
+            request.number
+        );
+        let pokemon = Pokemon::try_new(request.number, request.name, request.types)
+            .map_err(|e| error_status(e.into()))?;
+        let repository = self.context.pokemon_repository();
+        let event_bus = self.context.event_bus();
+        let pokemon = blocking(move || {
+            PokemonRegisterService::new(repository)
+                .with_event_bus(event_bus)
+                .handle(PokemonData::new(pokemon))
+        })
+        .await?;
+        Ok(Response::new(pokemon.into()))
+    }
+
+    async fn update(
+        &self,
+        request: Request<pb::UpdatePokemonRequest>,
+    ) -> Result<Response<pb::Pokemon>, Status> {
+        let user = self.authorize(&request, Permission::PokemonWrite).await?;
+        let request = request.into_inner();
+        log::info!(
+            "Update Pokemon requested by {} via gRPC: no {}",
+            user.subject,
+            request.number
+        );
+        let mut command = PokemonUpdateCommand::new(request.number);
+        command.set_name(request.name);
+        command.set_types(Some(request.types).filter(|types| !types.is_empty()));
+        command.set_expected_version(request.expected_version);
+        let context = self.context.clone();
+        let pokemon = blocking(move || {
+            // 更新サービスは存在しない場合を区別しないため、先に存在を確認する
+            PokemonGetService::new(context.pokemon_repository())
+                .handle(*command.get_number())
+                .map_err(|_| PokemonError::NotFound(*command.get_number()))?;
+            PokemonUpdateService::new(context.pokemon_repository())
+                .with_event_bus(context.event_bus())
+                .handle(command)
+        })
+        .await?;
+        Ok(Response::new(PokemonData::new(pokemon).into()))
+    }
+
+    async fn delete(
+        &self,
+        request: Request<pb::DeletePokemonRequest>,
+    ) -> Result<Response<()>, Status> {
+        let user = self.authorize(&request, Permission::PokemonDelete).await?;
+        let request = request.into_inner();
+        log::info!(
+            "Delete Pokemon requested by {} via gRPC: no {}",
+            user.subject,
+            request.number
+        );
+        let repository = self.context.pokemon_repository();
+        let event_bus = self.context.event_bus();
+        let result = blocking(move || {
+            PokemonDeleteService::new(repository)
+                .with_soft_delete(CONFIG.soft_delete)
+                .with_event_bus(event_bus)
+                .handle(request.number, request.expected_version)
+        })
+        .await;
+        match result {
+            Ok(_) => Ok(Response::new(())),
+            // 冪等な削除として扱う設定の場合は、存在しなくても削除済みとして成功にする
+            Err(status) if status.code() == tonic::Code::NotFound && CONFIG.delete_idempotent => {
+                Ok(Response::new(()))
+            }
+            Err(status) => Err(status),
+        }
+    }
+
+    type WatchStream = Pin<Box<dyn Stream<Item = Result<pb::PokemonEvent, Status>> + Send>>;
+
+    /// イベントログを購読し、呼び出し以降の変更を送る。
+    /// 送信が追いつかずに取りこぼした場合は、送った変更を反映した一覧とデータベースの一覧を比較し、差分を送る。
+    async fn watch(
+        &self,
+        request: Request<pb::WatchPokemonRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        self.authorize_read(&request).await?;
+        let (tx, mut rx) = mpsc::channel::<Result<pb::PokemonEvent, Status>>(WATCH_BUFFER);
+        let context = self.context.clone();
+        let mut receiver = context.change_feed().subscribe(None).receiver;
+        let mut shutdown = self.shutdown.clone();
+        tokio::spawn(async move {
+            // 取りこぼした場合の比較の基準として、購読を開始した時点の一覧を記録する
+            let mut snapshot = PokemonSnapshot::default();
+            if let Err(e) = resync(&context, &mut snapshot).await {
+                log::error!("Watch Pokemon failed: {:?}", e);
+                let _ = tx.send(Err(Status::internal(e.to_string()))).await;
+                return;
+            }
+            loop {
+                let changes = tokio::select! {
+                    event = receiver.recv() => match event {
+                        Ok(event) if snapshot.apply(&event.change) => vec![event.change],
+                        Ok(_) => vec![],
+                        Err(RecvError::Lagged(_)) => match resync(&context, &mut snapshot).await {
+                            Ok(changes) => changes,
+                            Err(e) => {
+                                log::error!("Watch Pokemon failed: {:?}", e);
+                                let _ = tx.send(Err(Status::internal(e.to_string()))).await;
+                                return;
+                            }
+                        },
+                        Err(RecvError::Closed) => return,
+                    },
+                    _ = tx.closed() => return,
+                    _ = shutdown.changed() => {
+                        let _ = tx.send(Err(Status::unavailable("server is shutting down"))).await;
+                        return;
+                    }
+                };
+                for change in changes {
+                    if tx.send(Ok(change.into())).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        let stream = futures_util::stream::poll_fn(move |cx| rx.poll_recv(cx));
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+/// データベースの一覧を `snapshot` と比較して差分を返し、`snapshot` を現在の一覧に置き換える
+async fn resync(
+    context: &RequestContext,
+    snapshot: &mut PokemonSnapshot,
+) -> anyhow::Result<Vec<PokemonChange>> {
+    let repository = context.pokemon_repository();
+    let mut current = std::mem::take(snapshot);
+    let (current, changes) = actix_web::rt::task::spawn_blocking(move || {
+        let changes = PokemonWatchService::new(repository).handle(&mut current);
+        (current, changes)
+    })
+    .await?;
+    *snapshot = current;
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_from_change() {
+        let pokemon = PokemonData::new(
+            Pokemon::try_new(1, "TestPokemon".to_string(), vec!["Fire".to_string()]).unwrap(),
+        );
+        let event = pb::PokemonEvent::from(PokemonChange::Updated(pokemon));
+        assert_eq!(event.r#type(), EventType::Updated);
+        assert_eq!(event.number, 1);
+        assert_eq!(event.pokemon.unwrap().types, vec!["Fire"]);
+
+        let event = pb::PokemonEvent::from(PokemonChange::Deleted(2));
+        assert_eq!(event.r#type(), EventType::Deleted);
+        assert!(event.pokemon.is_none());
+    }
+
+    #[test]
+    fn error_status_code() {
+        let status = error_status(
+            PokemonError::VersionMismatch {
+                number: 1,
+                expected: 2,
+            }
+            .into(),
+        );
+        assert_eq!(status.code(), tonic::Code::Aborted);
+        let status = problem_status(Problem::forbidden("forbidden"));
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+    }
+}
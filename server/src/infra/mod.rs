@@ -1,2 +1,6 @@
 pub mod actix;
+pub mod audit;
 pub mod diesel;
+pub mod grpc;
+pub mod outbox;
+pub mod webhook;
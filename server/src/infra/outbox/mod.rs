@@ -0,0 +1,380 @@
+//! アウトボックスの配信（リレー）。
+//! ポケモンの変更と同じトランザクションで記録されたドメインイベントを定期的に取り出し、
+//! 設定した配信先（ログ・ファイル・HTTP）へ記録順に送って配信済みにする。
+//! 配信してから配信済みにするまでの間に停止した場合は再送するため、配信は少なくとも 1 回（at-least-once）となる。
+//! 取り出したメッセージは期限まで他のインスタンスから取り出されないため、複数のインスタンスで動かしても同時には配信しない。
+
+use crate::application::outbox_relay_service::OutboxRelayService;
+use crate::config::Config;
+use crate::domain::models::outbox::{outbox::OutboxMessage, outbox_repository::OutboxRepository};
+use crate::infra::actix::router::RequestContext;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use serde_json::json;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// HTTP で配信する場合の 1 回の送信のタイムアウト
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 取り出したメッセージを他のインスタンスから取り出せないようにしておく時間の、送信のタイムアウトに対する余裕
+const LEASE_MARGIN: Duration = Duration::from_secs(30);
+
+/// アウトボックスの配信先の振る舞い
+#[async_trait]
+pub trait OutboxSink: Send + Sync {
+    /// メッセージを配信する。エラーを返した場合、メッセージは未配信のまま残り再送される。
+    async fn publish(&self, message: &OutboxMessage) -> Result<()>;
+}
+
+/// 配信先に送る JSON。配信先は `id` で重複を排除できる。
+fn envelope(message: &OutboxMessage) -> String {
+    let data = serde_json::from_str::<serde_json::Value>(&message.payload)
+        .unwrap_or_else(|_| serde_json::Value::String(message.payload.clone()));
+    json!({
+        "id": message.id,
+        "event": message.event_type,
+        "number": message.aggregate_id,
+        "occurred_at": message.created_at,
+        "data": data,
+    })
+    .to_string()
+}
+
+/// ログに出力する配信先
+pub struct LogSink;
+
+#[async_trait]
+impl OutboxSink for LogSink {
+    async fn publish(&self, message: &OutboxMessage) -> Result<()> {
+        log::info!(target: "outbox", "{}", envelope(message));
+        Ok(())
+    }
+}
+
+/// ファイルに 1 行 1 メッセージ（NDJSON）で追記する配信先
+pub struct FileSink {
+    path: PathBuf,
+}
+
+impl FileSink {
+    /// コンストラクタ
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl OutboxSink for FileSink {
+    async fn publish(&self, message: &OutboxMessage) -> Result<()> {
+        let path = self.path.clone();
+        let line = envelope(message);
+        actix_web::rt::task::spawn_blocking(move || {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .with_context(|| format!("failed to open {}", path.display()))?;
+            writeln!(file, "{}", line)?;
+            // 配信済みにする前に書き込みを確定させる
+            file.sync_data()?;
+            Ok(())
+        })
+        .await?
+    }
+}
+
+/// JSON を POST する配信先。2xx 以外の応答はエラーとする。
+pub struct HttpSink {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl HttpSink {
+    /// コンストラクタ
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl OutboxSink for HttpSink {
+    async fn publish(&self, message: &OutboxMessage) -> Result<()> {
+        let response = self
+            .client
+            .post(&self.url)
+            .timeout(HTTP_TIMEOUT)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-Outbox-Id", message.id.to_string())
+            .body(envelope(message))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            anyhow::bail!("unexpected status {}", response.status());
+        }
+        Ok(())
+    }
+}
+
+/// 設定から配信先を作成する。未知の配信先や URL の指定漏れは起動時のエラーとする。
+pub fn sink_from_config(config: &Config) -> Result<Box<dyn OutboxSink>> {
+    match config.outbox_sink.as_str() {
+        "log" => Ok(Box::new(LogSink)),
+        "file" => Ok(Box::new(FileSink::new(&config.outbox_file_path))),
+        "http" if config.outbox_http_url.is_empty() => {
+            anyhow::bail!("OUTBOX_HTTP_URL is required when OUTBOX_SINK is http")
+        }
+        "http" => Ok(Box::new(HttpSink::new(&config.outbox_http_url))),
+        other => anyhow::bail!("unknown OUTBOX_SINK: {}", other),
+    }
+}
+
+/// リポジトリの呼び出しはブロッキングするため、別のスレッドで行う
+async fn blocking<T, R>(
+    repository: T,
+    f: impl FnOnce(OutboxRelayService<T>) -> Result<R> + Send + 'static,
+) -> Result<R>
+where
+    T: OutboxRepository + Send + 'static,
+    R: Send + 'static,
+{
+    actix_web::rt::task::spawn_blocking(move || f(OutboxRelayService::new(repository))).await?
+}
+
+/// 未配信のメッセージを最大 batch_size 件取り出して記録順に配信し、配信した件数を返す。
+/// 記録順を保つため、配信に失敗したメッセージがあればそこで中断し、残りを戻して次の呼び出しで再送する。
+/// 取り出しの期限は、取り出した全てを送信のタイムアウトまで待って送る時間に余裕を加えたものとする。
+pub async fn relay<T>(
+    repository: &impl Fn() -> T,
+    sink: &dyn OutboxSink,
+    batch_size: i64,
+) -> Result<usize>
+where
+    T: OutboxRepository + Send + 'static,
+{
+    let lease = HTTP_TIMEOUT * batch_size.max(0) as u32 + LEASE_MARGIN;
+    let locked_until = Utc::now() + chrono::Duration::from_std(lease)?;
+    let messages = blocking(repository(), move |service| {
+        service.handle(batch_size, locked_until)
+    })
+    .await?;
+    let ids = messages
+        .iter()
+        .map(|message| message.id)
+        .collect::<Vec<_>>();
+    let mut sent = 0;
+    for (index, message) in messages.into_iter().enumerate() {
+        let id = message.id;
+        match sink.publish(&message).await {
+            Ok(()) => {
+                blocking(repository(), move |service| service.complete(id)).await?;
+                sent += 1;
+            }
+            Err(e) => {
+                log::warn!("Outbox delivery failed: id {}: {:?}", id, e);
+                let error = e.to_string();
+                blocking(repository(), move |service| service.fail(id, &error)).await?;
+                let rest = ids[index + 1..].to_vec();
+                blocking(repository(), move |service| service.release(&rest)).await?;
+                break;
+            }
+        }
+    }
+    Ok(sent)
+}
+
+/// 一定の間隔で未配信のメッセージを配信し続ける
+pub async fn run(
+    context: RequestContext,
+    sink: Box<dyn OutboxSink>,
+    interval: Duration,
+    batch_size: i64,
+) {
+    let repository = || context.outbox_repository();
+    loop {
+        match relay(&repository, sink.as_ref(), batch_size).await {
+            // 取り出した全てを配信できた場合は続きがありうるため、待たずに次を取り出す
+            Ok(sent) if sent as i64 == batch_size => continue,
+            Ok(_) => {}
+            Err(e) => log::error!("Outbox relay failed: {:?}", e),
+        }
+        actix_web::rt::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+    use std::sync::{Arc, Mutex};
+
+    /// テストのためのモックリポジトリ。配信済み・失敗・取り出し中の記録を共有する。
+    #[derive(Clone, Default)]
+    struct MockOutboxRepositoryImpl {
+        messages: Arc<Mutex<Vec<(OutboxMessage, bool, bool)>>>,
+    }
+
+    impl MockOutboxRepositoryImpl {
+        fn new(ids: &[i64]) -> Self {
+            let messages = ids
+                .iter()
+                .map(|&id| {
+                    let message = OutboxMessage {
+                        id,
+                        event_type: "pokemon.deleted".to_string(),
+                        aggregate_id: id as i32,
+                        payload: format!(r#"{{"number":{},"soft":false}}"#, id),
+                        created_at: Utc::now(),
+                        attempts: 0,
+                    };
+                    (message, false, false)
+                })
+                .collect();
+            Self {
+                messages: Arc::new(Mutex::new(messages)),
+            }
+        }
+
+        /// 未配信のメッセージを取り出さずに返す
+        fn pending(&self) -> Vec<OutboxMessage> {
+            self.messages
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(_, sent, _)| !sent)
+                .map(|(message, _, _)| message.clone())
+                .collect()
+        }
+    }
+
+    /// モックリポジトリの振る舞い
+    impl OutboxRepository for MockOutboxRepositoryImpl {
+        fn claim(&self, limit: i64, _locked_until: DateTime<Utc>) -> Result<Vec<OutboxMessage>> {
+            Ok(self
+                .messages
+                .lock()
+                .unwrap()
+                .iter_mut()
+                .filter(|(_, sent, _)| !sent)
+                .take(limit as usize)
+                .take_while(|(_, _, locked)| !locked)
+                .map(|(message, _, locked)| {
+                    *locked = true;
+                    message.clone()
+                })
+                .collect())
+        }
+
+        fn mark_sent(&self, id: i64) -> Result<()> {
+            for (message, sent, locked) in self.messages.lock().unwrap().iter_mut() {
+                if message.id == id {
+                    *sent = true;
+                    *locked = false;
+                }
+            }
+            Ok(())
+        }
+
+        fn mark_failed(&self, id: i64, _error: &str) -> Result<()> {
+            for (message, _, locked) in self.messages.lock().unwrap().iter_mut() {
+                if message.id == id {
+                    message.attempts += 1;
+                    *locked = false;
+                }
+            }
+            Ok(())
+        }
+
+        fn release(&self, ids: &[i64]) -> Result<()> {
+            for (message, _, locked) in self.messages.lock().unwrap().iter_mut() {
+                if ids.contains(&message.id) {
+                    *locked = false;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// 受け取った ID を記録し、指定した ID では失敗する配信先
+    struct MockSink {
+        failing: Mutex<Option<i64>>,
+        received: Mutex<Vec<i64>>,
+    }
+
+    #[async_trait]
+    impl OutboxSink for MockSink {
+        async fn publish(&self, message: &OutboxMessage) -> Result<()> {
+            self.received.lock().unwrap().push(message.id);
+            if *self.failing.lock().unwrap() == Some(message.id) {
+                anyhow::bail!("Dummy Error");
+            }
+            Ok(())
+        }
+    }
+
+    #[actix_web::test]
+    async fn relay_stops_at_failure_and_resends() {
+        let repository = MockOutboxRepositoryImpl::new(&[1, 2, 3]);
+        let factory = || repository.clone();
+        let sink = MockSink {
+            failing: Mutex::new(Some(2)),
+            received: Mutex::new(vec![]),
+        };
+
+        // 2 で失敗し、3 は送らない
+        assert_eq!(relay(&factory, &sink, 10).await.unwrap(), 1);
+        assert_eq!(*sink.received.lock().unwrap(), vec![1, 2]);
+        assert_eq!(repository.pending()[0].attempts, 1);
+
+        // 失敗したものから記録順に再送する
+        *sink.failing.lock().unwrap() = None;
+        assert_eq!(relay(&factory, &sink, 10).await.unwrap(), 2);
+        assert_eq!(*sink.received.lock().unwrap(), vec![1, 2, 2, 3]);
+        assert!(repository.pending().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn relay_skips_messages_claimed_elsewhere() {
+        let repository = MockOutboxRepositoryImpl::new(&[1, 2]);
+        let factory = || repository.clone();
+        let sink = MockSink {
+            failing: Mutex::new(None),
+            received: Mutex::new(vec![]),
+        };
+
+        // 他のインスタンスが取り出している間は、後に続くメッセージも送らない
+        repository.claim(1, Utc::now()).unwrap();
+        assert_eq!(relay(&factory, &sink, 10).await.unwrap(), 0);
+        assert!(sink.received.lock().unwrap().is_empty());
+
+        repository.release(&[1]).unwrap();
+        assert_eq!(relay(&factory, &sink, 10).await.unwrap(), 2);
+        assert_eq!(*sink.received.lock().unwrap(), vec![1, 2]);
+    }
+
+    #[actix_web::test]
+    async fn file_sink_appends_lines() {
+        let path = std::env::temp_dir().join(format!("outbox-test-{}.ndjson", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let sink = FileSink::new(&path);
+        let repository = MockOutboxRepositoryImpl::new(&[1, 2]);
+        assert_eq!(relay(&|| repository.clone(), &sink, 10).await.unwrap(), 2);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines = content
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["id"], 2);
+        assert_eq!(lines[1]["event"], "pokemon.deleted");
+        assert_eq!(lines[1]["data"], json!({ "number": 2, "soft": false }));
+    }
+}
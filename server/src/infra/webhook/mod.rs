@@ -0,0 +1,470 @@
+//! Webhook の送信。
+//! アウトボックスに記録されたドメインイベントから、購読している Webhook ごとの通知を作って `webhook_queue` テーブルに入れ、
+//! HMAC で署名した JSON を POST する。失敗した場合は指数バックオフで再送の時刻を記録し、送信のたびに結果を記録する。
+//! 送信待ちの通知はデータベースに保持するため、送信中に停止しても起動後に続きから送る。
+
+use crate::application::pokemon_data::PokemonData;
+use crate::application::pokemon_watch_service::PokemonChange;
+use crate::application::webhook_dispatch_service::WebhookDispatchService;
+use crate::config::Config;
+use crate::domain::models::outbox::outbox::OutboxMessage;
+use crate::domain::models::pokemon::{
+    pokemon_number::PokemonNumber, pokemon_repository::PokemonRepository,
+};
+use crate::domain::models::webhook::{
+    webhook::{sign, NewWebhookDelivery, WebhookJob},
+    webhook_repository::WebhookRepository,
+};
+use crate::infra::actix::router::RequestContext;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::convert::TryFrom;
+use std::time::Duration;
+
+/// 一度に通知を作るイベント・送信する通知の最大数
+const BATCH_SIZE: i64 = 100;
+
+/// 取り出した通知を他のインスタンスから取り出せないようにしておく時間の、送信のタイムアウトに対する余裕
+const LEASE_MARGIN: Duration = Duration::from_secs(30);
+
+/// 再送の方針
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// 初回を含めた最大の送信回数
+    pub max_attempts: u32,
+    /// 1 回目の再送までの待ち時間。以降は再送のたびに 2 倍にする。
+    pub base_delay: Duration,
+    /// 1 回の送信のタイムアウト
+    pub timeout: Duration,
+}
+
+impl RetryPolicy {
+    /// 設定から作成する
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max_attempts: config.webhook_max_attempts.max(1),
+            base_delay: Duration::from_secs(config.webhook_retry_base_delay),
+            timeout: Duration::from_secs(config.webhook_timeout),
+        }
+    }
+
+    /// attempt 回目の送信が失敗した後、次の送信までの待ち時間
+    fn delay(&self, attempt: u32) -> Duration {
+        self.base_delay * 2u32.saturating_pow(attempt - 1)
+    }
+
+    /// attempt 回目の送信が失敗した後の再送の時刻。最大の送信回数に達した場合は None
+    fn retry_at(&self, attempt: u32) -> Option<DateTime<Utc>> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        let delay = chrono::Duration::from_std(self.delay(attempt)).ok()?;
+        Some(Utc::now() + delay)
+    }
+}
+
+/// 通知を 1 回送信し、その結果を返す。
+/// 2xx 以外のステータスと、接続やタイムアウトのエラーを失敗として扱う。
+pub async fn send(
+    client: &reqwest::Client,
+    job: &WebhookJob,
+    timeout: Duration,
+) -> NewWebhookDelivery {
+    let timestamp = Utc::now().timestamp();
+    let result = client
+        .post(&job.url)
+        .timeout(timeout)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("X-Pokedex-Event", String::from(job.event))
+        .header("X-Pokedex-Delivery", &job.delivery_id)
+        .header("X-Pokedex-Timestamp", timestamp.to_string())
+        .header(
+            "X-Pokedex-Signature",
+            format!("sha256={}", sign(&job.secret, timestamp, &job.body)),
+        )
+        .body(job.body.clone())
+        .send()
+        .await;
+    let (status_code, error, succeeded) = match result {
+        Ok(response) if response.status().is_success() => {
+            (Some(response.status().as_u16() as i32), None, true)
+        }
+        Ok(response) => (
+            Some(response.status().as_u16() as i32),
+            Some(format!("unexpected status {}", response.status())),
+            false,
+        ),
+        Err(e) => (None, Some(e.to_string()), false),
+    };
+    NewWebhookDelivery {
+        webhook_id: job.webhook_id,
+        delivery_id: job.delivery_id.clone(),
+        event: job.event,
+        attempt: job.attempt + 1,
+        status_code,
+        error,
+        succeeded,
+    }
+}
+
+/// リポジトリの呼び出しはブロッキングするため、別のスレッドで行う
+async fn blocking<T, R>(
+    repository: T,
+    f: impl FnOnce(WebhookDispatchService<T>) -> Result<R> + Send + 'static,
+) -> Result<R>
+where
+    T: WebhookRepository + Send + 'static,
+    R: Send + 'static,
+{
+    actix_web::rt::task::spawn_blocking(move || f(WebhookDispatchService::new(repository))).await?
+}
+
+/// アウトボックスのイベントを通知する変更に読み替える。
+/// イベントは変更された項目しか持たないため、登録・更新は現在の状態を読み直す。
+/// 後から削除されて存在しない場合は、削除の通知に任せて通知しない。
+fn change_of(
+    pokemon_repository: &impl PokemonRepository,
+    message: &OutboxMessage,
+) -> Option<PokemonChange> {
+    let created = match message.event_type.as_str() {
+        "pokemon.registered" => true,
+        "pokemon.renamed" | "pokemon.retyped" => false,
+        "pokemon.deleted" => return Some(PokemonChange::Deleted(message.aggregate_id)),
+        _ => return None,
+    };
+    let number = PokemonNumber::try_from(message.aggregate_id).ok()?;
+    let pokemon = PokemonData::new(pokemon_repository.find_by_number(&number).ok()?);
+    if created {
+        Some(PokemonChange::Created(pokemon))
+    } else {
+        Some(PokemonChange::Updated(pokemon))
+    }
+}
+
+/// 送信の時刻になった通知を最大 batch_size 件取り出して並行して送り、取り出した件数を返す。
+/// 同じ Webhook への通知でも届く順序は保証しない。
+pub async fn dispatch<T>(
+    repository: &impl Fn() -> T,
+    client: &reqwest::Client,
+    policy: &RetryPolicy,
+    batch_size: i64,
+) -> Result<usize>
+where
+    T: WebhookRepository + Send + 'static,
+{
+    let locked_until = Utc::now() + chrono::Duration::from_std(policy.timeout + LEASE_MARGIN)?;
+    let jobs = blocking(repository(), move |service| {
+        service.claim(batch_size, locked_until)
+    })
+    .await?;
+    let count = jobs.len();
+    let results = futures_util::future::join_all(jobs.into_iter().map(|job| async move {
+        let delivery = send(client, &job, policy.timeout).await;
+        let retry_at = if delivery.succeeded {
+            None
+        } else {
+            let retry_at = policy.retry_at(delivery.attempt as u32);
+            if retry_at.is_none() {
+                log::warn!(
+                    "Webhook delivery gave up: webhook {}, delivery {}",
+                    job.webhook_id,
+                    job.delivery_id
+                );
+            }
+            retry_at
+        };
+        blocking(repository(), move |service| {
+            service.record(&job, &delivery, retry_at)
+        })
+        .await
+    }))
+    .await;
+    for result in results {
+        // 記録できなかった通知は取り出しの期限が切れた後に再び送る
+        if let Err(e) = result {
+            log::error!("Webhook delivery record failed: {:?}", e);
+        }
+    }
+    Ok(count)
+}
+
+/// 一定の間隔で、アウトボックスのイベントから通知を作り、送信の時刻になった通知を送り続ける
+pub async fn run(context: RequestContext, policy: RetryPolicy, interval: Duration) {
+    let client = reqwest::Client::new();
+    let repository = || context.webhook_repository();
+    loop {
+        let pokemon_repository = context.pokemon_repository();
+        let queued = blocking(repository(), move |service| {
+            service.handle(BATCH_SIZE, |message| {
+                Ok(change_of(&pokemon_repository, message))
+            })
+        })
+        .await
+        .unwrap_or_else(|e| {
+            log::error!("Webhook enqueue failed: {:?}", e);
+            0
+        });
+        let sent = dispatch(&repository, &client, &policy, BATCH_SIZE)
+            .await
+            .unwrap_or_else(|e| {
+                log::error!("Webhook dispatch failed: {:?}", e);
+                0
+            });
+        // 取り出した数が上限に達した場合は続きがありうるため、待たずに次を取り出す
+        if queued as i64 == BATCH_SIZE || sent as i64 == BATCH_SIZE {
+            continue;
+        }
+        actix_web::rt::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::webhook::webhook::{
+        NewWebhook, NewWebhookJob, Webhook, WebhookDelivery, WebhookEvent,
+    };
+    use actix_web::{post, web, App, HttpRequest, HttpResponse, HttpServer};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// 送信待ちの通知と、次に送信する時刻
+    type Queue = Arc<Mutex<Vec<(WebhookJob, DateTime<Utc>)>>>;
+
+    /// テストのためのモックリポジトリ。送信待ちの通知とその再送の時刻、送信の記録を共有する。
+    #[derive(Clone, Default)]
+    struct MockWebhookRepositoryImpl {
+        queue: Queue,
+        deliveries: Arc<Mutex<Vec<NewWebhookDelivery>>>,
+    }
+
+    impl MockWebhookRepositoryImpl {
+        fn new(job: WebhookJob) -> Self {
+            let repository = Self::default();
+            repository.queue.lock().unwrap().push((job, Utc::now()));
+            repository
+        }
+    }
+
+    /// モックリポジトリの振る舞い
+    impl WebhookRepository for MockWebhookRepositoryImpl {
+        fn find_by_id(&self, _id: i32) -> Result<Option<Webhook>> {
+            unimplemented!();
+        }
+
+        fn list(&self) -> Result<Vec<Webhook>> {
+            unimplemented!();
+        }
+
+        fn insert(&self, _webhook: &NewWebhook) -> Result<Webhook> {
+            unimplemented!();
+        }
+
+        fn delete(&self, _id: i32) -> Result<bool> {
+            unimplemented!();
+        }
+
+        fn insert_delivery(&self, delivery: &NewWebhookDelivery) -> Result<WebhookDelivery> {
+            self.deliveries.lock().unwrap().push(delivery.clone());
+            Ok(WebhookDelivery {
+                id: 1,
+                webhook_id: delivery.webhook_id,
+                delivery_id: delivery.delivery_id.clone(),
+                event: delivery.event,
+                attempt: delivery.attempt,
+                status_code: delivery.status_code,
+                error: delivery.error.clone(),
+                succeeded: delivery.succeeded,
+                attempted_at: Utc::now(),
+            })
+        }
+
+        fn list_deliveries(&self, _webhook_id: i32, _limit: i64) -> Result<Vec<WebhookDelivery>> {
+            unimplemented!();
+        }
+
+        fn enqueue(
+            &self,
+            _limit: i64,
+            _jobs: &dyn Fn(&OutboxMessage) -> Result<Vec<NewWebhookJob>>,
+        ) -> Result<usize> {
+            unimplemented!();
+        }
+
+        fn claim(&self, limit: i64, _locked_until: DateTime<Utc>) -> Result<Vec<WebhookJob>> {
+            let now = Utc::now();
+            Ok(self
+                .queue
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(_, next_attempt_at)| *next_attempt_at <= now)
+                .take(limit as usize)
+                .map(|(job, _)| job.clone())
+                .collect())
+        }
+
+        fn complete(&self, id: i64) -> Result<()> {
+            self.queue.lock().unwrap().retain(|(job, _)| job.id != id);
+            Ok(())
+        }
+
+        fn retry(&self, id: i64, next_attempt_at: DateTime<Utc>) -> Result<()> {
+            for (job, at) in self.queue.lock().unwrap().iter_mut() {
+                if job.id == id {
+                    job.attempt += 1;
+                    *at = next_attempt_at;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// 受信側の代わりに動かすサーバーの状態。最初の `failures` 回は 500 を返す。
+    struct Receiver {
+        failures: usize,
+        count: AtomicUsize,
+        received: Mutex<Vec<(String, String, String)>>,
+    }
+
+    #[post("/hook")]
+    async fn hook(receiver: web::Data<Receiver>, req: HttpRequest, body: String) -> HttpResponse {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+                .to_string()
+        };
+        receiver.received.lock().unwrap().push((
+            header("X-Pokedex-Timestamp"),
+            header("X-Pokedex-Signature"),
+            body,
+        ));
+        if receiver.count.fetch_add(1, Ordering::SeqCst) < receiver.failures {
+            HttpResponse::InternalServerError().finish()
+        } else {
+            HttpResponse::NoContent().finish()
+        }
+    }
+
+    /// 受信側のサーバーを起動し、通知先の URL を返す
+    fn start_receiver(receiver: web::Data<Receiver>) -> String {
+        let server = HttpServer::new(move || App::new().app_data(receiver.clone()).service(hook))
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+        let url = format!("http://{}/hook", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+        url
+    }
+
+    fn receiver(failures: usize) -> web::Data<Receiver> {
+        web::Data::new(Receiver {
+            failures,
+            count: AtomicUsize::new(0),
+            received: Mutex::new(vec![]),
+        })
+    }
+
+    fn job(url: String) -> WebhookJob {
+        WebhookJob {
+            id: 1,
+            webhook_id: 1,
+            url,
+            secret: "whsec_test".to_string(),
+            delivery_id: "d1".to_string(),
+            event: WebhookEvent::Deleted,
+            body: r#"{"number":1}"#.to_string(),
+            attempt: 0,
+        }
+    }
+
+    fn policy(max_attempts: u32, base_delay: Duration) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay,
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// 送信待ちの通知がなくなるまで、最大 `times` 回取り出して送る
+    async fn dispatch_all(
+        repository: &MockWebhookRepositoryImpl,
+        policy: &RetryPolicy,
+        times: usize,
+    ) {
+        let client = reqwest::Client::new();
+        for _ in 0..times {
+            if dispatch(&|| repository.clone(), &client, policy, 10)
+                .await
+                .unwrap()
+                == 0
+            {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn delay_exponential() {
+        let policy = policy(5, Duration::from_secs(1));
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(4), Duration::from_secs(8));
+        assert!(policy.retry_at(4).is_some());
+        assert!(policy.retry_at(5).is_none());
+    }
+
+    #[actix_web::test]
+    async fn dispatch_retries_until_success() {
+        let receiver = receiver(2);
+        let repository = MockWebhookRepositoryImpl::new(job(start_receiver(receiver.clone())));
+        dispatch_all(&repository, &policy(5, Duration::ZERO), 10).await;
+
+        assert!(repository.queue.lock().unwrap().is_empty());
+        assert_eq!(
+            repository
+                .deliveries
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|r| (r.attempt, r.status_code, r.succeeded))
+                .collect::<Vec<_>>(),
+            vec![
+                (1, Some(500), false),
+                (2, Some(500), false),
+                (3, Some(204), true)
+            ]
+        );
+        // 受信側は送信時刻と本文から署名を検証できる
+        for (timestamp, signature, body) in receiver.received.lock().unwrap().iter() {
+            let expected = sign("whsec_test", timestamp.parse().unwrap(), body);
+            assert_eq!(signature, &format!("sha256={}", expected));
+        }
+    }
+
+    #[actix_web::test]
+    async fn dispatch_gives_up() {
+        let receiver = receiver(usize::MAX);
+        let repository = MockWebhookRepositoryImpl::new(job(start_receiver(receiver.clone())));
+        dispatch_all(&repository, &policy(3, Duration::ZERO), 10).await;
+
+        assert!(repository.queue.lock().unwrap().is_empty());
+        assert_eq!(repository.deliveries.lock().unwrap().len(), 3);
+        assert_eq!(receiver.count.load(Ordering::SeqCst), 3);
+    }
+
+    #[actix_web::test]
+    async fn dispatch_waits_for_retry() {
+        let receiver = receiver(1);
+        let repository = MockWebhookRepositoryImpl::new(job(start_receiver(receiver.clone())));
+        dispatch_all(&repository, &policy(3, Duration::from_secs(60)), 10).await;
+
+        // 再送の時刻まではキューに残り、送信しない
+        let queue = repository.queue.lock().unwrap();
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue[0].0.attempt, 1);
+        assert!(queue[0].1 > Utc::now());
+        assert_eq!(receiver.count.load(Ordering::SeqCst), 1);
+    }
+}